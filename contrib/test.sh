@@ -14,6 +14,12 @@ then
     alias cargo="cargo +$TOOLCHAIN"
 fi
 
+# Pin `byteorder`, a dependency of `base64-compat`, for Rust 1.29
+if [ "$TRAVIS_RUST_VERSION" = "1.29.0" ]; then
+    cargo generate-lockfile --verbose
+    cargo update -p byteorder --precise "1.3.4" --verbose
+fi
+
 # Test without any features first
 cargo test --verbose
 
@@ -23,6 +29,32 @@ do
     cargo test --verbose --features="$feature"
 done
 
+# The base64 serde helpers need both features
+cargo test --verbose --features="use-serde base64"
+
+# Test without std if told to
+if [ "$DO_NO_STD" = true ]
+then
+    cargo test --verbose --no-default-features --features="no-std"
+    cargo test --verbose --no-default-features --features="no-std use-serde"
+fi
+
+# Test the async codec if told to
+if [ "$DO_ASYNC" = true ]
+then
+    cargo test --verbose --features="async"
+fi
+
+# Test the derive macros if told to
+if [ "$DO_DERIVE" = true ]
+then
+    cargo test --verbose --features="derive"
+    (
+        cd derive
+        cargo test --verbose
+    )
+fi
+
 # Fuzz if told to
 if [ "$DO_FUZZ" = true ]
 then
@@ -0,0 +1,434 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Consensus Encoding Derive Macros
+//!
+//! This crate provides `#[derive(Encodable, Decodable)]` for the consensus
+//! encoding traits of the `bitcoin` crate. It is usually used through the
+//! `derive` feature of `bitcoin`, which re-exports the macros next to the
+//! traits in `bitcoin::consensus`.
+//!
+//! The fields of a struct are encoded one after the other, in the order of
+//! their declaration, which is what the `impl_consensus_encoding!` macro of
+//! the `bitcoin` crate does.
+//!
+//! An enum is encoded as the discriminant of the variant followed by the
+//! fields of the variant. The discriminant of every variant must be given
+//! explicitly, either with `Variant = 1` for enums without fields or with a
+//! `#[consensus(discriminant = 1)]` attribute. It is encoded as a `u8`,
+//! unless the enum has a `#[consensus(discriminant = "u32")]` attribute
+//! naming another integer type. Discriminants which do not fit in that type
+//! or which are used by several variants are compile errors. Decoding an
+//! unknown discriminant fails with `encode::Error::ParseFailed`.
+//!
+//! ```
+//! #[macro_use] extern crate bitcoin_consensus_derive;
+//! extern crate bitcoin;
+//!
+//! use bitcoin::consensus::{Encodable, Decodable};
+//!
+//! #[derive(Encodable, Decodable)]
+//! struct Commitment {
+//!     txid: bitcoin::Txid,
+//!     data: Vec<u8>,
+//! }
+//!
+//! #[derive(Encodable, Decodable)]
+//! #[consensus(discriminant = "u16")]
+//! enum Message {
+//!     #[consensus(discriminant = 1)]
+//!     Commit(Commitment),
+//!     #[consensus(discriminant = 2)]
+//!     Reveal { secret: [u8; 32] },
+//! }
+//! ```
+//!
+//! ```compile_fail
+//! # #[macro_use] extern crate bitcoin_consensus_derive;
+//! # extern crate bitcoin;
+//! #[derive(Encodable, Decodable)]
+//! enum TooLarge {
+//!     A = 1,
+//!     B = 256, // does not fit in a `u8`
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! ```compile_fail
+//! # #[macro_use] extern crate bitcoin_consensus_derive;
+//! # extern crate bitcoin;
+//! #[derive(Encodable, Decodable)]
+//! enum Duplicate {
+//!     #[consensus(discriminant = 1)]
+//!     A(u8),
+//!     #[consensus(discriminant = 1)]
+//!     B(u16),
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! ```compile_fail
+//! # #[macro_use] extern crate bitcoin_consensus_derive;
+//! # extern crate bitcoin;
+//! const ONE: u8 = 1;
+//!
+//! #[derive(Encodable, Decodable)]
+//! #[repr(u8)]
+//! enum DuplicateConstant {
+//!     A = ONE,
+//!     #[consensus(discriminant = 1)]
+//!     B(u16),
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! The generated code refers to the `bitcoin` crate by its name, so it must
+//! be a dependency of the crate using the macros.
+//!
+
+#![crate_name = "bitcoin_consensus_derive"]
+
+// Coding conventions
+#![forbid(unsafe_code)]
+#![deny(non_upper_case_globals)]
+#![deny(non_camel_case_types)]
+#![deny(non_snake_case)]
+#![deny(unused_mut)]
+#![deny(dead_code)]
+#![deny(unused_imports)]
+#![deny(missing_docs)]
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use] extern crate quote;
+#[macro_use] extern crate syn;
+
+use proc_macro2::{Span, TokenStream};
+use syn::spanned::Spanned;
+use syn::{Attribute, Data, DeriveInput, Error, Expr, Fields, Generics, Ident, Lit, Member, Meta, NestedMeta, Type};
+
+/// Derives `bitcoin::consensus::Encodable`
+#[proc_macro_derive(Encodable, attributes(consensus))]
+pub fn derive_encodable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    encodable(&input).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+/// Derives `bitcoin::consensus::Decodable`
+#[proc_macro_derive(Decodable, attributes(consensus))]
+pub fn derive_decodable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    decodable(&input).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+/// A variant of an enum, or the fields of a struct
+struct Variant<'a> {
+    /// The path of the variant, the name of the struct for a struct
+    path: TokenStream,
+    /// The discriminant of the variant, `None` for a struct
+    discriminant: Option<Expr>,
+    /// The fields of the variant
+    members: Vec<Member>,
+    fields: &'a Fields,
+}
+
+/// The variants of the derived type and the type of its discriminant
+fn variants<'a>(input: &'a DeriveInput) -> Result<(Vec<Variant<'a>>, Option<Type>), Error> {
+    let name = &input.ident;
+    match input.data {
+        Data::Struct(ref data) => {
+            if let Some(attr) = discriminant_attr(&input.attrs)? {
+                return Err(Error::new(attr.span(), "structs have no discriminant"));
+            }
+            let variant = Variant {
+                path: quote!(#name),
+                discriminant: None,
+                members: members(&data.fields),
+                fields: &data.fields,
+            };
+            Ok((vec![variant], None))
+        }
+        Data::Enum(ref data) => {
+            let ty: Type = match discriminant_attr(&input.attrs)? {
+                Some(Lit::Str(ref ty)) => ty.parse()?,
+                Some(lit) => return Err(Error::new(lit.span(), "expected the discriminant type as a string")),
+                None => parse_quote!(u8),
+            };
+            let mut variants = Vec::with_capacity(data.variants.len());
+            let mut values = Vec::with_capacity(data.variants.len());
+            for variant in &data.variants {
+                let discriminant = match (discriminant_attr(&variant.attrs)?, &variant.discriminant) {
+                    (Some(Lit::Int(ref int)), &None) => Expr::Lit(syn::ExprLit { attrs: vec![], lit: Lit::Int(int.clone()) }),
+                    (Some(lit), &None) => return Err(Error::new(lit.span(), "expected an integer discriminant")),
+                    (None, &Some((_, ref expr))) => expr.clone(),
+                    (Some(lit), &Some(_)) => return Err(Error::new(lit.span(), "discriminant given twice")),
+                    (None, &None) => return Err(Error::new(
+                        variant.span(),
+                        "the discriminant of the variant must be given with #[consensus(discriminant = ...)]",
+                    )),
+                };
+                if let Expr::Lit(syn::ExprLit { lit: Lit::Int(ref int), .. }) = discriminant {
+                    let value = int.base10_parse::<u128>()?;
+                    if max_discriminant(&ty).map_or(false, |max| value > max) {
+                        return Err(Error::new(int.span(), format!("discriminant does not fit in {}", quote!(#ty))));
+                    }
+                    if let Some(&(_, ref other)) = values.iter().find(|&&(v, _)| v == value) {
+                        return Err(Error::new(int.span(), format!("discriminant already used by variant {}", other)));
+                    }
+                    values.push((value, &variant.ident));
+                }
+                let ident = &variant.ident;
+                variants.push(Variant {
+                    path: quote!(#name::#ident),
+                    discriminant: Some(discriminant),
+                    members: members(&variant.fields),
+                    fields: &variant.fields,
+                });
+            }
+            Ok((variants, Some(ty)))
+        }
+        Data::Union(ref data) => Err(Error::new(data.union_token.span(), "unions cannot be consensus encoded")),
+    }
+}
+
+/// Returns the value of the `#[consensus(discriminant = ...)]` attribute, if any.
+fn discriminant_attr(attrs: &[Attribute]) -> Result<Option<Lit>, Error> {
+    let mut ret = None;
+    for attr in attrs {
+        if !attr.path.is_ident("consensus") {
+            continue;
+        }
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new(meta.span(), "expected #[consensus(discriminant = ...)]")),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("discriminant") => {
+                    if ret.is_some() {
+                        return Err(Error::new(nv.span(), "discriminant given twice"));
+                    }
+                    ret = Some(nv.lit.clone());
+                }
+                nested => return Err(Error::new(nested.span(), "unknown consensus attribute")),
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// The largest value of `ty` if it is a primitive integer type with the same
+/// size on all platforms.
+fn max_discriminant(ty: &Type) -> Option<u128> {
+    let ident = match *ty {
+        Type::Path(ref path) if path.qself.is_none() => path.path.get_ident()?.to_string(),
+        _ => return None,
+    };
+    Some(match ident.as_str() {
+        "u8" => u8::max_value() as u128,
+        "u16" => u16::max_value() as u128,
+        "u32" => u32::max_value() as u128,
+        "u64" => u64::max_value() as u128,
+        "u128" => u128::max_value(),
+        "i8" => i8::max_value() as u128,
+        "i16" => i16::max_value() as u128,
+        "i32" => i32::max_value() as u128,
+        "i64" => i64::max_value() as u128,
+        "i128" => i128::max_value() as u128,
+        _ => return None,
+    })
+}
+
+/// Constant assertions that the discriminants which could not be checked by
+/// the macro fit in the discriminant type and are all different
+fn discriminant_checks(variants: &[Variant], ty: &Option<Type>) -> TokenStream {
+    let is_checked = |discriminant: &Expr| match *discriminant {
+        Expr::Lit(syn::ExprLit { lit: Lit::Int(_), .. }) => ty.as_ref().and_then(max_discriminant).is_some(),
+        _ => false,
+    };
+    let discriminants: Vec<&Expr> = variants.iter().filter_map(|variant| variant.discriminant.as_ref()).collect();
+    let mut checks = vec![];
+    for (i, discriminant) in discriminants.iter().enumerate() {
+        if !is_checked(discriminant) {
+            // Fails to compile with an overflow if the value is truncated
+            checks.push(quote_spanned! {discriminant.span()=>
+                let _: [(); 0] = [(); 0 - !((#discriminant) as #ty as i128 == (#discriminant) as i128) as usize];
+            });
+        }
+        for other in &discriminants[..i] {
+            if is_checked(discriminant) && is_checked(other) {
+                continue;
+            }
+            // Fails to compile with an overflow if both values are equal
+            checks.push(quote_spanned! {discriminant.span()=>
+                let _: [(); 0] = [(); 0 - ((#discriminant) as #ty == (#other) as #ty) as usize];
+            });
+        }
+    }
+    quote! {
+        const _: () = {
+            #(#checks)*
+        };
+    }
+}
+
+fn members(fields: &Fields) -> Vec<Member> {
+    fields.iter().enumerate().map(|(i, field)| match field.ident {
+        Some(ref ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(i.into()),
+    }).collect()
+}
+
+/// Adds `bound` to the type parameters of `generics`
+fn bound_generics(generics: &Generics, bound: TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+/// The match arms adding `call` of the discriminant and of each field to `len`
+fn add_len_arms<F>(variants: &[Variant], ty: &Option<Type>, call: F) -> Vec<TokenStream>
+    where F: Fn(TokenStream) -> TokenStream
+{
+    variants.iter().map(|variant| {
+        let path = &variant.path;
+        let members = &variant.members;
+        let bindings: Vec<Ident> = (0..members.len())
+            .map(|i| Ident::new(&format!("__field{}", i), Span::call_site()))
+            .collect();
+        let discriminant = variant.discriminant.as_ref().map(|discriminant| {
+            let call = call(quote!(&(#discriminant as #ty)));
+            quote!(len += #call;)
+        });
+        let calls = bindings.iter().map(|binding| call(quote!(#binding)));
+        quote! {
+            #path { #(#members: ref #bindings),* } => {
+                #discriminant
+                #(len += #calls;)*
+            }
+        }
+    }).collect()
+}
+
+fn encodable(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let (variants, ty) = variants(input)?;
+    let encode_arms = add_len_arms(&variants, &ty, |value| {
+        quote!(::bitcoin::consensus::Encodable::consensus_encode(#value, &mut s)?)
+    });
+    let len_arms = add_len_arms(&variants, &ty, |value| {
+        quote!(::bitcoin::consensus::Encodable::encoded_len(#value))
+    });
+
+    let checks = discriminant_checks(&variants, &ty);
+
+    let name = &input.ident;
+    let generics = bound_generics(&input.generics, quote!(::bitcoin::consensus::Encodable));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        #checks
+
+        impl #impl_generics ::bitcoin::consensus::Encodable for #name #ty_generics #where_clause {
+            #[inline]
+            #[allow(unused_mut)]
+            fn consensus_encode<__W: ::bitcoin::io::Write>(
+                &self,
+                mut s: __W,
+            ) -> Result<usize, ::bitcoin::consensus::encode::Error> {
+                let mut len = 0;
+                match *self {
+                    #(#encode_arms)*
+                }
+                Ok(len)
+            }
+
+            #[inline]
+            #[allow(unused_mut)]
+            fn encoded_len(&self) -> usize {
+                let mut len = 0;
+                match *self {
+                    #(#len_arms)*
+                }
+                len
+            }
+        }
+    })
+}
+
+fn decodable(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let (variants, ty) = variants(input)?;
+    let decode = |limited: bool| {
+        let field = if limited {
+            quote!(::bitcoin::consensus::Decodable::consensus_decode_limited(&mut d, ctx)?)
+        } else {
+            quote!(::bitcoin::consensus::Decodable::consensus_decode(&mut d)?)
+        };
+        let construct = |variant: &Variant| {
+            let path = &variant.path;
+            let members = &variant.members;
+            let fields = variant.fields.iter().map(|_| &field);
+            if limited {
+                quote!(ctx.nested(|ctx| Ok(#path { #(#members: #fields),* })))
+            } else {
+                quote!(Ok(#path { #(#members: #fields),* }))
+            }
+        };
+        match ty {
+            None => construct(&variants[0]),
+            Some(ref ty) => {
+                let arms = variants.iter().map(|variant| {
+                    let discriminant = variant.discriminant.as_ref().expect("enum variant");
+                    let construct = construct(variant);
+                    quote!(if discriminant == (#discriminant as #ty) { #construct } else)
+                });
+                quote! {
+                    let discriminant: #ty = ::bitcoin::consensus::Decodable::consensus_decode(&mut d)?;
+                    #(#arms)* {
+                        Err(::bitcoin::consensus::encode::Error::ParseFailed("unknown enum discriminant"))
+                    }
+                }
+            }
+        }
+    };
+    let decode_plain = decode(false);
+    let decode_limited = decode(true);
+    let checks = discriminant_checks(&variants, &ty);
+
+    let name = &input.ident;
+    let generics = bound_generics(&input.generics, quote!(::bitcoin::consensus::Decodable));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        #checks
+
+        impl #impl_generics ::bitcoin::consensus::Decodable for #name #ty_generics #where_clause {
+            #[inline]
+            #[allow(unused_mut, unused_variables)]
+            fn consensus_decode<__R: ::bitcoin::io::Read>(
+                mut d: __R,
+            ) -> Result<Self, ::bitcoin::consensus::encode::Error> {
+                #decode_plain
+            }
+
+            #[inline]
+            #[allow(unused_mut, unused_variables)]
+            fn consensus_decode_limited<__R: ::bitcoin::io::Read>(
+                mut d: __R,
+                ctx: &mut ::bitcoin::consensus::DecodeContext,
+            ) -> Result<Self, ::bitcoin::consensus::encode::Error> {
+                #decode_limited
+            }
+        }
+    })
+}
@@ -0,0 +1,148 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+#[macro_use] extern crate bitcoin_consensus_derive;
+extern crate bitcoin;
+
+use bitcoin::consensus::encode::{self, deserialize, serialize};
+use bitcoin::consensus::{deserialize_limited, DecodeLimits, Encodable, Limit};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::{Script, Transaction, TxOut, Txid};
+
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+struct OutPoint {
+    txid: Txid,
+    vout: u32,
+}
+
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+struct Output(u64, Script);
+
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+struct Unit;
+
+// Named like the type parameters of the trait methods
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+struct S(u8);
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+struct D(S);
+
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+struct Pair<A, B> {
+    first: A,
+    second: B,
+}
+
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+enum Fieldless {
+    A = 1,
+    B = 0xfe,
+}
+
+#[derive(Encodable, Decodable, PartialEq, Debug)]
+#[consensus(discriminant = "u16")]
+enum Message {
+    #[consensus(discriminant = 1)]
+    Ping,
+    #[consensus(discriminant = 0x0102)]
+    Output(Output),
+    #[consensus(discriminant = 3)]
+    Named { outpoint: OutPoint, data: Vec<u8> },
+}
+
+fn transaction() -> Transaction {
+    deserialize(&Vec::<u8>::from_hex("0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000").unwrap()).unwrap()
+}
+
+#[test]
+fn struct_matches_existing_encoding() {
+    let tx = transaction();
+
+    let prevout = tx.input[0].previous_output;
+    let outpoint = OutPoint { txid: prevout.txid, vout: prevout.vout };
+    assert_eq!(serialize(&outpoint), serialize(&prevout));
+    assert_eq!(deserialize::<OutPoint>(&serialize(&prevout)).unwrap(), outpoint);
+
+    let txout: &TxOut = &tx.output[0];
+    let output = Output(txout.value, txout.script_pubkey.clone());
+    assert_eq!(serialize(&output), serialize(txout));
+    assert_eq!(deserialize::<Output>(&serialize(txout)).unwrap(), output);
+
+    assert!(serialize(&Unit).is_empty());
+    assert_eq!(deserialize::<Unit>(&[]).unwrap(), Unit);
+    assert_eq!(deserialize::<D>(&serialize(&D(S(3)))).unwrap(), D(S(3)));
+
+    let pair = Pair { first: 7u8, second: output };
+    assert_eq!(serialize(&pair), [&[7u8][..], &serialize(txout)[..]].concat());
+    assert_eq!(pair.encoded_len(), serialize(&pair).len());
+    assert_eq!(deserialize::<Pair<u8, Output>>(&serialize(&pair)).unwrap(), pair);
+}
+
+#[test]
+fn enum_discriminants() {
+    assert_eq!(serialize(&Fieldless::A), vec![1]);
+    assert_eq!(serialize(&Fieldless::B), vec![0xfe]);
+    assert_eq!(deserialize::<Fieldless>(&[0xfe]).unwrap(), Fieldless::B);
+
+    assert_eq!(serialize(&Message::Ping), vec![1, 0]);
+    let output = Output(5, Script::new());
+    let msg = Message::Output(output);
+    assert_eq!(serialize(&msg), vec![2, 1, 5, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(deserialize::<Message>(&serialize(&msg)).unwrap(), msg);
+
+    let msg = Message::Named {
+        outpoint: OutPoint { txid: Default::default(), vout: 1 },
+        data: vec![0xab; 3],
+    };
+    let mut expected = vec![3, 0];
+    expected.extend(vec![0; 32]);
+    expected.extend(vec![1, 0, 0, 0, 3, 0xab, 0xab, 0xab]);
+    assert_eq!(serialize(&msg), expected);
+    assert_eq!(msg.encoded_len(), expected.len());
+    assert_eq!(deserialize::<Message>(&expected).unwrap(), msg);
+
+    match deserialize::<Fieldless>(&[2]) {
+        Err(encode::Error::ParseFailed("unknown enum discriminant")) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    match deserialize::<Message>(&[1, 1]) {
+        Err(encode::Error::ParseFailed("unknown enum discriminant")) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn limited_decoding() {
+    let msg = Message::Named {
+        outpoint: OutPoint { txid: Default::default(), vout: 1 },
+        data: vec![0xab; 3],
+    };
+    let bytes = serialize(&msg);
+    assert_eq!(deserialize_limited::<Message>(&bytes, DecodeLimits::default()).unwrap(), msg);
+
+    let limits = DecodeLimits { max_vec_len: 2, ..Default::default() };
+    match deserialize_limited::<Message>(&bytes, limits) {
+        Err(encode::Error::LimitExceeded { limit: Limit::VecLength, requested: 3, max: 2 }) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+
+    // The variant and the outpoint are each one level deeper
+    let limits = DecodeLimits { max_depth: 2, ..Default::default() };
+    assert_eq!(deserialize_limited::<Message>(&bytes, limits).unwrap(), msg);
+    let limits = DecodeLimits { max_depth: 1, ..Default::default() };
+    match deserialize_limited::<Message>(&bytes, limits) {
+        Err(encode::Error::LimitExceeded { limit: Limit::Depth, requested: 2, max: 1 }) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
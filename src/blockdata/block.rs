@@ -20,16 +20,22 @@
 //! these blocks and the blockchain.
 //!
 
+use core::fmt;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
+
 use util;
 use util::Error::{BlockBadTarget, BlockBadProofOfWork};
 use util::hash::bitcoin_merkle_root;
-use hashes::{Hash, HashEngine};
-use hash_types::{Wtxid, BlockHash, TxMerkleNode, WitnessMerkleNode, WitnessCommitment};
+use hashes::{Hash, HashEngine, sha256d};
+use hash_types::{Txid, Wtxid, BlockHash, TxMerkleNode, WitnessMerkleNode, WitnessCommitment};
 use util::uint::Uint256;
 use consensus::encode::Encodable;
+use consensus::Params;
 use network::constants::Network;
-use blockdata::transaction::Transaction;
-use blockdata::constants::{max_target, WITNESS_SCALE_FACTOR};
+use blockdata::script;
+use blockdata::transaction::{OutPoint, Transaction, TxCheckError, TxOut};
+use blockdata::constants::{max_money, max_target, MAX_BLOCK_SIGOPS_COST, MAX_BLOCK_WEIGHT, WITNESS_SCALE_FACTOR};
 use VarInt;
 
 /// A block header, which contains all the block's information except
@@ -51,6 +57,118 @@ pub struct BlockHeader {
     pub nonce: u32,
 }
 
+/// An error returned by the block validity checks of [`Block::check`] and
+/// [`Block::check_contextual`], naming the consensus rule which is violated.
+/// Display strings start with the reject reason used by Bitcoin Core.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BlockCheckError {
+    /// The block hash is above the target, or the target is invalid or above
+    /// the proof of work limit
+    BadProofOfWork,
+    /// The merkle root in the header does not match the transactions
+    BadMerkleRoot,
+    /// The transaction list contains duplicated subtrees which leave the
+    /// merkle root unchanged (CVE-2012-2459)
+    DuplicateTransactions,
+    /// The block has no transactions, or too many of them, or its size
+    /// without witness data exceeds the block weight limit
+    BadLength,
+    /// The first transaction is not a coinbase
+    NoCoinbase,
+    /// A transaction other than the first one is a coinbase
+    MultipleCoinbase(usize),
+    /// A transaction fails the context-free transaction checks
+    BadTransaction {
+        /// Index of the offending transaction in the block
+        index: usize,
+        /// The failed check
+        error: TxCheckError,
+    },
+    /// The signature operations cost exceeds the block limit
+    TooManySigops(usize),
+    /// The block timestamp is not above the median time of the previous blocks
+    TimeTooOld {
+        /// The block timestamp
+        time: u32,
+        /// The median time past of the previous blocks
+        median_time_past: u32,
+    },
+    /// The block version was made obsolete by BIP34, BIP66 or BIP65
+    ObsoleteVersion(i32),
+    /// The coinbase scriptSig does not start with the block height (BIP34)
+    BadCoinbaseHeight(u32),
+    /// The block contains witness data before segwit activation
+    UnexpectedWitness,
+    /// The witness commitment in the coinbase is missing or invalid
+    BadWitnessCommitment,
+    /// The block weight exceeds the limit
+    BadWeight(usize),
+    /// A spent output does not exist or was already spent
+    MissingInput(OutPoint),
+    /// The sum of input values of a transaction is out of range
+    InputValuesOutOfRange(Txid),
+    /// A transaction spends less than it creates
+    InputsBelowOutputs(Txid),
+    /// The coinbase claims more than the block subsidy plus fees
+    BadCoinbaseValue {
+        /// The maximum allowed value
+        max: u64,
+        /// The value claimed by the coinbase
+        actual: u64,
+    },
+}
+
+impl fmt::Display for BlockCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlockCheckError::BadProofOfWork => f.write_str("high-hash: proof of work failed"),
+            BlockCheckError::BadMerkleRoot => f.write_str("bad-txnmrklroot: merkle root mismatch"),
+            BlockCheckError::DuplicateTransactions => f.write_str("bad-txns-duplicate: duplicate transaction"),
+            BlockCheckError::BadLength => f.write_str("bad-blk-length: size limits failed"),
+            BlockCheckError::NoCoinbase => f.write_str("bad-cb-missing: first tx is not coinbase"),
+            BlockCheckError::MultipleCoinbase(index) => write!(f,
+                "bad-cb-multiple: transaction {} is a coinbase", index),
+            BlockCheckError::BadTransaction { index, ref error } => write!(f,
+                "bad transaction {}: {}", index, error),
+            BlockCheckError::TooManySigops(cost) => write!(f,
+                "bad-blk-sigops: sigops cost {} exceeds {}", cost, MAX_BLOCK_SIGOPS_COST),
+            BlockCheckError::TimeTooOld { time, median_time_past } => write!(f,
+                "time-too-old: block time {} is not above median time past {}", time, median_time_past),
+            BlockCheckError::ObsoleteVersion(version) => write!(f,
+                "bad-version: rejected nVersion={:#x} block", version),
+            BlockCheckError::BadCoinbaseHeight(height) => write!(f,
+                "bad-cb-height: block height {} mismatch in coinbase", height),
+            BlockCheckError::UnexpectedWitness => f.write_str("unexpected-witness: witness data before segwit"),
+            BlockCheckError::BadWitnessCommitment => f.write_str("bad-witness-merkle-match: witness commitment mismatch"),
+            BlockCheckError::BadWeight(weight) => write!(f,
+                "bad-blk-weight: weight {} exceeds {}", weight, MAX_BLOCK_WEIGHT),
+            BlockCheckError::MissingInput(ref outpoint) => write!(f,
+                "bad-txns-inputs-missingorspent: {}", outpoint),
+            BlockCheckError::InputValuesOutOfRange(ref txid) => write!(f,
+                "bad-txns-inputvalues-outofrange: {}", txid),
+            BlockCheckError::InputsBelowOutputs(ref txid) => write!(f,
+                "bad-txns-in-belowout: {}", txid),
+            BlockCheckError::BadCoinbaseValue { max, actual } => write!(f,
+                "bad-cb-amount: coinbase pays {}, limit {}", actual, max),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for BlockCheckError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            BlockCheckError::BadTransaction { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
 /// A Bitcoin block, which is a collection of transactions with an attached
 /// proof of work.
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -115,15 +233,7 @@ impl Block {
 
     /// Merkle root of transactions hashed for witness
     pub fn witness_root(&self) -> WitnessMerkleNode {
-        let hashes = self.txdata.iter().enumerate().map(|(i, t)|
-            if i == 0 {
-                // Replace the first hash with zeroes.
-                Wtxid::default().as_hash()
-            } else {
-                t.wtxid().as_hash()
-            }
-        );
-        bitcoin_merkle_root(hashes).into()
+        witness_root(self.txdata.iter())
     }
 
     /// Get the size of the block
@@ -140,6 +250,196 @@ impl Block {
         let txs_weight: usize = self.txdata.iter().map(Transaction::get_weight).sum();
         base_weight + txs_weight
     }
+
+    /// Get the size of the block without witness data
+    pub fn get_stripped_size(&self) -> usize {
+        let base_size = 80 + VarInt(self.txdata.len() as u64).len();
+        let txs_size: usize = self.txdata.iter().map(Transaction::get_stripped_size).sum();
+        base_size + txs_size
+    }
+
+    /// Checks whether the transaction list contains duplicated subtrees which
+    /// do not change the merkle root (CVE-2012-2459). Such a block is invalid,
+    /// while a block with the same header and without duplicates might not be.
+    pub fn is_merkle_tree_mutated(&self) -> bool {
+        let mut hashes: Vec<sha256d::Hash> = self.txdata.iter().map(|tx| tx.txid().as_hash()).collect();
+        while hashes.len() > 1 {
+            if hashes.chunks(2).any(|pair| pair.len() == 2 && pair[0] == pair[1]) {
+                return true;
+            }
+            hashes = hashes.chunks(2).map(|pair| {
+                let mut engine = sha256d::Hash::engine();
+                pair[0].consensus_encode(&mut engine).unwrap();
+                pair[pair.len() - 1].consensus_encode(&mut engine).unwrap();
+                sha256d::Hash::from_engine(engine)
+            }).collect();
+        }
+        false
+    }
+
+    /// Performs the context-free block checks done by Bitcoin Core's
+    /// `CheckBlock`: proof of work against the target in the header, merkle
+    /// root and its malleation, size limits, a single coinbase in the first
+    /// position, context-free transaction checks and the legacy signature
+    /// operations limit.
+    pub fn check(&self, params: &Params) -> Result<(), BlockCheckError> {
+        let target = self.header.target();
+        if target == Uint256::from_u64(0).unwrap() || target > params.pow_limit ||
+            self.header.validate_pow(&target).is_err() {
+            return Err(BlockCheckError::BadProofOfWork);
+        }
+
+        if !self.check_merkle_root() {
+            return Err(BlockCheckError::BadMerkleRoot);
+        }
+        if self.is_merkle_tree_mutated() {
+            return Err(BlockCheckError::DuplicateTransactions);
+        }
+
+        let max_weight = MAX_BLOCK_WEIGHT as usize;
+        if self.txdata.is_empty() || self.txdata.len() * WITNESS_SCALE_FACTOR > max_weight ||
+            self.get_stripped_size() * WITNESS_SCALE_FACTOR > max_weight {
+            return Err(BlockCheckError::BadLength);
+        }
+
+        if !self.txdata[0].is_coin_base() {
+            return Err(BlockCheckError::NoCoinbase);
+        }
+        if let Some(index) = self.txdata.iter().skip(1).position(Transaction::is_coin_base) {
+            return Err(BlockCheckError::MultipleCoinbase(index + 1));
+        }
+
+        let mut sigops = 0;
+        for (index, tx) in self.txdata.iter().enumerate() {
+            tx.check(params.network).map_err(|error| BlockCheckError::BadTransaction {
+                index: index,
+                error: error,
+            })?;
+            sigops += tx.get_legacy_sigop_count();
+        }
+        if sigops * WITNESS_SCALE_FACTOR > MAX_BLOCK_SIGOPS_COST as usize {
+            return Err(BlockCheckError::TooManySigops(sigops * WITNESS_SCALE_FACTOR));
+        }
+
+        Ok(())
+    }
+
+    /// Performs the block checks which depend on the position of the block in
+    /// the chain, given its height and the median time past of the previous
+    /// blocks: timestamp, obsolete versions, BIP34 height in coinbase, segwit
+    /// witness commitment and weight limit. It then connects the transactions,
+    /// checking that every spent output exists and is spent once, that no
+    /// transaction creates money, that the coinbase does not claim more than
+    /// the block subsidy plus fees and that the signature operations cost is
+    /// within the limit.
+    ///
+    /// Outputs created earlier in the block are resolved internally, all others
+    /// are looked up with `spent`, which must return `None` for spent or
+    /// unknown outputs. This does not include script validation, coinbase
+    /// maturity, transaction finality or the difficulty target checks.
+    pub fn check_contextual<S>(
+        &self,
+        params: &Params,
+        height: u32,
+        median_time_past: u32,
+        mut spent: S,
+    ) -> Result<(), BlockCheckError>
+        where S: FnMut(&OutPoint) -> Option<TxOut>
+    {
+        if self.header.time <= median_time_past {
+            return Err(BlockCheckError::TimeTooOld { time: self.header.time, median_time_past: median_time_past });
+        }
+
+        let version = self.header.version;
+        if (version < 2 && height >= params.bip34_height) ||
+            (version < 3 && height >= params.bip66_height) ||
+            (version < 4 && height >= params.bip65_height) {
+            return Err(BlockCheckError::ObsoleteVersion(version));
+        }
+
+        let coinbase = match self.txdata.first() {
+            Some(tx) if tx.is_coin_base() => tx,
+            _ => return Err(BlockCheckError::NoCoinbase),
+        };
+        if height >= params.bip34_height {
+            let expected = script::Builder::new().push_int(height as i64).into_script();
+            if !coinbase.input[0].script_sig[..].starts_with(&expected[..]) {
+                return Err(BlockCheckError::BadCoinbaseHeight(height));
+            }
+        }
+
+        let segwit = height >= params.segwit_height;
+        if segwit {
+            if !self.check_witness_commitment() {
+                return Err(BlockCheckError::BadWitnessCommitment);
+            }
+        } else if self.txdata.iter().any(|tx| tx.input.iter().any(|i| !i.witness.is_empty())) {
+            return Err(BlockCheckError::UnexpectedWitness);
+        }
+
+        let weight = self.get_weight();
+        if weight > MAX_BLOCK_WEIGHT as usize {
+            return Err(BlockCheckError::BadWeight(weight));
+        }
+
+        let p2sh = self.header.time >= params.bip16_time;
+        let max_money = max_money(params.network);
+        let mut created: HashMap<OutPoint, TxOut> = HashMap::new();
+        let mut spent_outside: HashSet<OutPoint> = HashSet::new();
+        let mut fees = 0u64;
+        let mut sigops = coinbase.get_legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        for (index, tx) in self.txdata.iter().enumerate().skip(1) {
+            let txid = tx.txid();
+            let mut prevouts = HashMap::with_capacity(tx.input.len());
+            let mut input_value = 0u64;
+            for input in &tx.input {
+                let outpoint = input.previous_output;
+                let prevout = match created.remove(&outpoint) {
+                    Some(prevout) => prevout,
+                    None if spent_outside.insert(outpoint) => match spent(&outpoint) {
+                        Some(prevout) => prevout,
+                        None => return Err(BlockCheckError::MissingInput(outpoint)),
+                    },
+                    None => return Err(BlockCheckError::MissingInput(outpoint)),
+                };
+                input_value = match input_value.checked_add(prevout.value) {
+                    Some(value) if prevout.value <= max_money && value <= max_money => value,
+                    _ => return Err(BlockCheckError::InputValuesOutOfRange(txid)),
+                };
+                prevouts.insert(outpoint, prevout);
+            }
+            let output_value = match tx.output.iter().try_fold(0u64, |sum, o| sum.checked_add(o.value)) {
+                Some(value) => value,
+                None => return Err(BlockCheckError::BadTransaction {
+                    index: index,
+                    error: TxCheckError::OutputTotalTooLarge,
+                }),
+            };
+            if input_value < output_value {
+                return Err(BlockCheckError::InputsBelowOutputs(txid));
+            }
+            fees = fees.saturating_add(input_value - output_value);
+            sigops += tx.get_sigop_cost(|outpoint| prevouts.get(outpoint).cloned(), p2sh, segwit)
+                .expect("all spent outputs are resolved");
+            for (vout, output) in tx.output.iter().enumerate() {
+                created.insert(OutPoint::new(txid, vout as u32), output.clone());
+            }
+        }
+
+        if sigops > MAX_BLOCK_SIGOPS_COST as usize {
+            return Err(BlockCheckError::TooManySigops(sigops));
+        }
+
+        let max_value = params.block_subsidy(height).saturating_add(fees);
+        let coinbase_value = coinbase.output.iter()
+            .try_fold(0u64, |sum, o| sum.checked_add(o.value))
+            .unwrap_or(u64::max_value());
+        if coinbase_value > max_value {
+            return Err(BlockCheckError::BadCoinbaseValue { max: max_value, actual: coinbase_value });
+        }
+
+        Ok(())
+    }
 }
 
 impl BlockHeader {
@@ -243,12 +543,33 @@ impl_consensus_encoding!(Block, header, txdata);
 serde_struct_impl!(BlockHeader, version, prev_blockhash, merkle_root, time, bits, nonce);
 serde_struct_impl!(Block, header, txdata);
 
+/// Merkle root of the wtxids of a block's transactions, the first of which is
+/// the coinbase
+pub(crate) fn witness_root<'a, I: ExactSizeIterator<Item = &'a Transaction>>(txdata: I) -> WitnessMerkleNode {
+    let hashes = txdata.enumerate().map(|(i, t)|
+        if i == 0 {
+            // Replace the first hash with zeroes.
+            Wtxid::default().as_hash()
+        } else {
+            t.wtxid().as_hash()
+        }
+    );
+    bitcoin_merkle_root(hashes).into()
+}
+
 #[cfg(test)]
 mod tests {
     use hashes::hex::FromHex;
 
-    use blockdata::block::{Block, BlockHeader};
-    use consensus::encode::{deserialize, serialize};
+    use blockdata::block::{Block, BlockCheckError, BlockHeader};
+    use blockdata::coinbase::CoinbaseBuilder;
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, Transaction, TxCheckError, TxIn, TxOut};
+    use consensus::encode::{deserialize, serialize, Encodable};
+    use consensus::Params;
+    use hash_types::Txid;
+    use hashes::Hash;
+    use network::constants::Network;
 
     #[test]
     fn block_test() {
@@ -274,6 +595,8 @@ mod tests {
         // [test] TODO: check the transaction data
 
         assert_eq!(real_decode.get_size(), some_block.len());
+        assert_eq!(real_decode.encoded_len(), some_block.len());
+        assert_eq!(real_decode.header.encoded_len(), 80);
         assert_eq!(real_decode.get_weight(), some_block.len() * 4);
 
         // should be also ok for a non-witness block as commitment is optional in that case
@@ -304,6 +627,7 @@ mod tests {
         // [test] TODO: check the transaction data
 
         assert_eq!(real_decode.get_size(), segwit_block.len());
+        assert_eq!(real_decode.encoded_len(), segwit_block.len());
         assert_eq!(real_decode.get_weight(), 17168);
 
         assert!(real_decode.check_witness_commitment());
@@ -311,6 +635,179 @@ mod tests {
         assert_eq!(serialize(&real_decode), segwit_block);
     }
 
+    fn regtest_block(height: u32, coinbase_value: u64, txdata: Vec<Transaction>) -> Block {
+        let coinbase = CoinbaseBuilder::new(height)
+            .extra_nonce(vec![0; 4]).unwrap()
+            .output(TxOut { value: coinbase_value, script_pubkey: Script::new() })
+            .witness_reserved_value([0; 32])
+            .into_transaction(&txdata);
+        let mut block = Block {
+            header: BlockHeader {
+                version: 0x20000000,
+                prev_blockhash: Default::default(),
+                merkle_root: Default::default(),
+                time: 1600000000,
+                bits: 0x207fffff,
+                nonce: 0,
+            },
+            txdata: Some(coinbase).into_iter().chain(txdata).collect(),
+        };
+        block.header.merkle_root = block.merkle_root();
+        while block.header.validate_pow(&block.header.target()).is_err() {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    fn spend(prevouts: &[OutPoint], value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: prevouts.iter().map(|prevout| TxIn {
+                previous_output: *prevout,
+                ..Default::default()
+            }).collect(),
+            output: vec![TxOut { value: value, script_pubkey: Script::from(vec![0x51]) }],
+        }
+    }
+
+    #[test]
+    fn block_check_test() {
+        let block: Block = deserialize(&Vec::from_hex("010000004ddccd549d28f385ab457e98d1b11ce80bfea2c5ab93015ade4973e400000000bf4473e53794beae34e64fccc471dace6ae544180816f89591894e0f417a914cd74d6e49ffff001d323b3a7b0201000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0804ffff001d026e04ffffffff0100f2052a0100000043410446ef0102d1ec5240f0d061a4246c1bdef63fc3dbab7733052fbbf0ecd8f41fc26bf049ebb4f9527f374280259e7cfa99c48b0e3f39c51347a19a5819651503a5ac00000000010000000321f75f3139a013f50f315b23b0c9a2b6eac31e2bec98e5891c924664889942260000000049483045022100cb2c6b346a978ab8c61b18b5e9397755cbd17d6eb2fe0083ef32e067fa6c785a02206ce44e613f31d9a6b0517e46f3db1576e9812cc98d159bfdaf759a5014081b5c01ffffffff79cda0945903627c3da1f85fc95d0b8ee3e76ae0cfdc9a65d09744b1f8fc85430000000049483045022047957cdd957cfd0becd642f6b84d82f49b6cb4c51a91f49246908af7c3cfdf4a022100e96b46621f1bffcf5ea5982f88cef651e9354f5791602369bf5a82a6cd61a62501fffffffffe09f5fe3ffbf5ee97a54eb5e5069e9da6b4856ee86fc52938c2f979b0f38e82000000004847304402204165be9a4cbab8049e1af9723b96199bfd3e85f44c6b4c0177e3962686b26073022028f638da23fc003760861ad481ead4099312c60030d4cb57820ce4d33812a5ce01ffffffff01009d966b01000000434104ea1feff861b51fe3f5f8a3b12d0f4712db80e919548a80839fc47c6a21e66d957e9c5d8cd108c7a2d2324bad71f9904ac0ae7336507d785b17a2c115e427a32fac00000000").unwrap()).unwrap();
+        let params = Params::new(Network::Bitcoin);
+        assert_eq!(block.check(&params), Ok(()));
+        assert!(!block.is_merkle_tree_mutated());
+
+        let mut bad_pow = block.clone();
+        bad_pow.header.nonce += 1;
+        assert_eq!(bad_pow.check(&params), Err(BlockCheckError::BadProofOfWork));
+        let mut bad_merkle = block.clone();
+        bad_merkle.txdata.pop();
+        assert_eq!(bad_merkle.check(&params), Err(BlockCheckError::BadMerkleRoot));
+        // A regtest target is above the mainnet proof of work limit
+        let regtest = regtest_block(1, 0, vec![]);
+        assert_eq!(regtest.check(&params), Err(BlockCheckError::BadProofOfWork));
+
+        let params = Params::new(Network::Regtest);
+        let outpoint = OutPoint::new(Txid::hash(&[0]), 0);
+        let tx1 = spend(&[outpoint], 1000);
+        let tx2 = spend(&[OutPoint::new(tx1.txid(), 0)], 900);
+        let block = regtest_block(1, 0, vec![tx1.clone(), tx2.clone()]);
+        assert_eq!(block.check(&params), Ok(()));
+
+        // Duplicating the last one of an odd number of transactions keeps the
+        // merkle root
+        let mut mutated = block.clone();
+        mutated.txdata.push(tx2.clone());
+        assert_eq!(mutated.merkle_root(), block.header.merkle_root);
+        assert!(mutated.is_merkle_tree_mutated());
+        assert_eq!(mutated.check(&params), Err(BlockCheckError::DuplicateTransactions));
+
+        let no_coinbase = regtest_block(1, 0, vec![]);
+        let mut no_coinbase = Block { header: no_coinbase.header, txdata: vec![tx1.clone()] };
+        no_coinbase.header.merkle_root = no_coinbase.merkle_root();
+        while no_coinbase.header.validate_pow(&no_coinbase.header.target()).is_err() {
+            no_coinbase.header.nonce += 1;
+        }
+        assert_eq!(no_coinbase.check(&params), Err(BlockCheckError::NoCoinbase));
+
+        let second_coinbase = CoinbaseBuilder::new(2).into_transaction(&[]);
+        let block = regtest_block(1, 0, vec![tx1.clone(), second_coinbase]);
+        assert_eq!(block.check(&params), Err(BlockCheckError::MultipleCoinbase(2)));
+
+        let block = regtest_block(1, 0, vec![spend(&[outpoint, outpoint], 0)]);
+        match block.check(&params) {
+            Err(BlockCheckError::BadTransaction { index: 1, .. }) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // 20 sigops per CHECKMULTISIG, counted as 4 weight units each
+        let mut sigops = spend(&[outpoint], 0);
+        sigops.output[0].script_pubkey = Script::from(vec![0xae; 1001]);
+        let block = regtest_block(1, 0, vec![sigops]);
+        assert_eq!(block.check(&params), Err(BlockCheckError::TooManySigops(80_080)));
+    }
+
+    #[test]
+    fn block_check_contextual_test() {
+        let params = Params::new(Network::Regtest);
+        let height = 200;
+        let subsidy = params.block_subsidy(height);
+        let outpoint = OutPoint::new(Txid::hash(&[0]), 0);
+        let utxo = |o: &OutPoint| if *o == outpoint {
+            Some(TxOut { value: 10_000, script_pubkey: Script::new() })
+        } else {
+            None
+        };
+
+        let tx1 = spend(&[outpoint], 9_000);
+        let tx2 = spend(&[OutPoint::new(tx1.txid(), 0)], 8_500);
+        let block = regtest_block(height, subsidy + 1_500, vec![tx1.clone(), tx2.clone()]);
+        assert_eq!(block.check(&params), Ok(()));
+        assert_eq!(block.check_contextual(&params, height, 1599999999, utxo), Ok(()));
+
+        assert_eq!(block.check_contextual(&params, height, 1600000000, utxo),
+                   Err(BlockCheckError::TimeTooOld { time: 1600000000, median_time_past: 1600000000 }));
+        assert_eq!(block.check_contextual(&params, height, 0, |_| None),
+                   Err(BlockCheckError::MissingInput(outpoint)));
+
+        let overpaying = regtest_block(height, subsidy + 1_501, vec![tx1.clone(), tx2.clone()]);
+        assert_eq!(overpaying.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::BadCoinbaseValue { max: subsidy + 1_500, actual: subsidy + 1_501 }));
+
+        // Children must follow their parents in the block
+        let unordered = regtest_block(height, 0, vec![tx2.clone(), tx1.clone()]);
+        assert_eq!(unordered.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::MissingInput(OutPoint::new(tx1.txid(), 0))));
+
+        let double_spend = regtest_block(height, 0, vec![tx1.clone(), spend(&[outpoint], 1)]);
+        assert_eq!(double_spend.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::MissingInput(outpoint)));
+
+        let overspend = regtest_block(height, 0, vec![spend(&[outpoint], 10_001)]);
+        let txid = overspend.txdata[1].txid();
+        assert_eq!(overspend.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::InputsBelowOutputs(txid)));
+
+        // Output sums overflowing u64 are rejected rather than wrapping
+        let mut overflow = spend(&[outpoint], u64::max_value());
+        overflow.output.push(overflow.output[0].clone());
+        let overflowing = regtest_block(height, 0, vec![overflow]);
+        assert_eq!(overflowing.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::BadTransaction { index: 1, error: TxCheckError::OutputTotalTooLarge }));
+        let mut overflowing = regtest_block(height, u64::max_value(), vec![tx1.clone()]);
+        overflowing.txdata[0].output.push(TxOut { value: u64::max_value(), script_pubkey: Script::new() });
+        assert_eq!(overflowing.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::BadCoinbaseValue { max: subsidy + 1_000, actual: u64::max_value() }));
+
+        let mut old_version = block.clone();
+        old_version.header.version = 3;
+        assert_eq!(old_version.check_contextual(&params, 1351, 0, utxo),
+                   Err(BlockCheckError::ObsoleteVersion(3)));
+
+        let mut bip34 = params.clone();
+        bip34.bip34_height = 0;
+        assert_eq!(block.check_contextual(&bip34, height, 0, utxo), Ok(()));
+        assert_eq!(block.check_contextual(&bip34, height + 1, 0, utxo),
+                   Err(BlockCheckError::BadCoinbaseHeight(height + 1)));
+
+        let mut bad_commitment = block.clone();
+        bad_commitment.txdata[0].input[0].witness = vec![vec![1; 32]];
+        assert_eq!(bad_commitment.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::BadWitnessCommitment));
+        let mut pre_segwit = params.clone();
+        pre_segwit.segwit_height = height + 1;
+        assert_eq!(block.check_contextual(&pre_segwit, height, 0, utxo),
+                   Err(BlockCheckError::UnexpectedWitness));
+
+        // 9000 OP_CHECKSIG in each output, counted as 4 weight units each
+        let mut sigops = spend(&[outpoint], 0);
+        sigops.output = vec![TxOut { value: 0, script_pubkey: Script::from(vec![0xac; 9_000]) }; 3];
+        let block = regtest_block(height, 0, vec![sigops]);
+        assert_eq!(block.check_contextual(&params, height, 0, utxo),
+                   Err(BlockCheckError::TooManySigops(4 * 27_000)));
+    }
+
     #[test]
     fn block_version_test() {
         let block = Vec::from_hex("ffffff7f0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000").unwrap();
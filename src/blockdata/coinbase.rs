@@ -0,0 +1,220 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Coinbase transactions
+//!
+//! This module provides a builder for coinbase transactions, committing to
+//! the block height as required by BIP34, reserving space for an extra nonce
+//! and carrying the segwit witness commitment of BIP141.
+//!
+//! ```rust
+//! use bitcoin::blockdata::coinbase::CoinbaseBuilder;
+//! use bitcoin::blockdata::script::Script;
+//! use bitcoin::blockdata::transaction::TxOut;
+//! use bitcoin::consensus::Params;
+//! use bitcoin::network::constants::Network;
+//!
+//! let params = Params::new(Network::Regtest);
+//! let height = 1000;
+//! let coinbase = CoinbaseBuilder::new(height)
+//!     .extra_nonce(vec![0; 8]).unwrap()
+//!     .output(TxOut { value: params.block_subsidy(height), script_pubkey: Script::new() })
+//!     .witness_reserved_value([0; 32])
+//!     .into_transaction(&[]);
+//! assert!(coinbase.is_coin_base());
+//! ```
+//!
+
+use prelude::*;
+
+use blockdata::block::{self, Block};
+use blockdata::constants::MAX_SEQUENCE;
+use blockdata::opcodes;
+use blockdata::script::{Builder, Script};
+use blockdata::transaction::{OutPoint, Transaction, TxCheckError, TxIn, TxOut};
+
+/// The prefix of the witness commitment, following `OP_RETURN` and its push
+/// opcode in the scriptPubkey of a coinbase output
+pub const WITNESS_COMMITMENT_HEADER: [u8; 4] = [0xaa, 0x21, 0xa9, 0xed];
+
+/// Maximum length of the scriptSig of a coinbase transaction
+pub const MAX_SCRIPT_SIG_LEN: usize = 100;
+
+/// Builds coinbase transactions
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CoinbaseBuilder {
+    height: u32,
+    extra_nonce: Vec<u8>,
+    outputs: Vec<TxOut>,
+    witness_reserved_value: Option<[u8; 32]>,
+}
+
+impl CoinbaseBuilder {
+    /// Creates a builder for the coinbase of a block at the given height
+    pub fn new(height: u32) -> CoinbaseBuilder {
+        CoinbaseBuilder {
+            height: height,
+            extra_nonce: vec![],
+            outputs: vec![],
+            witness_reserved_value: None,
+        }
+    }
+
+    /// Sets the extra nonce pushed into the scriptSig after the block height.
+    /// Miners usually reserve a few zero bytes here and roll them while
+    /// searching for a valid block.
+    ///
+    /// Fails with [`TxCheckError::BadCoinbaseLength`] if the scriptSig would
+    /// be longer than [`MAX_SCRIPT_SIG_LEN`] bytes, which makes the coinbase
+    /// invalid. The height takes up to 6 bytes and the push opcodes of the
+    /// extra nonce 1 or 2 bytes.
+    pub fn extra_nonce(mut self, extra_nonce: Vec<u8>) -> Result<CoinbaseBuilder, TxCheckError> {
+        self.extra_nonce = extra_nonce;
+        let len = self.script_sig().len();
+        if len > MAX_SCRIPT_SIG_LEN {
+            return Err(TxCheckError::BadCoinbaseLength(len));
+        }
+        Ok(self)
+    }
+
+    /// Adds an output. The total value of the outputs must not exceed the
+    /// block subsidy plus the fees of the block transactions.
+    pub fn output(mut self, output: TxOut) -> CoinbaseBuilder {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Enables the segwit witness commitment, using the given witness
+    /// reserved value as the coinbase input witness.
+    pub fn witness_reserved_value(mut self, value: [u8; 32]) -> CoinbaseBuilder {
+        self.witness_reserved_value = Some(value);
+        self
+    }
+
+    /// Builds the scriptSig: the BIP34 height followed by the extra nonce.
+    pub fn script_sig(&self) -> Script {
+        let mut builder = Builder::new().push_int(self.height as i64);
+        if !self.extra_nonce.is_empty() {
+            builder = builder.push_slice(&self.extra_nonce);
+        } else if builder.len() < 2 {
+            // Heights up to 16 are single opcodes, but the scriptSig must
+            // be at least two bytes long.
+            builder = builder.push_opcode(opcodes::OP_FALSE);
+        }
+        builder.into_script()
+    }
+
+    /// Builds the coinbase transaction of a block containing the given
+    /// non-coinbase transactions. These are only used for computing the
+    /// witness commitment, if it is enabled.
+    pub fn into_transaction(self, txdata: &[Transaction]) -> Transaction {
+        let mut coinbase = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: self.script_sig(),
+                sequence: MAX_SEQUENCE,
+                witness: vec![],
+            }],
+            output: self.outputs,
+        };
+
+        if let Some(reserved_value) = self.witness_reserved_value {
+            let witness_root = {
+                let txs: Vec<&Transaction> = Some(&coinbase).into_iter().chain(txdata).collect();
+                block::witness_root(txs.into_iter())
+            };
+            let commitment = Block::compute_witness_commitment(&witness_root, &reserved_value);
+
+            let mut data = WITNESS_COMMITMENT_HEADER.to_vec();
+            data.extend_from_slice(&commitment[..]);
+            coinbase.output.push(TxOut {
+                value: 0,
+                script_pubkey: Script::new_op_return(&data),
+            });
+            coinbase.input[0].witness = vec![reserved_value.to_vec()];
+        }
+
+        coinbase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashes::hex::FromHex;
+
+    use blockdata::block::{Block, BlockHeader};
+    use blockdata::constants::COIN_VALUE;
+    use blockdata::script::Script;
+    use blockdata::transaction::{Transaction, TxCheckError, TxOut};
+    use consensus::deserialize;
+    use consensus::Params;
+    use network::constants::Network;
+
+    use super::CoinbaseBuilder;
+
+    #[test]
+    fn subsidy() {
+        let params = Params::new(Network::Bitcoin);
+        assert_eq!(params.block_subsidy(0), 50 * COIN_VALUE);
+        assert_eq!(params.block_subsidy(209_999), 50 * COIN_VALUE);
+        assert_eq!(params.block_subsidy(210_000), 25 * COIN_VALUE);
+        assert_eq!(params.block_subsidy(630_000), 625_000_000);
+        assert_eq!(params.block_subsidy(6_930_000), 0);
+        assert_eq!(params.block_subsidy(64 * 210_000), 0);
+        assert_eq!(Params::new(Network::Regtest).block_subsidy(150), 25 * COIN_VALUE);
+    }
+
+    #[test]
+    fn script_sig() {
+        assert_eq!(CoinbaseBuilder::new(1).script_sig(), Script::from(vec![0x51, 0x00]));
+        assert_eq!(CoinbaseBuilder::new(17).script_sig(), Script::from(vec![0x01, 0x11]));
+        assert_eq!(CoinbaseBuilder::new(227931).extra_nonce(vec![0; 4]).unwrap().script_sig(),
+                   Script::from(vec![0x03, 0x5b, 0x7a, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00]));
+        assert_eq!(CoinbaseBuilder::new(0xffffffff).extra_nonce(vec![0; 92]).unwrap().script_sig().len(), 100);
+        assert_eq!(CoinbaseBuilder::new(1).extra_nonce(vec![0; 97]).unwrap().script_sig().len(), 100);
+        assert_eq!(CoinbaseBuilder::new(1).extra_nonce(vec![0; 98]), Err(TxCheckError::BadCoinbaseLength(101)));
+    }
+
+    #[test]
+    fn witness_commitment() {
+        let segwit_tx: Transaction = deserialize(&Vec::from_hex("02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000").unwrap()).unwrap();
+        let params = Params::new(Network::Regtest);
+        let coinbase = CoinbaseBuilder::new(500)
+            .extra_nonce(vec![0; 8]).unwrap()
+            .output(TxOut { value: params.block_subsidy(500), script_pubkey: Script::new() })
+            .witness_reserved_value([0; 32])
+            .into_transaction(&[segwit_tx.clone()]);
+
+        assert!(coinbase.is_coin_base());
+        assert_eq!(coinbase.output.len(), 2);
+        assert_eq!(coinbase.input[0].witness, vec![vec![0; 32]]);
+
+        let mut block = Block {
+            header: BlockHeader {
+                version: 0x20000000,
+                prev_blockhash: Default::default(),
+                merkle_root: Default::default(),
+                time: 0,
+                bits: 0x207fffff,
+                nonce: 0,
+            },
+            txdata: vec![coinbase, segwit_tx],
+        };
+        block.header.merkle_root = block.merkle_root();
+        assert!(block.check_merkle_root());
+        assert!(block.check_witness_commitment());
+    }
+}
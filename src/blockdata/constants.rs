@@ -19,7 +19,7 @@
 //! single transaction
 //!
 
-use std::default::Default;
+use core::default::Default;
 
 use hashes::hex::FromHex;
 use hashes::sha256d;
@@ -42,6 +42,9 @@ pub const DIFFCHANGE_INTERVAL: u32 = 2016;
 pub const DIFFCHANGE_TIMESPAN: u32 = 14 * 24 * 3600;
 /// The maximum allowed weight for a block, see BIP 141 (network rule)
 pub const MAX_BLOCK_WEIGHT: u32 = 4_000_000;
+/// The maximum allowed number of signature check operations in a block, weighted
+/// by the witness scale factor, see BIP 141 (network rule)
+pub const MAX_BLOCK_SIGOPS_COST: u32 = 80_000;
 /// The minimum transaction weight for a valid serialized transaction
 pub const MIN_TRANSACTION_WEIGHT: u32 = 4 * 60;
 /// The factor that non-witness serialization data is multiplied by during weight calculation
@@ -84,7 +87,7 @@ fn bitcoin_genesis_tx() -> Transaction {
 
     // Outputs
     let out_script = script::Builder::new()
-        .push_slice(&Vec::from_hex("04678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f").unwrap())
+        .push_slice(&<[u8; 65]>::from_hex("04678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f").unwrap())
         .push_opcode(opcodes::all::OP_CHECKSIG)
         .into_script();
     ret.output.push(TxOut {
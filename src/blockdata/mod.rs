@@ -23,4 +23,6 @@ pub mod opcodes;
 pub mod script;
 pub mod transaction;
 pub mod block;
+pub mod coinbase;
+pub mod view;
 
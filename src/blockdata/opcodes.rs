@@ -21,8 +21,9 @@
 #![allow(non_camel_case_types)]
 
 #[cfg(feature = "serde")] use serde;
+#[cfg(feature = "serde")] use prelude::ToString;
 
-use std::fmt;
+use core::fmt;
 
 // Note: I am deliberately not implementing PartialOrd or Ord on the
 //       opcode enum. If you want to check ranges of opcodes, etc.,
@@ -24,17 +24,21 @@
 //! This module provides the structures and functions needed to support scripts.
 //!
 
-use std::default::Default;
-use std::{error, fmt, io};
+use core::default::Default;
+use core::fmt;
+use io;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
 
 #[cfg(feature = "serde")] use serde;
 
 use hash_types::{PubkeyHash, WPubkeyHash, ScriptHash, WScriptHash};
 use blockdata::opcodes;
-use consensus::{encode, Decodable, Encodable};
+use consensus::{encode, Decodable, DecodeContext, Encodable, Limit};
+use consensus::encode::VarInt;
 use hashes::Hash;
 #[cfg(feature="bitcoinconsensus")] use bitcoinconsensus;
-#[cfg(feature="bitcoinconsensus")] use std::convert;
+#[cfg(feature="bitcoinconsensus")] use core::convert;
 #[cfg(feature="bitcoinconsensus")] use OutPoint;
 
 use util::key::PublicKey;
@@ -120,6 +124,7 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn description(&self) -> &str {
@@ -136,6 +141,24 @@ impl convert::From<bitcoinconsensus::Error> for Error {
         }
     }
 }
+
+/// Maximum number of public keys counted for a single `OP_CHECKMULTISIG`
+const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+
+/// Counts the signature operations of a witness program spent with the
+/// given witness; `program` is the full witness program script.
+fn witness_program_sigops(program: &[u8], witness: &[Vec<u8>]) -> usize {
+    // Only version 0 programs are defined
+    if program[0] != opcodes::all::OP_PUSHBYTES_0.into_u8() {
+        return 0;
+    }
+    match (program.len() - 2, witness.last()) {
+        (20, _) => 1,
+        (32, Some(witness_script)) => Script::from(witness_script.clone()).count_sigops(true),
+        _ => 0,
+    }
+}
+
 /// Helper to encode an integer in script format
 fn build_scriptint(n: i64) -> Vec<u8> {
     if n == 0 { return vec![] }
@@ -389,6 +412,84 @@ impl Script {
                                opcodes::All::from(self.0[0]).classify() == opcodes::Class::IllegalOp)
     }
 
+    /// Counts the signature operations in the script the way Bitcoin Core's
+    /// `GetSigOpCount` does. If `accurate` is set, `OP_CHECKMULTISIG` preceded
+    /// by `OP_PUSHNUM_N` counts as N operations, otherwise it always counts as
+    /// the maximal number of public keys, 20.
+    pub fn count_sigops(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last_pushnum = None;
+        for instruction in self.instructions() {
+            let op = match instruction {
+                Ok(Instruction::Op(op)) => op,
+                Ok(Instruction::PushBytes(_)) => {
+                    last_pushnum = None;
+                    continue;
+                }
+                Err(_) => break,
+            };
+            if op == opcodes::all::OP_CHECKSIG || op == opcodes::all::OP_CHECKSIGVERIFY {
+                count += 1;
+            } else if op == opcodes::all::OP_CHECKMULTISIG || op == opcodes::all::OP_CHECKMULTISIGVERIFY {
+                count += match last_pushnum {
+                    Some(n) if accurate => n,
+                    _ => MAX_PUBKEYS_PER_MULTISIG,
+                };
+            }
+            last_pushnum = match op.classify() {
+                opcodes::Class::PushNum(n) if n >= 1 => Some(n as usize),
+                _ => None,
+            };
+        }
+        count
+    }
+
+    /// Counts the signature operations of a P2SH redeem script pushed by
+    /// `script_sig` when this script pubkey is P2SH. For all other script
+    /// pubkeys, counts the signature operations of the script itself.
+    pub fn count_p2sh_sigops(&self, script_sig: &Script) -> usize {
+        if !self.is_p2sh() {
+            return self.count_sigops(true);
+        }
+        match script_sig.last_push_only_data() {
+            Some(data) => Script::from(data.to_vec()).count_sigops(true),
+            None => 0,
+        }
+    }
+
+    /// Counts the signature operations of the witness script executed when
+    /// spending this script pubkey with the given scriptSig and witness, as
+    /// defined by BIP141. This covers native as well as P2SH-wrapped witness
+    /// programs; for all other script pubkeys the count is zero.
+    pub fn count_witness_sigops(&self, script_sig: &Script, witness: &[Vec<u8>]) -> usize {
+        if self.is_witness_program() {
+            return witness_program_sigops(&self.0, witness);
+        }
+        if self.is_p2sh() {
+            if let Some(data) = script_sig.last_push_only_data() {
+                if Script::from(data.to_vec()).is_witness_program() {
+                    return witness_program_sigops(data, witness);
+                }
+            }
+        }
+        0
+    }
+
+    /// Returns the data pushed last by a push-only script, or `None` if the
+    /// script is not push-only or fails to parse. Small integer pushes and
+    /// empty scripts yield empty data, as in Bitcoin Core.
+    fn last_push_only_data(&self) -> Option<&[u8]> {
+        let mut last: &[u8] = &[];
+        for instruction in self.instructions() {
+            match instruction {
+                Ok(Instruction::PushBytes(data)) => last = data,
+                Ok(Instruction::Op(op)) if op.into_u8() <= opcodes::all::OP_PUSHNUM_16.into_u8() => last = &[],
+                _ => return None,
+            }
+        }
+        Some(last)
+    }
+
     /// Iterate over the script in the form of `Instruction`s, which are an enum covering
     /// opcodes, datapushes and errors. At most one error will be returned and then the
     /// iterator will end. To instead iterate over the script as sequence of bytes, treat
@@ -765,7 +866,7 @@ impl<'de> serde::Deserialize<'de> for Script {
     where
         D: serde::Deserializer<'de>,
     {
-        use std::fmt::Formatter;
+        use core::fmt::Formatter;
         use hashes::hex::FromHex;
 
         struct Visitor;
@@ -823,6 +924,11 @@ impl Encodable for Script {
     ) -> Result<usize, encode::Error> {
         self.0.consensus_encode(s)
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        self.0.encoded_len()
+    }
 }
 
 impl Decodable for Script {
@@ -830,6 +936,13 @@ impl Decodable for Script {
     fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
         Ok(Script(Decodable::consensus_decode(d)?))
     }
+
+    #[inline]
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        let len = VarInt::consensus_decode(&mut d)?.0 as usize;
+        ctx.check(Limit::ScriptSize, len)?;
+        Ok(Script(encode::decode_bytes(d, len, ctx)?.into_boxed_slice()))
+    }
 }
 
 #[cfg(test)]
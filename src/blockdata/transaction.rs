@@ -23,20 +23,39 @@
 //! This module provides the structures and functions needed to support transactions.
 //!
 
-use std::default::Default;
-use std::{fmt, io};
+use core::default::Default;
+use core::{cmp, fmt};
+#[cfg(feature = "std")] use std::error;
+use io;
+use prelude::*;
 
 use hashes::{self, Hash, sha256d};
 use hashes::hex::FromHex;
 
 use util::endian;
-use blockdata::constants::WITNESS_SCALE_FACTOR;
+use blockdata::constants::{max_money, MAX_BLOCK_WEIGHT, WITNESS_SCALE_FACTOR};
 #[cfg(feature="bitcoinconsensus")] use blockdata::script;
 use blockdata::script::Script;
-use consensus::{encode, Decodable, Encodable};
+use consensus::{encode, Decodable, DecodeContext, Encodable, Limit};
+use network::constants::Network;
 use hash_types::*;
 use VarInt;
 
+/// Lock times below this threshold are block heights, others are timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+/// Sequence number of inputs which do not prevent their transaction from
+/// being final
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+/// Sequence number flag disabling BIP68 relative lock time
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+/// Sequence number flag making a BIP68 relative lock time a time span
+/// rather than a number of blocks
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+/// Mask of the BIP68 relative lock time in a sequence number
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+/// BIP68 relative time locks are in units of 2^9 = 512 seconds
+const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
+
 /// A reference to a transaction output
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct OutPoint {
@@ -107,7 +126,7 @@ pub enum ParseOutPointError {
     /// Error in TXID part.
     Txid(hashes::hex::Error),
     /// Error in vout part.
-    Vout(::std::num::ParseIntError),
+    Vout(::core::num::ParseIntError),
     /// Error in general format.
     Format,
     /// Size exceeds max.
@@ -128,13 +147,14 @@ impl fmt::Display for ParseOutPointError {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
-impl ::std::error::Error for ParseOutPointError {
+impl error::Error for ParseOutPointError {
     fn description(&self) -> &str {
         "description() is deprecated; use Display"
     }
 
-    fn cause(&self) -> Option<&::std::error::Error> {
+    fn cause(&self) -> Option<&error::Error> {
         match *self {
             ParseOutPointError::Txid(ref e) => Some(e),
             ParseOutPointError::Vout(ref e) => Some(e),
@@ -155,7 +175,7 @@ fn parse_vout(s: &str) -> Result<u32, ParseOutPointError> {
     Ok(s.parse().map_err(ParseOutPointError::Vout)?)
 }
 
-impl ::std::str::FromStr for OutPoint {
+impl ::core::str::FromStr for OutPoint {
     type Err = ParseOutPointError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -177,6 +197,62 @@ impl ::std::str::FromStr for OutPoint {
     }
 }
 
+/// An error returned by the context-free transaction checks of
+/// [`Transaction::check`], which mirror Bitcoin Core's `CheckTransaction`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TxCheckError {
+    /// The transaction has no inputs
+    NoInputs,
+    /// The transaction has no outputs
+    NoOutputs,
+    /// The weight of the transaction without witness data exceeds the
+    /// block weight limit
+    Oversized(usize),
+    /// An output value exceeds the maximum amount of money
+    OutputTooLarge {
+        /// Index of the offending output
+        index: usize,
+        /// Value of the offending output
+        value: u64,
+    },
+    /// The sum of output values exceeds the maximum amount of money
+    OutputTotalTooLarge,
+    /// The same previous output is spent by more than one input
+    DuplicateInput(OutPoint),
+    /// The coinbase scriptSig is shorter than 2 or longer than 100 bytes
+    BadCoinbaseLength(usize),
+    /// A non-coinbase input at the given index spends a null previous output
+    NullPrevout(usize),
+}
+
+impl fmt::Display for TxCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TxCheckError::NoInputs => f.write_str("transaction has no inputs"),
+            TxCheckError::NoOutputs => f.write_str("transaction has no outputs"),
+            TxCheckError::Oversized(weight) => write!(f,
+                "transaction is oversized: stripped weight {} exceeds {}", weight, MAX_BLOCK_WEIGHT),
+            TxCheckError::OutputTooLarge { index, value } => write!(f,
+                "value {} of output {} is out of range", value, index),
+            TxCheckError::OutputTotalTooLarge => f.write_str("sum of output values is out of range"),
+            TxCheckError::DuplicateInput(ref outpoint) => write!(f,
+                "duplicate input spending {}", outpoint),
+            TxCheckError::BadCoinbaseLength(len) => write!(f,
+                "coinbase scriptSig length {} is outside of the allowed 2..100 range", len),
+            TxCheckError::NullPrevout(index) => write!(f,
+                "non-coinbase input {} spends a null previous output", index),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for TxCheckError {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
 /// A transaction input, which defines old coins to be consumed
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub struct TxIn {
@@ -271,6 +347,25 @@ pub struct Transaction {
 }
 serde_struct_impl!(Transaction, version, lock_time, input, output);
 
+/// The BIP68 relative lock time constraints of a transaction: the lowest
+/// height of a block which may include it, and the lowest median time past of
+/// the block preceding that block.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SequenceLock {
+    /// Minimum height of the including block
+    pub min_height: u32,
+    /// Minimum median time past of the block before the including block
+    pub min_time: u32,
+}
+
+impl SequenceLock {
+    /// Checks whether a block at `height`, whose previous block has median
+    /// time past `median_time_past`, satisfies the lock.
+    pub fn is_satisfied(&self, height: u32, median_time_past: u32) -> bool {
+        height >= self.min_height && median_time_past >= self.min_time
+    }
+}
+
 impl Transaction {
     /// Computes a "normalized TXID" which does not include any signatures.
     /// This gives a way to identify a transaction that is ``the same'' as
@@ -395,6 +490,47 @@ impl Transaction {
         self.get_scaled_size(1)
     }
 
+    /// Gets the size of this transaction without witness data, i.e. the size
+    /// of its pre-BIP141 consensus serialization.
+    #[inline]
+    pub fn get_stripped_size(&self) -> usize {
+        // Weight counts non-witness data four times and witness data once,
+        // so weight minus size is three times the stripped size.
+        (self.get_weight() - self.get_size()) / (WITNESS_SCALE_FACTOR - 1)
+    }
+
+    /// Counts the signature operations in all scriptSigs and scriptPubkeys of
+    /// this transaction, the way Bitcoin Core's `GetLegacySigOpCount` does.
+    pub fn get_legacy_sigop_count(&self) -> usize {
+        let input_sigops: usize = self.input.iter().map(|i| i.script_sig.count_sigops(false)).sum();
+        let output_sigops: usize = self.output.iter().map(|o| o.script_pubkey.count_sigops(false)).sum();
+        input_sigops + output_sigops
+    }
+
+    /// Computes the signature operations cost of this transaction as defined
+    /// by BIP141: legacy and, if `p2sh` is set, P2SH signature operations
+    /// count four times, while witness ones are counted once if `witness` is
+    /// set. Witness programs nested in P2SH are only looked into if `p2sh` is
+    /// set too. Returns `None` if some spent output is unknown to `spent`.
+    pub fn get_sigop_cost<S>(&self, mut spent: S, p2sh: bool, witness: bool) -> Option<usize>
+        where S: FnMut(&OutPoint) -> Option<TxOut>
+    {
+        let mut cost = self.get_legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        if self.is_coin_base() {
+            return Some(cost);
+        }
+        for input in &self.input {
+            let prevout = spent(&input.previous_output)?;
+            if p2sh && prevout.script_pubkey.is_p2sh() {
+                cost += prevout.script_pubkey.count_p2sh_sigops(&input.script_sig) * WITNESS_SCALE_FACTOR;
+            }
+            if witness && (p2sh || !prevout.script_pubkey.is_p2sh()) {
+                cost += prevout.script_pubkey.count_witness_sigops(&input.script_sig, &input.witness);
+            }
+        }
+        Some(cost)
+    }
+
     /// Internal utility function for get_{size,weight}
     fn get_scaled_size(&self, scale_factor: usize) -> usize {
         let mut input_weight = 0;
@@ -449,10 +585,109 @@ impl Transaction {
         Ok(())
     }
 
+    /// Checks whether the transaction is final in a block at `height` with
+    /// timestamp `time`: either its lock time has passed, or all its inputs
+    /// have final sequence numbers. Since BIP113, `time` must be the median
+    /// time past of the block preceding the including block.
+    pub fn is_final(&self, height: u32, time: u32) -> bool {
+        if self.lock_time == 0 {
+            return true;
+        }
+        let limit = if self.lock_time < LOCKTIME_THRESHOLD { height } else { time };
+        if self.lock_time < limit {
+            return true;
+        }
+        self.input.iter().all(|input| input.sequence == SEQUENCE_FINAL)
+    }
+
+    /// Computes the BIP68 relative lock time constraints of this transaction.
+    /// For each spent output, `confirmation` must return the height of the
+    /// block which created it, and the median time past of the block before
+    /// that one. Returns `None` if some spent output is unknown.
+    ///
+    /// Transactions with a version lower than 2 have no relative lock times.
+    /// As in Bitcoin Core the version is compared as an unsigned number, so
+    /// negative versions do enforce BIP68.
+    pub fn sequence_lock<C>(&self, mut confirmation: C) -> Option<SequenceLock>
+        where C: FnMut(&OutPoint) -> Option<(u32, u32)>
+    {
+        let mut lock = SequenceLock::default();
+        if (self.version as u32) < 2 || self.is_coin_base() {
+            return Some(lock);
+        }
+        for input in &self.input {
+            if input.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+                continue;
+            }
+            let (height, median_time_past) = confirmation(&input.previous_output)?;
+            let value = input.sequence & SEQUENCE_LOCKTIME_MASK;
+            if input.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+                let min_time = median_time_past.saturating_add(value << SEQUENCE_LOCKTIME_GRANULARITY);
+                lock.min_time = cmp::max(lock.min_time, min_time);
+            } else {
+                lock.min_height = cmp::max(lock.min_height, height.saturating_add(value));
+            }
+        }
+        Some(lock)
+    }
+
     /// Is this a coin base transaction?
     pub fn is_coin_base(&self) -> bool {
         self.input.len() == 1 && self.input[0].previous_output.is_null()
     }
+
+    /// Performs the context-free consensus checks done by Bitcoin Core's
+    /// `CheckTransaction`: the transaction must have inputs and outputs,
+    /// must fit into a block without its witness, must not create more than
+    /// `max_money` in any output or in total, must not spend the same
+    /// output twice, and must either be a coinbase with a 2 to 100 bytes
+    /// long scriptSig or spend only non-null previous outputs.
+    ///
+    /// These checks do not need any chain state and are cheap, so they are
+    /// useful for rejecting garbage before doing any further validation.
+    pub fn check(&self, network: Network) -> Result<(), TxCheckError> {
+        if self.input.is_empty() {
+            return Err(TxCheckError::NoInputs);
+        }
+        if self.output.is_empty() {
+            return Err(TxCheckError::NoOutputs);
+        }
+
+        let stripped_weight = self.get_stripped_size() * WITNESS_SCALE_FACTOR;
+        if stripped_weight > MAX_BLOCK_WEIGHT as usize {
+            return Err(TxCheckError::Oversized(stripped_weight));
+        }
+
+        let max_money = max_money(network);
+        let mut total: u64 = 0;
+        for (index, output) in self.output.iter().enumerate() {
+            if output.value > max_money {
+                return Err(TxCheckError::OutputTooLarge { index: index, value: output.value });
+            }
+            total = match total.checked_add(output.value) {
+                Some(total) if total <= max_money => total,
+                _ => return Err(TxCheckError::OutputTotalTooLarge),
+            };
+        }
+
+        let mut spent = HashSet::with_capacity(self.input.len());
+        for input in &self.input {
+            if !spent.insert(input.previous_output) {
+                return Err(TxCheckError::DuplicateInput(input.previous_output));
+            }
+        }
+
+        if self.is_coin_base() {
+            let len = self.input[0].script_sig.len();
+            if len < 2 || len > 100 {
+                return Err(TxCheckError::BadCoinbaseLength(len));
+            }
+        } else if let Some(index) = self.input.iter().position(|i| i.previous_output.is_null()) {
+            return Err(TxCheckError::NullPrevout(index));
+        }
+
+        Ok(())
+    }
 }
 
 impl_consensus_encoding!(TxOut, value, script_pubkey);
@@ -465,6 +700,10 @@ impl Encodable for OutPoint {
         let len = self.txid.consensus_encode(&mut s)?;
         Ok(len + self.vout.consensus_encode(s)?)
     }
+
+    fn encoded_len(&self) -> usize {
+        32 + 4
+    }
 }
 impl Decodable for OutPoint {
     fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
@@ -486,15 +725,23 @@ impl Encodable for TxIn {
         len += self.sequence.consensus_encode(s)?;
         Ok(len)
     }
+
+    fn encoded_len(&self) -> usize {
+        self.previous_output.encoded_len() + self.script_sig.encoded_len() + 4
+    }
 }
 impl Decodable for TxIn {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
-        Ok(TxIn {
-            previous_output: Decodable::consensus_decode(&mut d)?,
-            script_sig: Decodable::consensus_decode(&mut d)?,
-            sequence: Decodable::consensus_decode(d)?,
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        ctx.nested(|ctx| Ok(TxIn {
+            previous_output: Decodable::consensus_decode_limited(&mut d, ctx)?,
+            script_sig: Decodable::consensus_decode_limited(&mut d, ctx)?,
+            sequence: Decodable::consensus_decode_limited(&mut d, ctx)?,
             witness: vec![],
-        })
+        }))
     }
 }
 
@@ -527,48 +774,69 @@ impl Encodable for Transaction {
         len += self.lock_time.consensus_encode(s)?;
         Ok(len)
     }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 4 + self.input.encoded_len() + self.output.encoded_len() + 4;
+        // Transactions without inputs are always encoded with a witness
+        if self.input.is_empty() || self.input.iter().any(|input| !input.witness.is_empty()) {
+            // Segwit marker and flag
+            len += 2;
+            for input in &self.input {
+                len += input.witness.encoded_len();
+            }
+        }
+        len
+    }
 }
 
 impl Decodable for Transaction {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
-        let version = i32::consensus_decode(&mut d)?;
-        let input = Vec::<TxIn>::consensus_decode(&mut d)?;
-        // segwit
-        if input.is_empty() {
-            let segwit_flag = u8::consensus_decode(&mut d)?;
-            match segwit_flag {
-                // BIP144 input witnesses
-                1 => {
-                    let mut input = Vec::<TxIn>::consensus_decode(&mut d)?;
-                    let output = Vec::<TxOut>::consensus_decode(&mut d)?;
-                    for txin in input.iter_mut() {
-                        txin.witness = Decodable::consensus_decode(&mut d)?;
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        ctx.nested(|ctx| {
+            let version = i32::consensus_decode(&mut d)?;
+            let input = Vec::<TxIn>::consensus_decode_limited(&mut d, ctx)?;
+            // segwit
+            if input.is_empty() {
+                let segwit_flag = u8::consensus_decode(&mut d)?;
+                match segwit_flag {
+                    // BIP144 input witnesses
+                    1 => {
+                        let mut input = Vec::<TxIn>::consensus_decode_limited(&mut d, ctx)?;
+                        let output = Vec::<TxOut>::consensus_decode_limited(&mut d, ctx)?;
+                        for txin in input.iter_mut() {
+                            let items = VarInt::consensus_decode(&mut d)?.0;
+                            ctx.check(Limit::WitnessItems, items as usize)?;
+                            txin.witness = encode::decode_vec(&mut d, items, ctx)?;
+                        }
+                        if !input.is_empty() && input.iter().all(|input| input.witness.is_empty()) {
+                            Err(encode::Error::ParseFailed("witness flag set but no witnesses present"))
+                        } else {
+                            Ok(Transaction {
+                                version: version,
+                                input: input,
+                                output: output,
+                                lock_time: Decodable::consensus_decode(d)?,
+                            })
+                        }
                     }
-                    if !input.is_empty() && input.iter().all(|input| input.witness.is_empty()) {
-                        Err(encode::Error::ParseFailed("witness flag set but no witnesses present"))
-                    } else {
-                        Ok(Transaction {
-                            version: version,
-                            input: input,
-                            output: output,
-                            lock_time: Decodable::consensus_decode(d)?,
-                        })
+                    // We don't support anything else
+                    x => {
+                        Err(encode::Error::UnsupportedSegwitFlag(x))
                     }
                 }
-                // We don't support anything else
-                x => {
-                    Err(encode::Error::UnsupportedSegwitFlag(x))
-                }
+            // non-segwit
+            } else {
+                Ok(Transaction {
+                    version: version,
+                    input: input,
+                    output: Decodable::consensus_decode_limited(&mut d, ctx)?,
+                    lock_time: Decodable::consensus_decode(d)?,
+                })
             }
-        // non-segwit
-        } else {
-            Ok(Transaction {
-                version: version,
-                input: input,
-                output: Decodable::consensus_decode(&mut d)?,
-                lock_time: Decodable::consensus_decode(d)?,
-            })
-        }
+        })
     }
 }
 
@@ -608,7 +876,7 @@ impl fmt::Display for SigHashType {
     }
 }
 
-impl std::str::FromStr for SigHashType {
+impl ::core::str::FromStr for SigHashType {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -660,12 +928,12 @@ impl SigHashType {
 
 #[cfg(test)]
 mod tests {
-    use super::{OutPoint, ParseOutPointError, Transaction, TxIn};
+    use super::{OutPoint, ParseOutPointError, SequenceLock, Transaction, TxIn, TxOut, TxCheckError};
 
     use std::str::FromStr;
     use blockdata::constants::WITNESS_SCALE_FACTOR;
     use blockdata::script::Script;
-    use consensus::encode::serialize;
+    use consensus::encode::{serialize, Encodable};
     use consensus::encode::deserialize;
 
     use hashes::Hash;
@@ -734,6 +1002,146 @@ mod tests {
         assert!(!tx.is_coin_base());
     }
 
+    #[test]
+    fn test_check() {
+        use network::constants::Network;
+        use blockdata::constants::{self, max_money};
+
+        let genesis = constants::genesis_block(Network::Bitcoin);
+        assert_eq!(genesis.txdata[0].check(Network::Bitcoin), Ok(()));
+
+        let tx: Transaction = deserialize(&Vec::from_hex("0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000").unwrap()).unwrap();
+        assert_eq!(tx.check(Network::Bitcoin), Ok(()));
+
+        let mut bad = tx.clone();
+        bad.input.clear();
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::NoInputs));
+
+        let mut bad = tx.clone();
+        bad.output.clear();
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::NoOutputs));
+
+        let mut bad = tx.clone();
+        bad.output[0].value = max_money(Network::Bitcoin) + 1;
+        assert_eq!(bad.check(Network::Bitcoin),
+                   Err(TxCheckError::OutputTooLarge { index: 0, value: max_money(Network::Bitcoin) + 1 }));
+
+        let mut bad = tx.clone();
+        bad.output[0].value = max_money(Network::Bitcoin);
+        bad.output.push(TxOut { value: 1, script_pubkey: Script::new() });
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::OutputTotalTooLarge));
+
+        let mut bad = tx.clone();
+        let input = bad.input[0].clone();
+        bad.input.push(input);
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::DuplicateInput(tx.input[0].previous_output)));
+
+        let mut bad = tx.clone();
+        bad.input.push(TxIn::default());
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::NullPrevout(1)));
+
+        let mut bad = genesis.txdata[0].clone();
+        bad.input[0].script_sig = Script::from(vec![0x51]);
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::BadCoinbaseLength(1)));
+        bad.input[0].script_sig = Script::from(vec![0x51; 101]);
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::BadCoinbaseLength(101)));
+
+        let mut bad = tx.clone();
+        bad.output[0].script_pubkey = Script::from(vec![0x6a; 1_000_000]);
+        assert_eq!(bad.check(Network::Bitcoin), Err(TxCheckError::Oversized(bad.get_weight())));
+    }
+
+    #[test]
+    fn test_is_final() {
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn { sequence: 0xfffffffe, ..Default::default() }],
+            output: vec![],
+        };
+        assert!(tx.is_final(0, 0));
+
+        tx.lock_time = 100;
+        assert!(!tx.is_final(100, 600_000_000));
+        assert!(tx.is_final(101, 0));
+
+        tx.lock_time = 1_600_000_000;
+        assert!(!tx.is_final(1_000_000, 1_600_000_000));
+        assert!(tx.is_final(0, 1_600_000_001));
+
+        tx.input[0].sequence = 0xffffffff;
+        assert!(tx.is_final(0, 0));
+    }
+
+    #[test]
+    fn test_sequence_lock() {
+        let prev = |vout| OutPoint::new(Txid::hash(&[]), vout);
+        let input = |vout, sequence| TxIn { previous_output: prev(vout), sequence: sequence, ..Default::default() };
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![
+                input(0, 10),
+                input(1, 20),
+                input(2, (1 << 22) | 3),
+                input(3, (1 << 31) | 1000),
+            ],
+            output: vec![],
+        };
+        let confirmation = |outpoint: &OutPoint| match outpoint.vout {
+            0 => Some((100, 1_000_000)),
+            1 => Some((85, 990_000)),
+            2 => Some((90, 995_000)),
+            _ => None,
+        };
+        let lock = tx.sequence_lock(confirmation).unwrap();
+        assert_eq!(lock, SequenceLock { min_height: 110, min_time: 995_000 + 3 * 512 });
+        assert!(lock.is_satisfied(110, 996_536));
+        assert!(!lock.is_satisfied(109, 996_536));
+        assert!(!lock.is_satisfied(110, 996_535));
+
+        tx.input.push(input(4, 0));
+        assert_eq!(tx.sequence_lock(confirmation), None);
+
+        tx.version = -1;
+        assert_eq!(tx.sequence_lock(confirmation), None);
+
+        tx.version = 1;
+        assert_eq!(tx.sequence_lock(confirmation), Some(SequenceLock::default()));
+    }
+
+    #[test]
+    fn test_sigop_cost() {
+        use blockdata::script::Builder;
+
+        let program = Script::new_v0_wpkh(&WPubkeyHash::hash(&[]));
+        let nested = program.to_p2sh();
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&[]), 0),
+                script_sig: Builder::new().push_slice(program.as_bytes()).into_script(),
+                witness: vec![vec![0; 72], vec![0; 33]],
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+        let spent = |script_pubkey: &Script| {
+            let txout = TxOut { value: 0, script_pubkey: script_pubkey.clone() };
+            move |_: &OutPoint| Some(txout.clone())
+        };
+
+        assert_eq!(tx.get_sigop_cost(spent(&nested), true, true), Some(1));
+        assert_eq!(tx.get_sigop_cost(spent(&nested), true, false), Some(0));
+        // Without P2SH the redeem script is never looked at
+        assert_eq!(tx.get_sigop_cost(spent(&nested), false, true), Some(0));
+
+        tx.input[0].script_sig = Script::new();
+        assert_eq!(tx.get_sigop_cost(spent(&program), false, true), Some(1));
+        assert_eq!(tx.get_sigop_cost(|_| None, true, true), None);
+    }
+
     #[test]
     fn test_nonsegwit_transaction() {
         let tx_bytes = Vec::from_hex("0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000").unwrap();
@@ -758,6 +1166,7 @@ mod tests {
                    "a6eab3c14ab5272a58a5ba91505ba1a4b6d7a3a9fcbd187b6cd99a7b6d548cb7".to_string());
         assert_eq!(realtx.get_weight(), tx_bytes.len()*WITNESS_SCALE_FACTOR);
         assert_eq!(realtx.get_size(), tx_bytes.len());
+        assert_eq!(realtx.encoded_len(), tx_bytes.len());
     }
 
     #[test]
@@ -921,6 +1330,12 @@ mod tests {
 
         let consensus_encoded = serialize(&tx);
         assert_eq!(consensus_encoded, tx_bytes);
+        assert_eq!(tx.encoded_len(), tx_bytes.len());
+        assert_eq!(tx.input[0].encoded_len(), serialize(&tx.input[0]).len());
+
+        // Transactions without inputs are encoded with the segwit marker
+        let empty = Transaction { version: 1, lock_time: 0, input: vec![], output: tx.output.clone() };
+        assert_eq!(empty.encoded_len(), serialize(&empty).len());
     }
 
 
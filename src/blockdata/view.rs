@@ -0,0 +1,584 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Borrowed views
+//!
+//! Read-only views of serialized blocks and transactions which borrow the
+//! scripts and witnesses from the original buffer instead of allocating
+//! them. The framing of the data is validated once when the view is created,
+//! with the same rules as the owned decoders, so that iterating over inputs,
+//! outputs and witnesses afterwards cannot fail. Transaction ids are
+//! computed on demand from the serialized data.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use bitcoin::blockdata::constants::genesis_block;
+//! use bitcoin::blockdata::view::BlockView;
+//! use bitcoin::consensus::serialize;
+//! use bitcoin::network::constants::Network;
+//!
+//! let data = serialize(&genesis_block(Network::Bitcoin));
+//! let block = BlockView::new(&data).unwrap();
+//! for tx in block.transactions() {
+//!     for output in tx.outputs() {
+//!         println!("{}: {} satoshis", tx.txid(), output.value);
+//!     }
+//! }
+//! ```
+//!
+
+use io;
+use prelude::*;
+
+use hashes::{Hash, HashEngine};
+
+use blockdata::block::{Block, BlockHeader};
+use blockdata::transaction::{OutPoint, Transaction};
+use consensus::encode::{self, Decodable, VarInt};
+use hash_types::{BlockHash, TxMerkleNode, Txid, Wtxid};
+use util::endian;
+use util::hash::bitcoin_merkle_root;
+
+/// Cursor over a buffer handing out borrowed slices
+#[derive(Clone)]
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], encode::Error> {
+        if self.data.len() - self.pos < len {
+            return Err(encode::Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of data")));
+        }
+        self.pos += len;
+        Ok(&self.data[self.pos - len..self.pos])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, encode::Error> {
+        Ok(endian::slice_to_u32_le(self.take(4)?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, encode::Error> {
+        Ok(endian::slice_to_u64_le(self.take(8)?))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, encode::Error> {
+        let mut rest = &self.data[self.pos..];
+        let len = rest.len();
+        let VarInt(n) = VarInt::consensus_decode(&mut rest)?;
+        self.pos += len - rest.len();
+        Ok(n)
+    }
+
+    fn read_bytes(&mut self) -> Result<&'a [u8], encode::Error> {
+        let len = self.read_varint()?;
+        if len > encode::MAX_VEC_SIZE as u64 {
+            return Err(encode::Error::OversizedVectorAllocation { requested: len as usize, max: encode::MAX_VEC_SIZE });
+        }
+        self.take(len as usize)
+    }
+}
+
+/// A borrowed view of a serialized transaction
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TransactionView<'a> {
+    data: &'a [u8],
+    input_count: usize,
+    input_start: usize,
+    output_count: usize,
+    output_start: usize,
+    witness_start: Option<usize>,
+}
+
+impl<'a> TransactionView<'a> {
+    /// Creates a view of a serialized transaction, which must span all of
+    /// `data`.
+    pub fn new(data: &'a [u8]) -> Result<TransactionView<'a>, encode::Error> {
+        let mut reader = Reader { data: data, pos: 0 };
+        let tx = TransactionView::parse(&mut reader)?;
+        if reader.pos != data.len() {
+            return Err(encode::Error::ParseFailed("data not consumed entirely when explicitly deserializing"));
+        }
+        Ok(tx)
+    }
+
+    fn parse(reader: &mut Reader<'a>) -> Result<TransactionView<'a>, encode::Error> {
+        let start = reader.pos;
+        reader.take(4)?;
+        let mut input_count = reader.read_varint()?;
+        let segwit = input_count == 0;
+        if segwit {
+            match reader.take(1)?[0] {
+                1 => input_count = reader.read_varint()?,
+                x => return Err(encode::Error::UnsupportedSegwitFlag(x)),
+            }
+        }
+        let input_start = reader.pos - start;
+        for _ in 0..input_count {
+            reader.take(36)?;
+            reader.read_bytes()?;
+            reader.take(4)?;
+        }
+        let output_count = reader.read_varint()?;
+        let output_start = reader.pos - start;
+        for _ in 0..output_count {
+            reader.take(8)?;
+            reader.read_bytes()?;
+        }
+        let witness_start = if segwit {
+            let witness_start = reader.pos - start;
+            let mut witnesses = false;
+            for _ in 0..input_count {
+                let len = reader.read_varint()?;
+                witnesses |= len != 0;
+                for _ in 0..len {
+                    reader.read_bytes()?;
+                }
+            }
+            if input_count != 0 && !witnesses {
+                return Err(encode::Error::ParseFailed("witness flag set but no witnesses present"));
+            }
+            Some(witness_start)
+        } else {
+            None
+        };
+        reader.take(4)?;
+
+        Ok(TransactionView {
+            data: &reader.data[start..reader.pos],
+            input_count: input_count as usize,
+            input_start: input_start,
+            output_count: output_count as usize,
+            output_start: output_start,
+            witness_start: witness_start,
+        })
+    }
+
+    /// Returns the serialized transaction.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the protocol version.
+    pub fn version(&self) -> i32 {
+        endian::slice_to_i32_le(&self.data[..4])
+    }
+
+    /// Returns the lock time.
+    pub fn lock_time(&self) -> u32 {
+        endian::slice_to_u32_le(&self.data[self.data.len() - 4..])
+    }
+
+    /// Checks whether the transaction is serialized with witnesses.
+    pub fn has_witness(&self) -> bool {
+        self.witness_start.is_some()
+    }
+
+    /// Computes the transaction id, which does not commit to witnesses.
+    pub fn txid(&self) -> Txid {
+        match self.witness_start {
+            Some(witness_start) => {
+                let mut engine = Txid::engine();
+                engine.input(&self.data[..4]);
+                engine.input(&self.data[6..witness_start]);
+                engine.input(&self.data[self.data.len() - 4..]);
+                Txid::from_engine(engine)
+            },
+            None => Txid::hash(self.data),
+        }
+    }
+
+    /// Computes the witness transaction id.
+    pub fn wtxid(&self) -> Wtxid {
+        Wtxid::hash(self.data)
+    }
+
+    /// Returns the number of inputs.
+    pub fn input_count(&self) -> usize {
+        self.input_count
+    }
+
+    /// Returns the number of outputs.
+    pub fn output_count(&self) -> usize {
+        self.output_count
+    }
+
+    /// Iterates over the inputs.
+    pub fn inputs(&self) -> Inputs<'a> {
+        Inputs {
+            reader: Reader { data: self.data, pos: self.input_start },
+            witness_reader: self.witness_start.map(|pos| Reader { data: self.data, pos: pos }),
+            remaining: self.input_count,
+        }
+    }
+
+    /// Iterates over the outputs.
+    pub fn outputs(&self) -> Outputs<'a> {
+        Outputs {
+            reader: Reader { data: self.data, pos: self.output_start },
+            remaining: self.output_count,
+        }
+    }
+
+    /// Decodes an owned transaction.
+    pub fn decode(&self) -> Result<Transaction, encode::Error> {
+        encode::deserialize(self.data)
+    }
+}
+
+/// A borrowed view of a transaction input
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TxInView<'a> {
+    /// The reference to the previous output that is being used as an input
+    pub previous_output: OutPoint,
+    /// The serialized script which pushes values on the stack
+    pub script_sig: &'a [u8],
+    /// The sequence number
+    pub sequence: u32,
+    /// The witness stack
+    pub witness: WitnessView<'a>,
+}
+
+/// A borrowed view of a transaction output
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TxOutView<'a> {
+    /// The value of the output, in satoshis
+    pub value: u64,
+    /// The serialized script which must be satisfied for the output to be spent
+    pub script_pubkey: &'a [u8],
+}
+
+/// A borrowed view of the witness stack of an input
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WitnessView<'a> {
+    data: &'a [u8],
+    len: usize,
+}
+
+impl<'a> WitnessView<'a> {
+    /// Returns the number of stack elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates over the stack elements.
+    pub fn iter(&self) -> WitnessElements<'a> {
+        WitnessElements {
+            reader: Reader { data: self.data, pos: 0 },
+            remaining: self.len,
+        }
+    }
+
+    /// Copies the stack elements.
+    pub fn to_vec(&self) -> Vec<Vec<u8>> {
+        self.iter().map(|element| element.to_vec()).collect()
+    }
+}
+
+/// Iterator over the inputs of a [`TransactionView`]
+#[derive(Clone)]
+pub struct Inputs<'a> {
+    reader: Reader<'a>,
+    witness_reader: Option<Reader<'a>>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Inputs<'a> {
+    type Item = TxInView<'a>;
+
+    fn next(&mut self) -> Option<TxInView<'a>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // The framing was validated when creating the transaction view
+        let txid = Txid::from_slice(self.reader.take(32).unwrap()).unwrap();
+        let vout = self.reader.read_u32().unwrap();
+        let script_sig = self.reader.read_bytes().unwrap();
+        let sequence = self.reader.read_u32().unwrap();
+        let witness = match self.witness_reader {
+            Some(ref mut reader) => {
+                let len = reader.read_varint().unwrap() as usize;
+                let start = reader.pos;
+                for _ in 0..len {
+                    reader.read_bytes().unwrap();
+                }
+                WitnessView { data: &reader.data[start..reader.pos], len: len }
+            },
+            None => WitnessView { data: &[], len: 0 },
+        };
+        Some(TxInView {
+            previous_output: OutPoint::new(txid, vout),
+            script_sig: script_sig,
+            sequence: sequence,
+            witness: witness,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Inputs<'a> {}
+
+/// Iterator over the outputs of a [`TransactionView`]
+#[derive(Clone)]
+pub struct Outputs<'a> {
+    reader: Reader<'a>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Outputs<'a> {
+    type Item = TxOutView<'a>;
+
+    fn next(&mut self) -> Option<TxOutView<'a>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // The framing was validated when creating the transaction view
+        Some(TxOutView {
+            value: self.reader.read_u64().unwrap(),
+            script_pubkey: self.reader.read_bytes().unwrap(),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Outputs<'a> {}
+
+/// Iterator over the elements of a [`WitnessView`]
+#[derive(Clone)]
+pub struct WitnessElements<'a> {
+    reader: Reader<'a>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for WitnessElements<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // The framing was validated when creating the transaction view
+        Some(self.reader.read_bytes().unwrap())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for WitnessElements<'a> {}
+
+/// A borrowed view of a serialized block
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockView<'a> {
+    header: BlockHeader,
+    data: &'a [u8],
+    transactions: Vec<TransactionView<'a>>,
+}
+
+impl<'a> BlockView<'a> {
+    /// Creates a view of a serialized block, which must span all of `data`.
+    pub fn new(data: &'a [u8]) -> Result<BlockView<'a>, encode::Error> {
+        let mut reader = Reader { data: data, pos: 0 };
+        let header = encode::deserialize(reader.take(80)?)?;
+        let count = reader.read_varint()?;
+        // Every transaction takes at least ten bytes
+        let mut transactions = Vec::with_capacity(::core::cmp::min(count as usize, data.len() / 10));
+        for _ in 0..count {
+            transactions.push(TransactionView::parse(&mut reader)?);
+        }
+        if reader.pos != data.len() {
+            return Err(encode::Error::ParseFailed("data not consumed entirely when explicitly deserializing"));
+        }
+        Ok(BlockView {
+            header: header,
+            data: data,
+            transactions: transactions,
+        })
+    }
+
+    /// Returns the serialized block.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the block header.
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    /// Returns the block hash.
+    pub fn block_hash(&self) -> BlockHash {
+        self.header.block_hash()
+    }
+
+    /// Returns the transactions.
+    pub fn transactions(&self) -> &[TransactionView<'a>] {
+        &self.transactions
+    }
+
+    /// Checks if the merkle root of the header matches the transactions.
+    pub fn check_merkle_root(&self) -> bool {
+        let hashes = self.transactions.iter().map(|tx| tx.txid().as_hash());
+        let root: TxMerkleNode = bitcoin_merkle_root(hashes).into();
+        root == self.header.merkle_root
+    }
+
+    /// Decodes an owned block.
+    pub fn decode(&self) -> Result<Block, encode::Error> {
+        encode::deserialize(self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blockdata::block::{Block, BlockHeader};
+    use blockdata::constants::genesis_block;
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use consensus::encode::{self, serialize};
+    use hash_types::Txid;
+    use hashes::Hash;
+    use network::constants::Network;
+
+    use super::{BlockView, TransactionView};
+
+    fn block() -> Block {
+        let input = |vout, witness: Vec<Vec<u8>>| TxIn {
+            previous_output: OutPoint::new(Txid::hash(&[vout as u8]), vout),
+            script_sig: Script::from(vec![0x51; vout as usize]),
+            sequence: 0xfffffffd - vout,
+            witness: witness,
+        };
+        let output = |value| TxOut { value: value, script_pubkey: Script::from(vec![0x6a; value as usize % 300]) };
+        let mut block = genesis_block(Network::Regtest);
+        block.txdata.push(Transaction {
+            version: 2,
+            lock_time: 500,
+            input: vec![input(0, vec![vec![1; 72], vec![], vec![2; 33]]), input(1, vec![]), input(300, vec![vec![3]])],
+            output: vec![output(1000), output(299)],
+        });
+        block.txdata.push(Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![input(2, vec![])],
+            output: vec![output(0x1000)],
+        });
+        block.header.merkle_root = block.merkle_root();
+        block
+    }
+
+    fn check_transaction(view: &TransactionView, tx: &Transaction) {
+        assert_eq!(view.version(), tx.version);
+        assert_eq!(view.lock_time(), tx.lock_time);
+        assert_eq!(view.txid(), tx.txid());
+        assert_eq!(view.wtxid(), tx.wtxid());
+        assert_eq!(view.input_count(), tx.input.len());
+        assert_eq!(view.inputs().len(), tx.input.len());
+        for (input, txin) in view.inputs().zip(&tx.input) {
+            assert_eq!(input.previous_output, txin.previous_output);
+            assert_eq!(input.script_sig, &txin.script_sig[..]);
+            assert_eq!(input.sequence, txin.sequence);
+            assert_eq!(input.witness.len(), txin.witness.len());
+            assert_eq!(input.witness.to_vec(), txin.witness);
+        }
+        assert_eq!(view.output_count(), tx.output.len());
+        for (output, txout) in view.outputs().zip(&tx.output) {
+            assert_eq!(output.value, txout.value);
+            assert_eq!(output.script_pubkey, &txout.script_pubkey[..]);
+        }
+        assert_eq!(&view.decode().unwrap(), tx);
+    }
+
+    #[test]
+    fn block_view() {
+        let block = block();
+        let data = serialize(&block);
+        let view = BlockView::new(&data).unwrap();
+        assert_eq!(view.header(), &block.header);
+        assert_eq!(view.block_hash(), block.block_hash());
+        assert!(view.check_merkle_root());
+        assert_eq!(view.transactions().len(), 3);
+        assert!(!view.transactions()[0].has_witness());
+        assert!(view.transactions()[1].has_witness());
+        for (tx_view, tx) in view.transactions().iter().zip(&block.txdata) {
+            assert_eq!(tx_view.as_bytes(), &serialize(tx)[..]);
+            check_transaction(tx_view, tx);
+        }
+        assert_eq!(view.decode().unwrap(), block);
+
+        let mut bad_root = block.clone();
+        bad_root.header.merkle_root = Default::default();
+        assert!(!BlockView::new(&serialize(&bad_root)).unwrap().check_merkle_root());
+    }
+
+    #[test]
+    fn framing_errors() {
+        let data = serialize(&block());
+        for len in &[0, 79, 81, 200, data.len() - 1] {
+            match BlockView::new(&data[..*len]) {
+                Err(encode::Error::Io(_)) => {},
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert!(BlockView::new(&trailing).is_err());
+
+        let tx = serialize(&block().txdata[1]);
+        assert!(TransactionView::new(&tx).is_ok());
+        let mut bad_flag = tx.clone();
+        bad_flag[5] = 2;
+        match TransactionView::new(&bad_flag) {
+            Err(encode::Error::UnsupportedSegwitFlag(2)) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // Witness flag without witnesses
+        let mut superfluous = serialize(&block().txdata[2]);
+        superfluous.splice(4..4, vec![0, 1]);
+        let witness_start = superfluous.len() - 4;
+        superfluous.splice(witness_start..witness_start, vec![0]);
+        match TransactionView::new(&superfluous) {
+            Err(encode::Error::ParseFailed(_)) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(TransactionView::new(&superfluous).is_err(), encode::deserialize::<Transaction>(&superfluous).is_err());
+    }
+
+    #[test]
+    fn header_only() {
+        let header: BlockHeader = genesis_block(Network::Bitcoin).header;
+        let mut data = serialize(&header);
+        data.push(0);
+        let view = BlockView::new(&data).unwrap();
+        assert!(view.transactions().is_empty());
+    }
+}
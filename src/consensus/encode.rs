@@ -29,10 +29,10 @@
 //! big-endian decimals, etc.)
 //!
 
-use std::{fmt, error, io, mem, u32};
-use std::borrow::Cow;
-use std::io::{Cursor, Read, Write};
-use hashes::hex::ToHex;
+use core::{fmt, mem, u32};
+#[cfg(feature = "std")] use std::error;
+use io::{self, Cursor, Read, Write};
+use prelude::*;
 
 use hashes::{sha256d, Hash};
 use hash_types::{BlockHash, FilterHash, TxMerkleNode};
@@ -41,8 +41,9 @@ use util::endian;
 use util::psbt;
 
 use blockdata::transaction::{TxOut, Transaction, TxIn};
-use network::message_blockdata::Inventory;
-use network::address::Address;
+#[cfg(feature = "std")] use network::message_blockdata::Inventory;
+use util::bip152::ShortId;
+#[cfg(feature = "std")] use network::address::{Address, AddrV2Message};
 
 /// Encoding error
 #[derive(Debug)]
@@ -84,6 +85,15 @@ pub enum Error {
     UnrecognizedNetworkCommand(String),
     /// Invalid Inventory type
     UnknownInventoryType(u32),
+    /// A decoding limit was exceeded
+    LimitExceeded {
+        /// The exceeded limit
+        limit: Limit,
+        /// The requested value
+        requested: usize,
+        /// The maximum value
+        max: usize,
+    },
 }
 
 impl fmt::Display for Error {
@@ -96,7 +106,7 @@ impl fmt::Display for Error {
             Error::OversizedVectorAllocation { requested: ref r, max: ref m } => write!(f,
                 "allocation of oversized vector: requested {}, maximum {}", r, m),
             Error::InvalidChecksum { expected: ref e, actual: ref a } => write!(f,
-                "invalid checksum: expected {}, actual {}", e.to_hex(), a.to_hex()),
+                "invalid checksum: expected {}, actual {}", HexBytes(e), HexBytes(a)),
             Error::NonMinimalVarInt => write!(f, "non-minimal varint"),
             Error::UnknownNetworkMagic(ref m) => write!(f, "unknown network magic: {}", m),
             Error::ParseFailed(ref e) => write!(f, "parse failed: {}", e),
@@ -105,10 +115,13 @@ impl fmt::Display for Error {
             Error::UnrecognizedNetworkCommand(ref nwcmd) => write!(f,
                 "unrecognized network command: {}", nwcmd),
             Error::UnknownInventoryType(ref tp) => write!(f, "Unknown Inventory type: {}", tp),
+            Error::LimitExceeded { limit, requested, max } => write!(f,
+                "decoding limit exceeded: {} of {}, maximum {}", limit, requested, max),
         }
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
@@ -123,7 +136,8 @@ impl error::Error for Error {
             | Error::ParseFailed(..)
             | Error::UnsupportedSegwitFlag(..)
             | Error::UnrecognizedNetworkCommand(..)
-            | Error::UnknownInventoryType(..) => None,
+            | Error::UnknownInventoryType(..)
+            | Error::LimitExceeded { .. } => None,
         }
     }
 
@@ -158,7 +172,16 @@ pub fn serialize<T: Encodable + ?Sized>(data: &T) -> Vec<u8> {
 
 /// Encode an object into a hex-encoded string
 pub fn serialize_hex<T: Encodable + ?Sized>(data: &T) -> String {
-    serialize(data)[..].to_hex()
+    HexBytes(&serialize(data)).to_string()
+}
+
+/// Formats bytes as lowercase hex, without requiring an allocation
+pub(crate) struct HexBytes<'a>(pub(crate) &'a [u8]);
+
+impl<'a> fmt::Display for HexBytes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        ::hashes::hex::format_hex(self.0, f)
+    }
 }
 
 /// Deserialize an object from a vector, will error if said deserialization
@@ -186,6 +209,32 @@ pub fn deserialize_partial<T: Decodable>(
     Ok((rv, consumed))
 }
 
+/// Deserialize an object from a vector within decoding limits, will error if
+/// said deserialization doesn't consume the entire vector.
+pub fn deserialize_limited<T: Decodable>(data: &[u8], limits: DecodeLimits) -> Result<T, Error> {
+    let (rv, consumed) = deserialize_partial_limited(data, limits)?;
+
+    // Fail if data are not consumed entirely.
+    if consumed == data.len() {
+        Ok(rv)
+    } else {
+        Err(Error::ParseFailed("data not consumed entirely when explicitly deserializing"))
+    }
+}
+
+/// Deserialize an object from a vector within decoding limits, but will not
+/// report an error if said deserialization doesn't consume the entire vector.
+pub fn deserialize_partial_limited<T: Decodable>(
+    data: &[u8],
+    limits: DecodeLimits,
+) -> Result<(T, usize), Error> {
+    let mut decoder = Cursor::new(data);
+    let rv = Decodable::consensus_decode_limited(&mut decoder, &mut DecodeContext::new(limits))?;
+    let consumed = decoder.position() as usize;
+
+    Ok((rv, consumed))
+}
+
 
 /// Extensions of `Write` to encode data as per Bitcoin consensus
 pub trait WriteExt {
@@ -254,7 +303,7 @@ macro_rules! decoder_fn {
     ($name:ident, $val_type:ty, $readfn:ident, $byte_len: expr) => {
         #[inline]
         fn $name(&mut self) -> Result<$val_type, Error> {
-            assert_eq!(::std::mem::size_of::<$val_type>(), $byte_len); // size_of isn't a constfn in 1.22
+            assert_eq!(::core::mem::size_of::<$val_type>(), $byte_len); // size_of isn't a constfn in 1.22
             let mut val = [0; $byte_len];
             self.read_exact(&mut val[..]).map_err(Error::Io)?;
             Ok(endian::$readfn(&val))
@@ -327,12 +376,176 @@ pub trait Encodable {
     /// the underlying `Write` errors. Returns the number of bytes written on
     /// success
     fn consensus_encode<W: io::Write>(&self, e: W) -> Result<usize, Error>;
+
+    /// Returns the number of bytes `consensus_encode` writes, without
+    /// serializing the object where possible. The default implementation
+    /// encodes the object into a sink counting the written bytes. The length
+    /// is unspecified for objects failing to encode.
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        let mut counter = LenCounter(0);
+        let _ = self.consensus_encode(&mut counter);
+        counter.0
+    }
+}
+
+/// A sink counting the bytes written to it
+struct LenCounter(usize);
+
+impl io::Write for LenCounter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 /// Data which can be encoded in a consensus-consistent way
 pub trait Decodable: Sized {
     /// Decode an object with a well-defined format
     fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error>;
+
+    /// Decode an object with a well-defined format, accounting for the memory
+    /// it allocates and the nesting of its fields in `ctx`. The default
+    /// implementation ignores the limits and should only be kept by types of
+    /// bounded size.
+    #[inline]
+    fn consensus_decode_limited<D: io::Read>(d: D, _ctx: &mut DecodeContext) -> Result<Self, Error> {
+        Self::consensus_decode(d)
+    }
+}
+
+/// Decoding limits which can be exceeded
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Limit {
+    /// Total size of the decoded vectors
+    Allocation,
+    /// Number of elements of a vector
+    VecLength,
+    /// Size of a script
+    ScriptSize,
+    /// Number of witness elements of a transaction input
+    WitnessItems,
+    /// Nesting depth of decoded structures
+    Depth,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Limit::Allocation => "allocation size",
+            Limit::VecLength => "vector length",
+            Limit::ScriptSize => "script size",
+            Limit::WitnessItems => "witness items",
+            Limit::Depth => "nesting depth",
+        })
+    }
+}
+
+/// Limits on the resources used to decode untrusted data. Vectors are also
+/// always limited to [`MAX_VEC_SIZE`] bytes each, which is the only limit of
+/// the default value.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DecodeLimits {
+    /// Maximum total size, in bytes, of the decoded vectors
+    pub max_allocation: usize,
+    /// Maximum number of elements of a vector, including byte vectors
+    pub max_vec_len: usize,
+    /// Maximum size, in bytes, of a script
+    pub max_script_size: usize,
+    /// Maximum number of witness elements of a transaction input
+    pub max_witness_items: usize,
+    /// Maximum nesting depth of decoded structures
+    pub max_depth: usize,
+}
+
+impl DecodeLimits {
+    /// Returns the maximum value of a limit.
+    pub fn max(&self, limit: Limit) -> usize {
+        match limit {
+            Limit::Allocation => self.max_allocation,
+            Limit::VecLength => self.max_vec_len,
+            Limit::ScriptSize => self.max_script_size,
+            Limit::WitnessItems => self.max_witness_items,
+            Limit::Depth => self.max_depth,
+        }
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> DecodeLimits {
+        DecodeLimits {
+            max_allocation: usize::max_value(),
+            max_vec_len: usize::max_value(),
+            max_script_size: usize::max_value(),
+            max_witness_items: usize::max_value(),
+            max_depth: usize::max_value(),
+        }
+    }
+}
+
+/// Resources used so far by a decoding within limits
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct DecodeContext {
+    limits: DecodeLimits,
+    allocated: usize,
+    depth: usize,
+}
+
+impl DecodeContext {
+    /// Creates a context for a decoding within `limits`.
+    pub fn new(limits: DecodeLimits) -> DecodeContext {
+        DecodeContext {
+            limits: limits,
+            allocated: 0,
+            depth: 0,
+        }
+    }
+
+    /// Returns the limits of the decoding.
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    /// Returns the total size of the vectors allocated so far.
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// Checks that `value` does not exceed the maximum of `limit`.
+    pub fn check(&self, limit: Limit, value: usize) -> Result<(), Error> {
+        let max = self.limits.max(limit);
+        if value > max {
+            Err(Error::LimitExceeded { limit: limit, requested: value, max: max })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Accounts for the allocation of `size` bytes, which must be done before
+    /// allocating them.
+    pub fn allocate(&mut self, size: usize) -> Result<(), Error> {
+        let allocated = self.allocated.saturating_add(size);
+        self.check(Limit::Allocation, allocated)?;
+        self.allocated = allocated;
+        Ok(())
+    }
+
+    /// Decodes the fields of a structure, one nesting level deeper.
+    pub fn nested<T, F>(&mut self, decode: F) -> Result<T, Error>
+        where F: FnOnce(&mut DecodeContext) -> Result<T, Error>
+    {
+        self.check(Limit::Depth, self.depth + 1)?;
+        self.depth += 1;
+        let result = decode(self);
+        self.depth -= 1;
+        result
+    }
 }
 
 /// A variable-length unsigned integer
@@ -361,6 +574,11 @@ macro_rules! impl_int_encodable{
                 s.$meth_enc(self.to_le())?;
                 Ok(mem::size_of::<$ty>())
             }
+
+            #[inline]
+            fn encoded_len(&self) -> usize {
+                mem::size_of::<$ty>()
+            }
         }
     )
 }
@@ -414,6 +632,11 @@ impl Encodable for VarInt {
             },
         }
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        self.len()
+    }
 }
 
 impl Decodable for VarInt {
@@ -458,6 +681,11 @@ impl Encodable for bool {
         s.emit_bool(*self)?;
         Ok(1)
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        1
+    }
 }
 
 impl Decodable for bool {
@@ -476,6 +704,11 @@ impl Encodable for String {
         s.emit_slice(&b)?;
         Ok(vi_len + b.len())
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        len_with_size(self.len())
+    }
 }
 
 impl Decodable for String {
@@ -484,6 +717,12 @@ impl Decodable for String {
         String::from_utf8(Decodable::consensus_decode(d)?)
             .map_err(|_| self::Error::ParseFailed("String was not valid UTF8"))
     }
+
+    #[inline]
+    fn consensus_decode_limited<D: io::Read>(d: D, ctx: &mut DecodeContext) -> Result<String, Error> {
+        String::from_utf8(Decodable::consensus_decode_limited(d, ctx)?)
+            .map_err(|_| self::Error::ParseFailed("String was not valid UTF8"))
+    }
 }
 
 // Cow<'static, str>
@@ -495,14 +734,22 @@ impl Encodable for Cow<'static, str> {
         s.emit_slice(&b)?;
         Ok(vi_len + b.len())
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        len_with_size(self.len())
+    }
 }
 
 impl Decodable for Cow<'static, str> {
     #[inline]
     fn consensus_decode<D: io::Read>(d: D) -> Result<Cow<'static, str>, Error> {
-        String::from_utf8(Decodable::consensus_decode(d)?)
-            .map_err(|_| self::Error::ParseFailed("String was not valid UTF8"))
-            .map(Cow::Owned)
+        String::consensus_decode(d).map(Cow::Owned)
+    }
+
+    #[inline]
+    fn consensus_decode_limited<D: io::Read>(d: D, ctx: &mut DecodeContext) -> Result<Cow<'static, str>, Error> {
+        String::consensus_decode_limited(d, ctx).map(Cow::Owned)
     }
 }
 
@@ -519,6 +766,11 @@ macro_rules! impl_array {
                 s.emit_slice(&self[..])?;
                 Ok(self.len())
             }
+
+            #[inline]
+            fn encoded_len(&self) -> usize {
+                $size
+            }
         }
 
         impl Decodable for [u8; $size] {
@@ -534,6 +786,7 @@ macro_rules! impl_array {
 
 impl_array!(2);
 impl_array!(4);
+impl_array!(6);
 impl_array!(8);
 impl_array!(12);
 impl_array!(16);
@@ -557,6 +810,11 @@ impl Encodable for [u16; 8] {
         for c in self.iter() { c.consensus_encode(&mut s)?; }
         Ok(16)
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        16
+    }
 }
 
 // Vectors
@@ -575,22 +833,22 @@ macro_rules! impl_vec {
                 }
                 Ok(len)
             }
+
+            #[inline]
+            fn encoded_len(&self) -> usize {
+                self.iter().fold(VarInt(self.len() as u64).len(), |len, c| len + c.encoded_len())
+            }
         }
         impl Decodable for Vec<$type> {
             #[inline]
-            fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+            fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+                Self::consensus_decode_limited(d, &mut DecodeContext::default())
+            }
+
+            #[inline]
+            fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, Error> {
                 let len = VarInt::consensus_decode(&mut d)?.0;
-                let byte_size = (len as usize)
-                                    .checked_mul(mem::size_of::<$type>())
-                                    .ok_or(self::Error::ParseFailed("Invalid length"))?;
-                if byte_size > MAX_VEC_SIZE {
-                    return Err(self::Error::OversizedVectorAllocation { requested: byte_size, max: MAX_VEC_SIZE })
-                }
-                let mut ret = Vec::with_capacity(len as usize);
-                for _ in 0..len {
-                    ret.push(Decodable::consensus_decode(&mut d)?);
-                }
-                Ok(ret)
+                decode_vec(d, len, ctx)
             }
         }
     }
@@ -601,35 +859,79 @@ impl_vec!(TxMerkleNode);
 impl_vec!(Transaction);
 impl_vec!(TxOut);
 impl_vec!(TxIn);
-impl_vec!(Inventory);
+#[cfg(feature = "std")] impl_vec!(Inventory);
+impl_vec!(ShortId);
 impl_vec!(Vec<u8>);
-impl_vec!((u32, Address));
+#[cfg(feature = "std")] impl_vec!((u32, Address));
+#[cfg(feature = "std")] impl_vec!(AddrV2Message);
 impl_vec!(u64);
 
+/// Decodes the `len` elements of a vector within the limits of `ctx`
+pub(crate) fn decode_vec<T: Decodable, D: io::Read>(mut d: D, len: u64, ctx: &mut DecodeContext) -> Result<Vec<T>, Error> {
+    let byte_size = (len as usize)
+                        .checked_mul(mem::size_of::<T>())
+                        .ok_or(self::Error::ParseFailed("Invalid length"))?;
+    if byte_size > MAX_VEC_SIZE {
+        return Err(self::Error::OversizedVectorAllocation { requested: byte_size, max: MAX_VEC_SIZE })
+    }
+    ctx.check(Limit::VecLength, len as usize)?;
+    ctx.allocate(byte_size)?;
+    ctx.nested(|ctx| {
+        let mut ret = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            ret.push(Decodable::consensus_decode_limited(&mut d, ctx)?);
+        }
+        Ok(ret)
+    })
+}
+
+/// Decodes `len` bytes within the limits of `ctx`
+pub(crate) fn decode_bytes<D: io::Read>(mut d: D, len: usize, ctx: &mut DecodeContext) -> Result<Vec<u8>, Error> {
+    if len > MAX_VEC_SIZE {
+        return Err(self::Error::OversizedVectorAllocation { requested: len, max: MAX_VEC_SIZE })
+    }
+    ctx.check(Limit::VecLength, len)?;
+    ctx.allocate(len)?;
+    let mut ret = vec![0u8; len];
+    d.read_slice(&mut ret)?;
+    Ok(ret)
+}
+
 fn consensus_encode_with_size<S: io::Write>(data: &[u8], mut s: S) -> Result<usize, Error> {
     let vi_len = VarInt(data.len() as u64).consensus_encode(&mut s)?;
     s.emit_slice(&data)?;
     Ok(vi_len + data.len())
 }
 
+/// Length of the encoding of `len` bytes preceded by their size
+#[inline]
+pub(crate) fn len_with_size(len: usize) -> usize {
+    VarInt(len as u64).len() + len
+}
+
 
 impl Encodable for Vec<u8> {
     #[inline]
     fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
         consensus_encode_with_size(self, s)
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        len_with_size(self.len())
+    }
 }
 
 impl Decodable for Vec<u8> {
     #[inline]
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    #[inline]
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, Error> {
         let len = VarInt::consensus_decode(&mut d)?.0 as usize;
-        if len > MAX_VEC_SIZE {
-            return Err(self::Error::OversizedVectorAllocation { requested: len, max: MAX_VEC_SIZE })
-        }
-        let mut ret = vec![0u8; len];
-        d.read_slice(&mut ret)?;
-        Ok(ret)
+        decode_bytes(d, len, ctx)
     }
 }
 
@@ -638,6 +940,11 @@ impl Encodable for Box<[u8]> {
     fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
         consensus_encode_with_size(self, s)
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        len_with_size(self.len())
+    }
 }
 
 impl Decodable for Box<[u8]> {
@@ -645,6 +952,11 @@ impl Decodable for Box<[u8]> {
     fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
         <Vec<u8>>::consensus_decode(d).map(From::from)
     }
+
+    #[inline]
+    fn consensus_decode_limited<D: io::Read>(d: D, ctx: &mut DecodeContext) -> Result<Self, Error> {
+        <Vec<u8>>::consensus_decode_limited(d, ctx).map(From::from)
+    }
 }
 
 
@@ -663,11 +975,21 @@ impl Encodable for CheckedData {
         s.emit_slice(&self.0)?;
         Ok(8 + self.0.len())
     }
+
+    #[inline]
+    fn encoded_len(&self) -> usize {
+        8 + self.0.len()
+    }
 }
 
 impl Decodable for CheckedData {
     #[inline]
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    #[inline]
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, Error> {
         let len = u32::consensus_decode(&mut d)?;
         if len > MAX_VEC_SIZE as u32 {
             return Err(self::Error::OversizedVectorAllocation {
@@ -675,6 +997,7 @@ impl Decodable for CheckedData {
                 max: MAX_VEC_SIZE
             });
         }
+        ctx.allocate(len as usize)?;
         let checksum = <[u8; 4]>::consensus_decode(&mut d)?;
         let mut ret = vec![0u8; len as usize];
         d.read_slice(&mut ret)?;
@@ -705,6 +1028,13 @@ macro_rules! tuple_encode {
                 $(len += $x.consensus_encode(&mut s)?;)*
                 Ok(len)
             }
+
+            #[inline]
+            #[allow(non_snake_case)]
+            fn encoded_len(&self) -> usize {
+                let &($(ref $x),*) = self;
+                0 $(+ $x.encoded_len())*
+            }
         }
 
         impl<$($x: Decodable),*> Decodable for ($($x),*) {
@@ -713,6 +1043,12 @@ macro_rules! tuple_encode {
             fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
                 Ok(($({let $x = Decodable::consensus_decode(&mut d)?; $x }),*))
             }
+
+            #[inline]
+            #[allow(non_snake_case)]
+            fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, Error> {
+                Ok(($({let $x = Decodable::consensus_decode_limited(&mut d, ctx)?; $x }),*))
+            }
         }
     );
 }
@@ -725,15 +1061,17 @@ tuple_encode!(T0, T1, T2, T3, T4, T5, T6, T7);
 // Tests
 #[cfg(test)]
 mod tests {
-    use std::{io, mem, fmt};
+    use std::{mem, fmt};
     use std::mem::discriminant;
+    use io;
     use super::{deserialize, serialize, Error, CheckedData, VarInt};
+    use super::{deserialize_limited, DecodeContext, DecodeLimits, Limit};
     use super::{Transaction, BlockHash, FilterHash, TxMerkleNode, TxOut, TxIn};
     use consensus::{Encodable, deserialize_partial, Decodable};
     use util::endian::{u64_to_array_le, u32_to_array_le, u16_to_array_le};
     use secp256k1::rand::{thread_rng, Rng};
-    use network::message_blockdata::Inventory;
-    use network::Address;
+    #[cfg(feature = "std")] use network::message_blockdata::Inventory;
+    #[cfg(feature = "std")] use network::Address;
 
     #[test]
     fn serialize_int_test() {
@@ -808,7 +1146,7 @@ mod tests {
     }
 
     fn test_varint_len(varint: VarInt, expected: usize) {
-        let mut encoder = io::Cursor::new(vec![]);
+        let mut encoder = vec![];
         assert_eq!(varint.consensus_encode(&mut encoder).unwrap(), expected);
         assert_eq!(varint.len(), expected);
     }
@@ -939,9 +1277,9 @@ mod tests {
         test_len_is_max_vec::<Transaction>();
         test_len_is_max_vec::<TxOut>();
         test_len_is_max_vec::<TxIn>();
-        test_len_is_max_vec::<Inventory>();
+        #[cfg(feature = "std")] test_len_is_max_vec::<Inventory>();
         test_len_is_max_vec::<Vec<u8>>();
-        test_len_is_max_vec::<(u32, Address)>();
+        #[cfg(feature = "std")] test_len_is_max_vec::<(u32, Address)>();
         test_len_is_max_vec::<u64>();
     }
 
@@ -1005,5 +1343,116 @@ mod tests {
 
         }
     }
-}
 
+    fn limit_exceeded(err: Error) -> (Limit, usize, usize) {
+        match err {
+            Error::LimitExceeded { limit, requested, max } => (limit, requested, max),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn encoded_len_test() {
+        fn check<T: Encodable>(obj: T) {
+            assert_eq!(obj.encoded_len(), serialize(&obj).len());
+        }
+
+        check(true);
+        check(1u8);
+        check(-1i16);
+        check(0x12345678u32);
+        check(-1i64);
+        for &n in &[0, 0xfc, 0xfd, 0xffff, 0x10000, 0xffffffff, 0x100000000, u64::max_value()] {
+            check(VarInt(n));
+        }
+        check(String::from("bitcoin"));
+        check([7u8; 33]);
+        check([7u16; 8]);
+        check(vec![0u8; 0xfd]);
+        check(vec![vec![1u8; 3], vec![]]);
+        check(vec![1u64, 2, 3]);
+        check(CheckedData(vec![1, 2, 3]));
+        check((1u8, VarInt(0x10000), vec![0u8; 5], 2u32));
+        check(BlockHash::default());
+        check(TxOut::default());
+        #[cfg(feature = "std")] check(vec![Inventory::Error]);
+
+        // Types without a dedicated implementation are counted by encoding them
+        struct Encoded(Vec<u8>);
+        impl Encodable for Encoded {
+            fn consensus_encode<W: io::Write>(&self, mut e: W) -> Result<usize, Error> {
+                e.write_all(&self.0)?;
+                Ok(self.0.len())
+            }
+        }
+        assert_eq!(Encoded(vec![0; 42]).encoded_len(), 42);
+    }
+
+    #[test]
+    fn deserialize_limited_test() {
+        use hashes::hex::FromHex;
+
+        // Coinbase with a 54-byte scriptSig, outputs of 25 and 38 bytes and
+        // a witness of a single 32-byte element
+        let tx_bytes = Vec::<u8>::from_hex("010000000001010000000000000000000000000000000000000000000000000000000000000000ffffffff3603da1b0e00045503bd5704c7dd8a0d0ced13bb5785010800000000000a636b706f6f6c122f4e696e6a61506f6f6c2f5345475749542fffffffff02b4e5a212000000001976a914876fbb82ec05caa6af7a3b5e5a983aae6c6cc6d688ac0000000000000000266a24aa21a9edf91c46b49eb8a29089980f02ee6b57e7d63d33b18b4fddac2bcd7db2a39837040120000000000000000000000000000000000000000000000000000000000000000000000000").unwrap();
+        let tx: Transaction = deserialize(&tx_bytes).unwrap();
+
+        // The default limits decode as `deserialize` does
+        assert_eq!(deserialize_limited::<Transaction>(&tx_bytes, DecodeLimits::default()).unwrap(), tx);
+
+        let mut ctx = DecodeContext::default();
+        let decoded = Transaction::consensus_decode_limited(&tx_bytes[..], &mut ctx).unwrap();
+        assert_eq!(decoded, tx);
+        let allocated = ctx.allocated();
+        assert!(allocated > 0);
+        let limits = DecodeLimits { max_allocation: allocated, ..Default::default() };
+        assert_eq!(deserialize_limited::<Transaction>(&tx_bytes, limits).unwrap(), tx);
+
+        let limits = DecodeLimits { max_allocation: allocated - 1, ..Default::default() };
+        let err = deserialize_limited::<Transaction>(&tx_bytes, limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::Allocation, allocated, allocated - 1));
+
+        let limits = DecodeLimits { max_script_size: 53, ..Default::default() };
+        let err = deserialize_limited::<Transaction>(&tx_bytes, limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::ScriptSize, 54, 53));
+
+        let limits = DecodeLimits { max_witness_items: 0, ..Default::default() };
+        let err = deserialize_limited::<Transaction>(&tx_bytes, limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::WitnessItems, 1, 0));
+
+        let limits = DecodeLimits { max_vec_len: 54, ..Default::default() };
+        assert_eq!(deserialize_limited::<Transaction>(&tx_bytes, limits).unwrap(), tx);
+        let limits = DecodeLimits { max_vec_len: 53, ..Default::default() };
+        let err = deserialize_limited::<Transaction>(&tx_bytes, limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::VecLength, 54, 53));
+
+        let limits = DecodeLimits { max_depth: 1, ..Default::default() };
+        let err = deserialize_limited::<Transaction>(&tx_bytes, limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::Depth, 2, 1));
+    }
+
+    #[test]
+    fn deserialize_limited_vec_test() {
+        // Lengths are checked before anything is allocated
+        let limits = DecodeLimits { max_vec_len: 1000, ..Default::default() };
+        let err = deserialize_limited::<Vec<u64>>(&[0xfd, 0xe9, 0x03], limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::VecLength, 1001, 1000));
+
+        let limits = DecodeLimits { max_allocation: 16, ..Default::default() };
+        assert_eq!(deserialize_limited::<Vec<u64>>(&[2u8; 17], limits).unwrap(), vec![0x0202020202020202; 2]);
+        let err = deserialize_limited::<Vec<u64>>(&[3u8; 25], limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::Allocation, 24, 16));
+
+        // Allocations add up over the whole decoding
+        let outer = 2 * mem::size_of::<Vec<u8>>();
+        let limits = DecodeLimits { max_allocation: outer + 4, ..Default::default() };
+        assert_eq!(deserialize_limited::<Vec<Vec<u8>>>(&[2, 2, 0, 0, 2, 0, 0], limits).unwrap(), vec![vec![0; 2]; 2]);
+        let err = deserialize_limited::<Vec<Vec<u8>>>(&[2, 2, 0, 0, 3, 0, 0, 0], limits).unwrap_err();
+        assert_eq!(limit_exceeded(err), (Limit::Allocation, outer + 5, outer + 4));
+
+        assert_eq!(
+            Error::LimitExceeded { limit: Limit::VecLength, requested: 2, max: 1 }.to_string(),
+            "decoding limit exceeded: vector length of 2, maximum 1"
+        );
+    }
+}
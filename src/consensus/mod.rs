@@ -20,7 +20,16 @@
 
 pub mod encode;
 pub mod params;
+pub mod pow;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod signet;
+pub mod versionbits;
 
 pub use self::encode::{Encodable, Decodable, WriteExt, ReadExt};
 pub use self::encode::{serialize, deserialize, deserialize_partial};
+pub use self::encode::{deserialize_limited, deserialize_partial_limited, DecodeContext, DecodeLimits, Limit};
 pub use self::params::Params;
+
+#[cfg(feature = "derive")]
+pub use bitcoin_consensus_derive::{Encodable, Decodable};
@@ -17,6 +17,12 @@
 //! This module provides predefined set of parameters for different chains.
 //!
 
+use prelude::*;
+
+use blockdata::constants::COIN_VALUE;
+use blockdata::script::Script;
+use consensus::signet;
+use consensus::versionbits::{Deployment, Schedule};
 use network::constants::Network;
 use util::uint::Uint256;
 
@@ -49,11 +55,41 @@ const MAX_BITS_REGTEST: Uint256 = Uint256([
     0x7fffff0000000000u64,
 ]);
 
+/// Challenge of the default signet: a 1-of-2 multisig.
+const SIGNET_CHALLENGE: [u8; 71] = [
+    0x51, // OP_PUSHNUM_1
+    0x21, 0x03, 0xad, 0x5e, 0x0e, 0xda, 0xd1, 0x8c, 0xb1, 0xf0, 0xfc, 0x0d, 0x28, 0xa3, 0xd4, 0xf1, 0xf3,
+    0xe4, 0x45, 0x64, 0x03, 0x37, 0x48, 0x9a, 0xbb, 0x10, 0x40, 0x4f, 0x2d, 0x1e, 0x08, 0x6b, 0xe4, 0x30,
+    0x21, 0x03, 0x59, 0xef, 0x50, 0x21, 0x96, 0x4f, 0xe2, 0x2d, 0x6f, 0x8e, 0x05, 0xb2, 0x46, 0x3c, 0x95,
+    0x40, 0xce, 0x96, 0x88, 0x3f, 0xe3, 0xb2, 0x78, 0x76, 0x0f, 0x04, 0x8f, 0x51, 0x89, 0xf2, 0xe6, 0xc4,
+    0x52, 0xae, // OP_PUSHNUM_2 OP_CHECKMULTISIG
+];
+
+/// Version bits test deployment, never active outside of regtest.
+const TESTDUMMY: Deployment = Deployment {
+    name: "testdummy",
+    bit: 28,
+    schedule: Schedule::NeverActive,
+    min_activation_height: 0,
+};
+/// BIP341 taproot deployment on Mainnet and Testnet.
+const TAPROOT: Deployment = Deployment {
+    name: "taproot",
+    bit: 2,
+    schedule: Schedule::Time {
+        start_time: 1619222400, // Apr 24 2021
+        timeout: 1628640000,    // Aug 11 2021
+    },
+    min_activation_height: 0,
+};
+
 #[derive(Debug, Clone)]
 /// Parameters that influence chain consensus.
 pub struct Params {
     /// Network for which parameters are valid.
     pub network: Network,
+    /// Number of blocks after which the block subsidy halves.
+    pub subsidy_halving_interval: u32,
     /// Time when BIP16 becomes active.
     pub bip16_time: u32,
     /// Block height at which BIP34 becomes active.
@@ -62,12 +98,17 @@ pub struct Params {
     pub bip65_height: u32,
     /// Block height at which BIP66 becomes active.
     pub bip66_height: u32,
+    /// Block height at which segregated witness (BIP141, BIP143 and BIP147)
+    /// becomes active.
+    pub segwit_height: u32,
     /// Minimum blocks including miner confirmation of the total of 2016 blocks in a retargeting period,
     /// (nPowTargetTimespan / nPowTargetSpacing) which is also used for BIP9 deployments.
     /// Examples: 1916 for 95%, 1512 for testchains.
     pub rule_change_activation_threshold: u32,
     /// Number of blocks with the same set of rules.
     pub miner_confirmation_window: u32,
+    /// Soft fork deployments signaled with version bits.
+    pub deployments: Vec<Deployment>,
     /// Proof of work limit value. It contains the lowest possible difficulty.
     ///
     /// Note that this value differs from Bitcoin Core's powLimit field in that this value is
@@ -81,6 +122,8 @@ pub struct Params {
     pub pow_target_spacing: u64,
     /// Difficulty recalculation interval.
     pub pow_target_timespan: u64,
+    /// BIP325 challenge which blocks must satisfy, on signets only.
+    pub signet_challenge: Option<Script>,
     /// Determines whether minimal difficulty may be used for blocks or not.
     pub allow_min_difficulty_blocks: bool,
     /// Determines whether retargeting is disabled for this network or not.
@@ -93,65 +136,120 @@ impl Params {
         match network {
             Network::Bitcoin => Params {
                 network: Network::Bitcoin,
+                subsidy_halving_interval: 210000,
                 bip16_time: 1333238400,                 // Apr 1 2012
                 bip34_height: 227931, // 000000000000024b89b42a942fe0d9fea3bb44ab7bd1b19115dd6a759c0808b8
                 bip65_height: 388381, // 000000000000000004c2b624ed5d7756c508d90fd0da2c7c679febfa6c4735f0
                 bip66_height: 363725, // 00000000000000000379eaa19dce8c9b722d46ae6a57c2f1a988119488b50931
+                segwit_height: 481824, // 0000000000000000001c8018d9cb3b742ef25114f27563e3fc4a1902167f9893
                 rule_change_activation_threshold: 1916, // 95%
                 miner_confirmation_window: 2016,
+                deployments: vec![TESTDUMMY, Deployment { min_activation_height: 709632, ..TAPROOT }],
                 pow_limit: MAX_BITS_BITCOIN,
                 pow_target_spacing: 10 * 60,            // 10 minutes.
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
+                signet_challenge: None,
                 allow_min_difficulty_blocks: false,
                 no_pow_retargeting: false,
             },
             Network::Testnet => Params {
                 network: Network::Testnet,
+                subsidy_halving_interval: 210000,
                 bip16_time: 1333238400,                 // Apr 1 2012
                 bip34_height: 21111, // 0000000023b3a96d3484e5abb3755c413e7d41500f8e2a5c3f0dd01299cd8ef8
                 bip65_height: 581885, // 00000000007f6655f22f98e72ed80d8b06dc761d5da09df0fa1dc4be4f861eb6
                 bip66_height: 330776, // 000000002104c8c45e99a8853285a3b592602a3ccde2b832481da85e9e4ba182
+                segwit_height: 834624, // 00000000002b980fcd729daaa248fd9316a5200e9b367f4ff2c42453e84201ca
                 rule_change_activation_threshold: 1512, // 75%
                 miner_confirmation_window: 2016,
+                deployments: vec![TESTDUMMY, TAPROOT],
                 pow_limit: MAX_BITS_TESTNET,
                 pow_target_spacing: 10 * 60,            // 10 minutes.
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
+                signet_challenge: None,
                 allow_min_difficulty_blocks: true,
                 no_pow_retargeting: false,
             },
             Network::Signet => Params {
                 network: Network::Signet,
+                subsidy_halving_interval: 210000,
                 bip16_time: 1333238400,                 // Apr 1 2012
                 bip34_height: 1,
                 bip65_height: 1,
                 bip66_height: 1,
+                segwit_height: 1,
                 rule_change_activation_threshold: 1916, // 95%
                 miner_confirmation_window: 2016,
+                deployments: vec![TESTDUMMY, Deployment { schedule: Schedule::AlwaysActive, ..TAPROOT }],
                 pow_limit: MAX_BITS_SIGNET,
                 pow_target_spacing: 10 * 60,            // 10 minutes.
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
+                signet_challenge: Some(Script::from(SIGNET_CHALLENGE.to_vec())),
                 allow_min_difficulty_blocks: false,
                 no_pow_retargeting: false,
             },
             Network::Regtest => Params {
                 network: Network::Regtest,
+                subsidy_halving_interval: 150,
                 bip16_time: 1333238400,  // Apr 1 2012
                 bip34_height: 100000000, // not activated on regtest
                 bip65_height: 1351,
                 bip66_height: 1251,                    // used only in rpc tests
+                segwit_height: 0,                      // always active
                 rule_change_activation_threshold: 108, // 75%
                 miner_confirmation_window: 144,
+                deployments: vec![
+                    Deployment {
+                        schedule: Schedule::Time { start_time: 0, timeout: u32::max_value() },
+                        ..TESTDUMMY
+                    },
+                    Deployment { schedule: Schedule::AlwaysActive, ..TAPROOT },
+                ],
                 pow_limit: MAX_BITS_REGTEST,
                 pow_target_spacing: 10 * 60,            // 10 minutes.
                 pow_target_timespan: 14 * 24 * 60 * 60, // 2 weeks.
+                signet_challenge: None,
                 allow_min_difficulty_blocks: true,
                 no_pow_retargeting: true,
             },
         }
     }
 
+    /// Creates parameters for a custom signet with the given challenge.
+    pub fn custom_signet(challenge: Script) -> Self {
+        Params {
+            signet_challenge: Some(challenge),
+            ..Params::new(Network::Signet)
+        }
+    }
+
+    /// Returns the network magic, which is derived from the challenge on
+    /// signets.
+    pub fn magic(&self) -> u32 {
+        match self.signet_challenge {
+            Some(ref challenge) => signet::magic(challenge),
+            None => self.network.magic(),
+        }
+    }
+
+    /// Returns the version bits deployment with the given name, if any.
+    pub fn deployment(&self, name: &str) -> Option<&Deployment> {
+        self.deployments.iter().find(|d| d.name == name)
+    }
+
     /// Calculates the number of blocks between difficulty adjustments.
     pub fn difficulty_adjustment_interval(&self) -> u64 {
         self.pow_target_timespan / self.pow_target_spacing
     }
+
+    /// Calculates the block subsidy, in satoshis, for a block at the given
+    /// height according to the halving schedule.
+    pub fn block_subsidy(&self, height: u32) -> u64 {
+        let halvings = height / self.subsidy_halving_interval;
+        // Force block reward to zero when right shift is undefined.
+        if halvings >= 64 {
+            return 0;
+        }
+        (50 * COIN_VALUE) >> halvings
+    }
 }
@@ -0,0 +1,262 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Proof of work
+//!
+//! This module computes the proof of work required from a block, following
+//! Bitcoin Core's `GetNextWorkRequired`: difficulty is retargeted every
+//! difficulty adjustment interval, with the adjustment clamped to a factor of
+//! four. Networks allowing minimal difficulty blocks (testnet) accept the
+//! proof of work limit for blocks which come more than twice the target
+//! spacing after their predecessor, and networks without retargeting (regtest)
+//! keep the difficulty of the previous block.
+//!
+//! Previous headers are accessed through the [`HeaderLookup`] trait, so that
+//! header-only clients can verify proof of work with any header storage.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
+
+use blockdata::block::BlockHeader;
+use consensus::Params;
+use util::uint::Uint256;
+
+/// Access to the headers of the chain which is being extended
+pub trait HeaderLookup {
+    /// Returns the header at the given height, if known
+    fn header_at(&self, height: u32) -> Option<BlockHeader>;
+}
+
+/// A chain of headers starting with the genesis block
+impl HeaderLookup for [BlockHeader] {
+    fn header_at(&self, height: u32) -> Option<BlockHeader> {
+        self.get(height as usize).cloned()
+    }
+}
+
+impl HeaderLookup for Vec<BlockHeader> {
+    fn header_at(&self, height: u32) -> Option<BlockHeader> {
+        self[..].header_at(height)
+    }
+}
+
+/// Computes the median time past of the block at `height`, i.e. the median
+/// timestamp of this block and the ten blocks before it, or `None` if one of
+/// these headers cannot be found.
+pub fn median_time_past<L: HeaderLookup + ?Sized>(chain: &L, height: u32) -> Option<u32> {
+    let mut times = Vec::with_capacity(11);
+    for h in height.saturating_sub(10)..height + 1 {
+        times.push(chain.header_at(h)?.time);
+    }
+    times.sort();
+    Some(times[times.len() / 2])
+}
+
+/// Proof of work verification errors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The header at the given height, needed to compute the required
+    /// proof of work, was not found
+    MissingHeader(u32),
+    /// The header does not commit to the required target
+    BadDifficultyBits {
+        /// The required compact target
+        expected: u32,
+        /// The compact target of the header
+        actual: u32,
+    },
+    /// The header hash is above its target
+    BadProofOfWork,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::MissingHeader(height) => write!(f, "missing header at height {}", height),
+            Error::BadDifficultyBits { expected, actual } => write!(f,
+                "incorrect proof of work: bits {:#010x}, expected {:#010x}", actual, expected),
+            Error::BadProofOfWork => f.write_str("block hash above target"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+/// Computes the compact target required from a block at `last_height + 1`
+/// with timestamp `time`, given the chain ending at `last_height`.
+pub fn get_next_work_required<L>(params: &Params, chain: &L, last_height: u32, time: u32) -> Result<u32, Error>
+    where L: HeaderLookup + ?Sized
+{
+    let lookup = |height| chain.header_at(height).ok_or(Error::MissingHeader(height));
+    let pow_limit = BlockHeader::compact_target_from_u256(&params.pow_limit);
+    let interval = params.difficulty_adjustment_interval() as u32;
+    let last = lookup(last_height)?;
+
+    if (last_height + 1) % interval != 0 {
+        if !params.allow_min_difficulty_blocks {
+            return Ok(last.bits);
+        }
+        // A block more than twice the target spacing after its predecessor
+        // may have the minimal difficulty
+        if time as u64 > last.time as u64 + 2 * params.pow_target_spacing {
+            return Ok(pow_limit);
+        }
+        // Otherwise the difficulty of the last block which was not mined
+        // under the rule above applies
+        let mut height = last_height;
+        let mut header = last;
+        while height > 0 && height % interval != 0 && header.bits == pow_limit {
+            height -= 1;
+            header = lookup(height)?;
+        }
+        return Ok(header.bits);
+    }
+
+    let first = lookup(last_height + 1 - interval)?;
+    Ok(calculate_next_work_required(params, &last, first.time))
+}
+
+/// Computes the compact target of a retarget following the block `last`,
+/// which is the final block of a difficulty adjustment interval starting
+/// with a block with timestamp `first_time`.
+pub fn calculate_next_work_required(params: &Params, last: &BlockHeader, first_time: u32) -> u32 {
+    if params.no_pow_retargeting {
+        return last.bits;
+    }
+
+    let timespan = params.pow_target_timespan as i64;
+    let mut actual_timespan = last.time as i64 - first_time as i64;
+    if actual_timespan < timespan / 4 {
+        actual_timespan = timespan / 4;
+    }
+    if actual_timespan > timespan * 4 {
+        actual_timespan = timespan * 4;
+    }
+
+    let target = last.target().mul_u32(actual_timespan as u32) / Uint256::from_u64(timespan as u64).unwrap();
+    let target = if target > params.pow_limit { params.pow_limit } else { target };
+    BlockHeader::compact_target_from_u256(&target)
+}
+
+/// Checks that the header at `height` commits to the required target and
+/// that its hash satisfies it.
+pub fn check_proof_of_work<L>(params: &Params, chain: &L, height: u32, header: &BlockHeader) -> Result<(), Error>
+    where L: HeaderLookup + ?Sized
+{
+    let expected = if height == 0 {
+        BlockHeader::compact_target_from_u256(&params.pow_limit)
+    } else {
+        get_next_work_required(params, chain, height - 1, header.time)?
+    };
+    if header.bits != expected {
+        return Err(Error::BadDifficultyBits { expected: expected, actual: header.bits });
+    }
+    header.validate_pow(&header.target()).map_err(|_| Error::BadProofOfWork)
+}
+
+#[cfg(test)]
+mod tests {
+    use blockdata::block::BlockHeader;
+    use blockdata::constants::genesis_block;
+    use consensus::Params;
+    use network::constants::Network;
+
+    use super::{calculate_next_work_required, check_proof_of_work, get_next_work_required, median_time_past, Error};
+
+    fn header(time: u32, bits: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_blockhash: Default::default(),
+            merkle_root: Default::default(),
+            time: time,
+            bits: bits,
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn calculate_retarget() {
+        // Test vectors from Bitcoin Core's pow_tests.cpp
+        let params = Params::new(Network::Bitcoin);
+        assert_eq!(calculate_next_work_required(&params, &header(1262152739, 0x1d00ffff), 1261130161), 0x1d00d86a);
+        // Clamped to the proof of work limit
+        assert_eq!(calculate_next_work_required(&params, &header(1233061996, 0x1d00ffff), 1231006505), 0x1d00ffff);
+        // Clamped to a fourth of the target timespan
+        assert_eq!(calculate_next_work_required(&params, &header(1279297671, 0x1c05a3f4), 1279008237), 0x1c0168fd);
+        // Clamped to four times the target timespan
+        assert_eq!(calculate_next_work_required(&params, &header(1269211443, 0x1c387f6f), 1263163443), 0x1d00e1fd);
+
+        let regtest = Params::new(Network::Regtest);
+        assert_eq!(calculate_next_work_required(&regtest, &header(1269211443, 0x1c387f6f), 1263163443), 0x1c387f6f);
+    }
+
+    #[test]
+    fn next_work_required() {
+        let params = Params::new(Network::Bitcoin);
+        let mut chain: Vec<_> = (0..2016).map(|i| header(1_000_000 + i * 300, 0x1c05a3f4)).collect();
+        assert_eq!(get_next_work_required(&params, &chain, 2014, 0), Ok(0x1c05a3f4));
+        assert_eq!(get_next_work_required(&params, &chain, 2015, 0),
+                   Ok(calculate_next_work_required(&params, &chain[2015], chain[0].time)));
+        assert_eq!(get_next_work_required(&params, &chain[..100], 2015, 0), Err(Error::MissingHeader(2015)));
+
+        // Testnet minimal difficulty blocks and walk-back
+        let testnet = Params::new(Network::Testnet);
+        let last_time = chain[2013].time;
+        assert_eq!(get_next_work_required(&testnet, &chain, 2013, last_time + 1201), Ok(0x1d00ffff));
+        assert_eq!(get_next_work_required(&testnet, &chain, 2013, last_time + 1200), Ok(0x1c05a3f4));
+        chain[2012].bits = 0x1d00ffff;
+        chain[2013].bits = 0x1d00ffff;
+        assert_eq!(get_next_work_required(&testnet, &chain, 2013, last_time), Ok(0x1c05a3f4));
+        // The walk-back stops at the start of the interval
+        for header in &mut chain {
+            header.bits = 0x1d00ffff;
+        }
+        chain[0].bits = 0x1c05a3f4;
+        assert_eq!(get_next_work_required(&testnet, &chain, 2013, last_time), Ok(0x1c05a3f4));
+    }
+
+    #[test]
+    fn median_time() {
+        let times = [10, 30, 20, 50, 40, 60, 70, 0, 90, 80, 100, 110];
+        let chain: Vec<_> = times.iter().map(|&t| header(t, 0x1d00ffff)).collect();
+        assert_eq!(median_time_past(&chain, 0), Some(10));
+        assert_eq!(median_time_past(&chain, 1), Some(30));
+        assert_eq!(median_time_past(&chain, 2), Some(20));
+        assert_eq!(median_time_past(&chain, 10), Some(50));
+        assert_eq!(median_time_past(&chain, 11), Some(60));
+        assert_eq!(median_time_past(&chain, 12), None);
+    }
+
+    #[test]
+    fn proof_of_work() {
+        let params = Params::new(Network::Bitcoin);
+        let genesis = genesis_block(Network::Bitcoin).header;
+        assert_eq!(check_proof_of_work(&params, &[][..], 0, &genesis), Ok(()));
+
+        let mut bad = genesis;
+        bad.nonce += 1;
+        assert_eq!(check_proof_of_work(&params, &[][..], 0, &bad), Err(Error::BadProofOfWork));
+        bad.bits = 0x1c05a3f4;
+        assert_eq!(check_proof_of_work(&params, &[genesis][..], 1, &bad),
+                   Err(Error::BadDifficultyBits { expected: 0x1d00ffff, actual: 0x1c05a3f4 }));
+    }
+}
@@ -0,0 +1,325 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+// methods are implementation of a standardized serde-specific signature
+#![allow(missing_docs)]
+
+//! Serde Consensus Encoding
+//!
+//! This module adds serde serialization and deserialization of objects, such
+//! as transactions, blocks and PSBTs, as their consensus encoding instead of
+//! as nested structures. Human-readable formats get a string, in hex or in
+//! base64, while binary formats get the raw bytes.
+//! The provided modules can be used as follows:
+//!
+//! ```rust,ignore
+//! use serde::{Serialize, Deserialize};
+//! use bitcoin::{Block, Transaction};
+//! use bitcoin::util::psbt::PartiallySignedTransaction;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! pub struct HasConsensusEncoding {
+//!     #[serde(with = "bitcoin::consensus::serde::hex")]
+//!     pub tx: Transaction,
+//!     #[serde(default, with = "bitcoin::consensus::serde::hex::opt")]
+//!     pub block: Option<Block>,
+//!     #[serde(with = "bitcoin::consensus::serde::base64")]
+//!     pub psbt: PartiallySignedTransaction,
+//! }
+//! ```
+
+use core::fmt;
+use core::marker::PhantomData;
+use prelude::*;
+
+use serde::{de, Deserializer, Serializer};
+
+use consensus::encode::{self, Decodable, Encodable};
+
+/// Serializes `obj` as its consensus encoding, turned into a string by
+/// `to_str` for human-readable formats.
+fn serialize_with<T, S>(obj: &T, s: S, to_str: fn(&[u8]) -> String) -> Result<S::Ok, S::Error>
+    where T: Encodable, S: Serializer
+{
+    let bytes = encode::serialize(obj);
+    if s.is_human_readable() {
+        s.serialize_str(&to_str(&bytes))
+    } else {
+        s.serialize_bytes(&bytes)
+    }
+}
+
+/// Deserializes an object from its consensus encoding, turned back from a
+/// string by `from_str` for human-readable formats.
+fn deserialize_with<'de, T, D>(
+    d: D,
+    from_str: fn(&str) -> Result<Vec<u8>, String>,
+    expecting: &'static str,
+) -> Result<T, D::Error>
+    where T: Decodable, D: Deserializer<'de>
+{
+    struct Visitor<T> {
+        from_str: fn(&str) -> Result<Vec<u8>, String>,
+        expecting: &'static str,
+        phantom: PhantomData<T>,
+    }
+
+    impl<'de, T: Decodable> de::Visitor<'de> for Visitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(self.expecting)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+            let bytes = (self.from_str)(v).map_err(E::custom)?;
+            self.visit_bytes(&bytes)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<T, E> {
+            encode::deserialize(v).map_err(E::custom)
+        }
+    }
+
+    let visitor = Visitor {
+        from_str: from_str,
+        expecting: expecting,
+        phantom: PhantomData,
+    };
+    if d.is_human_readable() {
+        d.deserialize_str(visitor)
+    } else {
+        d.deserialize_bytes(visitor)
+    }
+}
+
+/// Deserializes an optional object with [deserialize_with], from `None` if
+/// the value is missing.
+fn deserialize_opt_with<'de, T, D>(
+    d: D,
+    from_str: fn(&str) -> Result<Vec<u8>, String>,
+    expecting: &'static str,
+) -> Result<Option<T>, D::Error>
+    where T: Decodable, D: Deserializer<'de>
+{
+    struct Visitor<T> {
+        from_str: fn(&str) -> Result<Vec<u8>, String>,
+        expecting: &'static str,
+        phantom: PhantomData<T>,
+    }
+
+    impl<'de, T: Decodable> de::Visitor<'de> for Visitor<T> {
+        type Value = Option<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an optional {}", self.expecting)
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Option<T>, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Option<T>, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Option<T>, D::Error> {
+            Ok(Some(deserialize_with(d, self.from_str, self.expecting)?))
+        }
+    }
+
+    d.deserialize_option(Visitor {
+        from_str: from_str,
+        expecting: expecting,
+        phantom: PhantomData,
+    })
+}
+
+pub mod hex {
+    //! Serialize and deserialize objects as their consensus encoding in hex.
+    //! Use with `#[serde(with = "consensus::serde::hex")]`.
+
+    use prelude::*;
+
+    use serde::{Deserializer, Serializer};
+
+    use consensus::encode::{Decodable, Encodable};
+    use hashes::hex::{FromHex, ToHex};
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.to_hex()
+    }
+
+    fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+        Vec::from_hex(s).map_err(|e| e.to_string())
+    }
+
+    pub fn serialize<T: Encodable, S: Serializer>(obj: &T, s: S) -> Result<S::Ok, S::Error> {
+        super::serialize_with(obj, s, to_hex)
+    }
+
+    const EXPECTING: &'static str = "a consensus encoding in hex";
+
+    pub fn deserialize<'d, T: Decodable, D: Deserializer<'d>>(d: D) -> Result<T, D::Error> {
+        super::deserialize_with(d, from_hex, EXPECTING)
+    }
+
+    pub mod opt {
+        //! Serialize and deserialize [Option] of objects as their consensus
+        //! encoding in hex.
+        //! Use with `#[serde(default, with = "consensus::serde::hex::opt")]`.
+
+        use serde::{Deserializer, Serializer};
+
+        use consensus::encode::{Decodable, Encodable};
+
+        pub fn serialize<T: Encodable, S: Serializer>(obj: &Option<T>, s: S) -> Result<S::Ok, S::Error> {
+            match *obj {
+                Some(ref obj) => super::serialize(obj, s),
+                None => s.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'d, T: Decodable, D: Deserializer<'d>>(d: D) -> Result<Option<T>, D::Error> {
+            super::super::deserialize_opt_with(d, super::from_hex, super::EXPECTING)
+        }
+    }
+}
+
+#[cfg(feature = "base64")]
+pub mod base64 {
+    //! Serialize and deserialize objects as their consensus encoding in
+    //! base64, which is how PSBTs are usually exchanged.
+    //! Use with `#[serde(with = "consensus::serde::base64")]`.
+
+    use prelude::*;
+
+    use serde::{Deserializer, Serializer};
+
+    use consensus::encode::{Decodable, Encodable};
+
+    fn to_base64(bytes: &[u8]) -> String {
+        ::base64::encode(bytes)
+    }
+
+    fn from_base64(s: &str) -> Result<Vec<u8>, String> {
+        ::base64::decode(s).map_err(|e| e.to_string())
+    }
+
+    pub fn serialize<T: Encodable, S: Serializer>(obj: &T, s: S) -> Result<S::Ok, S::Error> {
+        super::serialize_with(obj, s, to_base64)
+    }
+
+    const EXPECTING: &'static str = "a consensus encoding in base64";
+
+    pub fn deserialize<'d, T: Decodable, D: Deserializer<'d>>(d: D) -> Result<T, D::Error> {
+        super::deserialize_with(d, from_base64, EXPECTING)
+    }
+
+    pub mod opt {
+        //! Serialize and deserialize [Option] of objects as their consensus
+        //! encoding in base64.
+        //! Use with `#[serde(default, with = "consensus::serde::base64::opt")]`.
+
+        use serde::{Deserializer, Serializer};
+
+        use consensus::encode::{Decodable, Encodable};
+
+        pub fn serialize<T: Encodable, S: Serializer>(obj: &Option<T>, s: S) -> Result<S::Ok, S::Error> {
+            match *obj {
+                Some(ref obj) => super::serialize(obj, s),
+                None => s.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'d, T: Decodable, D: Deserializer<'d>>(d: D) -> Result<Option<T>, D::Error> {
+            super::super::deserialize_opt_with(d, super::from_base64, super::EXPECTING)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use serde_test::{assert_tokens, Configure, Token};
+
+    use blockdata::block::Block;
+    use blockdata::constants::genesis_block;
+    use blockdata::script::Script;
+    use blockdata::transaction::{Transaction, TxOut};
+    use consensus::encode::{deserialize, serialize};
+    use hashes::hex::{FromHex, ToHex};
+    use network::constants::Network;
+
+    static TX_HEX: &'static str = "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000";
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Hex {
+        #[serde(with = "::consensus::serde::hex")]
+        tx: Transaction,
+        #[serde(default, with = "::consensus::serde::hex::opt")]
+        block: Option<Block>,
+    }
+
+    #[test]
+    fn serde_hex_test() {
+        let tx: Transaction = deserialize(&Vec::from_hex(TX_HEX).unwrap()).unwrap();
+        let block = genesis_block(Network::Bitcoin);
+
+        let obj = Hex { tx: tx.clone(), block: None };
+        let json = serde_json::to_value(&obj).unwrap();
+        assert_eq!(json["tx"], TX_HEX);
+        assert!(json["block"].is_null());
+        assert_eq!(serde_json::from_value::<Hex>(json).unwrap(), obj);
+        assert_eq!(serde_json::from_str::<Hex>(&format!("{{\"tx\":\"{}\"}}", TX_HEX)).unwrap(), obj);
+
+        let obj = Hex { tx: tx, block: Some(block.clone()) };
+        let json = serde_json::to_value(&obj).unwrap();
+        assert_eq!(json["block"], serialize(&block).to_hex());
+        assert_eq!(serde_json::from_value::<Hex>(json).unwrap(), obj);
+
+        assert!(serde_json::from_str::<Hex>("{\"tx\":\"0100\"}").is_err());
+        assert!(serde_json::from_str::<Hex>("{\"tx\":\"zz\"}").is_err());
+    }
+
+    #[test]
+    fn serde_hex_tokens_test() {
+        #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+        struct Out(#[serde(with = "::consensus::serde::hex")] TxOut);
+
+        let out = Out(TxOut { value: 1, script_pubkey: Script::new() });
+        assert_tokens(&out.clone().readable(), &[Token::NewtypeStruct { name: "Out" }, Token::Str("010000000000000000")]);
+        assert_tokens(&out.compact(), &[Token::NewtypeStruct { name: "Out" }, Token::Bytes(&[1, 0, 0, 0, 0, 0, 0, 0, 0])]);
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn serde_base64_test() {
+        use util::psbt::PartiallySignedTransaction;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Psbt(#[serde(with = "::consensus::serde::base64")] PartiallySignedTransaction);
+
+        // Test vector of BIP174
+        let bytes = Vec::from_hex("70736274ff0100a00200000002ab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be40000000000feffffffab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be40100000000feffffff02603bea0b000000001976a914768a40bbd740cbe81d988e71de2a4d5c71396b1d88ac8e240000000000001976a9146f4620b553fa095e721b9ee0efe9fa039cca459788ac000000000001076a47304402204759661797c01b036b25928948686218347d89864b719e1f7fcf57d1e511658702205309eabf56aa4d8891ffd111fdf1336f3a29da866d7f8486d75546ceedaf93190121035cdc61fc7ba971c0b501a646a2a83b102cb43881217ca682dc86e2d73fa882920001012000e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787010416001485d13537f2e265405a34dbafa9e3dda01fb82308000000").unwrap();
+        let psbt = Psbt(deserialize(&bytes).unwrap());
+        let json = serde_json::to_value(&psbt).unwrap();
+        assert_eq!(json, ::base64::encode(&bytes));
+        assert!(json.as_str().unwrap().starts_with("cHNidP8"));
+        assert_eq!(serde_json::from_value::<Psbt>(json).unwrap(), psbt);
+
+        assert!(serde_json::from_str::<Psbt>("\"cHNidP8=\"").is_err());
+        assert!(serde_json::from_str::<Psbt>("\"not base64\"").is_err());
+    }
+}
@@ -0,0 +1,382 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Signet
+//!
+//! Block solutions of signets, as defined by BIP325
+//! (https://github.com/bitcoin/bips/blob/master/bip-0325.mediawiki).
+//!
+//! Signet blocks must additionally satisfy a challenge script. The solution,
+//! a scriptSig and a witness, is stored in a push of the coinbase witness
+//! commitment output starting with [`SIGNET_HEADER`]. It is checked by
+//! spending a virtual `to_spend` transaction, whose output script is the
+//! challenge, with a virtual `to_sign` transaction; `to_spend` commits to the
+//! block header and to the transactions without the solution.
+//!
+
+use core::fmt;
+use io;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
+
+use hashes::Hash;
+
+use blockdata::block::Block;
+#[cfg(feature="bitcoinconsensus")] use blockdata::constants::genesis_block;
+use blockdata::opcodes;
+use blockdata::script::{Builder, Instruction, Script};
+#[cfg(feature="bitcoinconsensus")] use blockdata::script;
+use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use consensus::encode::{self, Decodable, Encodable};
+#[cfg(feature="bitcoinconsensus")] use consensus::Params;
+use hash_types::TxMerkleNode;
+use util::endian;
+use util::hash::bitcoin_merkle_root;
+
+/// Header of the push of the coinbase witness commitment output containing
+/// the signet solution
+pub const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+/// Signet block solution errors
+#[derive(Debug)]
+pub enum Error {
+    /// The block has no coinbase witness commitment output
+    NoWitnessCommitment,
+    /// The solution is not a serialized scriptSig and witness
+    InvalidSolution,
+    #[cfg(feature="bitcoinconsensus")]
+    /// The solution does not satisfy the challenge
+    Script(script::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoWitnessCommitment => f.write_str("signet: missing witness commitment"),
+            Error::InvalidSolution => f.write_str("signet: block solution parse failure"),
+            #[cfg(feature="bitcoinconsensus")]
+            Error::Script(ref e) => write!(f, "signet: invalid block solution: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::NoWitnessCommitment | Error::InvalidSolution => None,
+            #[cfg(feature="bitcoinconsensus")]
+            Error::Script(ref e) => Some(e),
+        }
+    }
+
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+/// Computes the network magic of the signet with the given challenge: the
+/// first four bytes of the double SHA256 of the serialized challenge.
+pub fn magic(challenge: &Script) -> u32 {
+    let hash = ::hashes::sha256d::Hash::hash(&encode::serialize(challenge));
+    endian::slice_to_u32_le(&hash[0..4])
+}
+
+/// Splits the signet solution off a witness commitment output script, as
+/// Bitcoin Core's `FetchAndClearCommitmentSection` does: returns the script
+/// with the solution removed from the first push starting with
+/// [`SIGNET_HEADER`], and the solution, if there is one.
+fn split_solution(script: &Script) -> Option<(Script, Vec<u8>)> {
+    let mut replacement = Builder::new();
+    let mut solution = None;
+    for instruction in script.instructions() {
+        replacement = match instruction {
+            Ok(Instruction::PushBytes(data)) => {
+                if solution.is_none() && data.len() > SIGNET_HEADER.len() && data.starts_with(&SIGNET_HEADER) {
+                    solution = Some(data[SIGNET_HEADER.len()..].to_vec());
+                    replacement.push_slice(&SIGNET_HEADER)
+                } else {
+                    replacement.push_slice(data)
+                }
+            },
+            Ok(Instruction::Op(op)) => replacement.push_opcode(op),
+            Err(_) => break,
+        };
+    }
+    solution.map(|solution| (replacement.into_script(), solution))
+}
+
+/// The virtual transactions of a signet block solution
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SignetTxs {
+    /// Transaction committing to the block, with the challenge as its output
+    pub to_spend: Transaction,
+    /// Transaction spending `to_spend` with the block solution
+    pub to_sign: Transaction,
+}
+
+impl SignetTxs {
+    /// Creates the virtual transactions of a block for the given challenge.
+    /// Blocks without a solution produce an empty `to_sign` input, which
+    /// satisfies trivial challenges such as `OP_TRUE`.
+    pub fn new(block: &Block, challenge: &Script) -> Result<SignetTxs, Error> {
+        let mut to_spend = Transaction {
+            version: 0,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new().push_opcode(opcodes::OP_FALSE).into_script(),
+                sequence: 0,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: challenge.clone(),
+            }],
+        };
+        let mut to_sign = Transaction {
+            version: 0,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::new(),
+                sequence: 0,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script(),
+            }],
+        };
+
+        let mut coinbase = match block.txdata.first() {
+            Some(coinbase) => coinbase.clone(),
+            None => return Err(Error::NoWitnessCommitment),
+        };
+        let commitment = coinbase.output.iter_mut().rev().find(|o| {
+            o.script_pubkey.len() >= 38 && o.script_pubkey[0..6] == [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed]
+        }).ok_or(Error::NoWitnessCommitment)?;
+
+        if let Some((cleared, solution)) = split_solution(&commitment.script_pubkey) {
+            commitment.script_pubkey = cleared;
+            let mut cursor = io::Cursor::new(&solution);
+            let script_sig = Script::consensus_decode(&mut cursor).map_err(|_| Error::InvalidSolution)?;
+            let witness: Vec<Vec<u8>> = Decodable::consensus_decode(&mut cursor).map_err(|_| Error::InvalidSolution)?;
+            if cursor.position() as usize != solution.len() {
+                return Err(Error::InvalidSolution);
+            }
+            to_sign.input[0].script_sig = script_sig;
+            to_sign.input[0].witness = witness;
+        }
+
+        let txids = Some(coinbase.txid()).into_iter()
+            .chain(block.txdata[1..].iter().map(Transaction::txid))
+            .map(|txid| txid.as_hash());
+        let merkle_root: TxMerkleNode = bitcoin_merkle_root(txids.collect::<Vec<_>>().into_iter()).into();
+
+        let mut block_data = vec![];
+        block.header.version.consensus_encode(&mut block_data).unwrap();
+        block.header.prev_blockhash.consensus_encode(&mut block_data).unwrap();
+        merkle_root.consensus_encode(&mut block_data).unwrap();
+        block.header.time.consensus_encode(&mut block_data).unwrap();
+        to_spend.input[0].script_sig = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_slice(&block_data)
+            .into_script();
+        to_sign.input[0].previous_output = OutPoint::new(to_spend.txid(), 0);
+
+        Ok(SignetTxs {
+            to_spend: to_spend,
+            to_sign: to_sign,
+        })
+    }
+
+    #[cfg(feature="bitcoinconsensus")]
+    /// Verifies that the block solution satisfies the challenge, with the
+    /// P2SH, DERSIG, NULLDUMMY and witness script verification flags.
+    pub fn verify(&self) -> Result<(), Error> {
+        let flags = ::bitcoinconsensus::VERIFY_P2SH | ::bitcoinconsensus::VERIFY_DERSIG |
+            ::bitcoinconsensus::VERIFY_NULLDUMMY | ::bitcoinconsensus::VERIFY_WITNESS;
+        let to_sign = encode::serialize(&self.to_sign);
+        ::bitcoinconsensus::verify_with_flags(
+            &self.to_spend.output[0].script_pubkey[..], 0, &to_sign, 0, flags,
+        ).map_err(|e| Error::Script(script::Error::from(e)))
+    }
+}
+
+#[cfg(feature="bitcoinconsensus")]
+/// Checks the solution of a signet block against the challenge of `params`,
+/// as Bitcoin Core's `CheckSignetBlockSolution` does. The genesis block and
+/// blocks of networks without a challenge need no solution.
+pub fn check_block_solution(block: &Block, params: &Params) -> Result<(), Error> {
+    let challenge = match params.signet_challenge {
+        Some(ref challenge) => challenge,
+        None => return Ok(()),
+    };
+    if block.header == genesis_block(params.network).header {
+        return Ok(());
+    }
+    SignetTxs::new(block, challenge)?.verify()
+}
+
+#[cfg(test)]
+mod tests {
+    use hashes::hex::FromHex;
+
+    use blockdata::block::{Block, BlockHeader};
+    use blockdata::coinbase::CoinbaseBuilder;
+    use blockdata::opcodes;
+    use blockdata::script::{Builder, Script};
+    use blockdata::transaction::OutPoint;
+    use consensus::encode::serialize;
+    use consensus::Params;
+    use network::constants::Network;
+
+    use super::{magic, split_solution, Error, SignetTxs, SIGNET_HEADER};
+
+    fn block(commitment: Script) -> Block {
+        let mut coinbase = CoinbaseBuilder::new(1).into_transaction(&[]);
+        coinbase.output.push(::blockdata::transaction::TxOut { value: 0, script_pubkey: commitment });
+        Block {
+            header: BlockHeader {
+                version: 0x20000000,
+                prev_blockhash: Default::default(),
+                merkle_root: Default::default(),
+                time: 1600000000,
+                bits: 0x1e0377ae,
+                nonce: 0,
+            },
+            txdata: vec![coinbase],
+        }
+    }
+
+    fn commitment(solution: Option<&[u8]>) -> Script {
+        let builder = Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_slice(&Vec::from_hex("aa21a9ed0000000000000000000000000000000000000000000000000000000000000000").unwrap());
+        match solution {
+            Some(solution) => {
+                let mut data = SIGNET_HEADER.to_vec();
+                data.extend_from_slice(solution);
+                builder.push_slice(&data).into_script()
+            },
+            None => builder.into_script(),
+        }
+    }
+
+    #[test]
+    fn signet_magic() {
+        let params = Params::new(Network::Signet);
+        assert_eq!(magic(params.signet_challenge.as_ref().unwrap()), Network::Signet.magic());
+        assert_eq!(params.magic(), Network::Signet.magic());
+        assert_eq!(Params::new(Network::Bitcoin).magic(), Network::Bitcoin.magic());
+
+        let custom = Params::custom_signet(Builder::new().push_opcode(opcodes::OP_TRUE).into_script());
+        assert_eq!(custom.network, Network::Signet);
+        assert!(custom.magic() != Network::Signet.magic());
+    }
+
+    #[test]
+    fn solution_extraction() {
+        // Empty scriptSig and a witness with a single element
+        let solution = [0x00, 0x01, 0x02, 0xab, 0xcd];
+        let script = commitment(Some(&solution));
+        let (cleared, extracted) = split_solution(&script).unwrap();
+        assert_eq!(extracted, solution.to_vec());
+        assert_eq!(&cleared[..], &commitment(Some(&[]))[..]);
+        assert_eq!(split_solution(&commitment(None)), None);
+        assert_eq!(split_solution(&cleared), None);
+
+        let txs = SignetTxs::new(&block(script), &Script::new()).unwrap();
+        assert_eq!(txs.to_sign.input[0].script_sig, Script::new());
+        assert_eq!(txs.to_sign.input[0].witness, vec![vec![0xab, 0xcd]]);
+        assert_eq!(txs.to_sign.input[0].previous_output, OutPoint::new(txs.to_spend.txid(), 0));
+        assert_eq!(txs.to_spend.input[0].script_sig.len(), 1 + 1 + 72);
+        assert_eq!(serialize(&txs.to_sign.output[0].script_pubkey), vec![0x01, 0x6a]);
+
+        // The virtual transactions do not commit to the solution itself
+        let other = SignetTxs::new(&block(commitment(Some(&[0x00, 0x01, 0x01, 0xef]))), &Script::new()).unwrap();
+        assert_eq!(other.to_spend, txs.to_spend);
+        assert!(other.to_sign != txs.to_sign);
+        let unsolved = SignetTxs::new(&block(commitment(None)), &Script::new()).unwrap();
+        assert!(unsolved.to_spend != txs.to_spend);
+        assert!(unsolved.to_sign.input[0].witness.is_empty());
+
+        match SignetTxs::new(&block(commitment(Some(&[0x00, 0x01, 0x01, 0xef, 0x00]))), &Script::new()) {
+            Err(Error::InvalidSolution) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        match SignetTxs::new(&block(Script::new()), &Script::new()) {
+            Err(Error::NoWitnessCommitment) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(feature="bitcoinconsensus")]
+    #[test]
+    fn solution_verification() {
+        use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+        use consensus::signet::check_block_solution;
+        use hash_types::Txid;
+
+        // A 1-of-2 bare multisig challenge, like the one of the default signet
+        let secp = Secp256k1::new();
+        let keys = [SecretKey::from_slice(&[1; 32]).unwrap(), SecretKey::from_slice(&[2; 32]).unwrap()];
+        let challenge = Builder::new()
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_slice(&PublicKey::from_secret_key(&secp, &keys[0]).serialize())
+            .push_slice(&PublicKey::from_secret_key(&secp, &keys[1]).serialize())
+            .push_opcode(opcodes::all::OP_PUSHNUM_2)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        let params = Params::custom_signet(challenge.clone());
+
+        // Sign the virtual transaction of the block with an empty solution
+        let unsolved = SignetTxs::new(&block(commitment(Some(&[]))), &challenge).unwrap();
+        let sighash = unsolved.to_sign.signature_hash(0, &challenge, 1);
+        let mut signature = secp.sign(&Message::from_slice(&sighash[..]).unwrap(), &keys[1]).serialize_der().to_vec();
+        signature.push(1);
+        let script_sig = Builder::new().push_opcode(opcodes::OP_FALSE).push_slice(&signature).into_script();
+        let mut solution = serialize(&script_sig);
+        solution.push(0); // empty witness
+
+        let solved = block(commitment(Some(&solution)));
+        let txs = SignetTxs::new(&solved, &challenge).unwrap();
+        assert_eq!(
+            txs.to_spend.txid(),
+            Txid::from_hex("e4fdadc967d8f3f7a0c9db60de84087e634a032d667ee76917d856848d321c6e").unwrap()
+        );
+        assert_eq!(txs.to_spend, unsolved.to_spend);
+        assert_eq!(txs.to_sign.input[0].script_sig, script_sig);
+        assert!(txs.verify().is_ok());
+        assert!(check_block_solution(&solved, &params).is_ok());
+
+        // Neither a tampered signature nor another challenge are accepted
+        let last = signature.len() - 2;
+        signature[last] ^= 1;
+        let script_sig = Builder::new().push_opcode(opcodes::OP_FALSE).push_slice(&signature).into_script();
+        let mut tampered = serialize(&script_sig);
+        tampered.push(0);
+        match check_block_solution(&block(commitment(Some(&tampered))), &params) {
+            Err(Error::Script(_)) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        match check_block_solution(&solved, &Params::new(Network::Signet)) {
+            Err(Error::Script(_)) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
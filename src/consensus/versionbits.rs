@@ -0,0 +1,329 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Version bits
+//!
+//! Soft fork deployments signaled by miners in block versions, as defined by
+//! BIP9 (https://github.com/bitcoin/bips/blob/master/bip-0009.mediawiki) with
+//! median-time-past based start and timeout, and by BIP8
+//! (https://github.com/bitcoin/bips/blob/master/bip-0008.mediawiki) with
+//! height based start and timeout and optional lock-in on timeout.
+//!
+//! The state of a deployment only changes at the boundaries of miner
+//! confirmation windows and is computed by walking the chain through the
+//! [`HeaderLookup`] trait.
+//!
+
+use consensus::pow::{median_time_past, HeaderLookup};
+use consensus::Params;
+
+/// Block version bits which must be set for a version to signal
+pub const VERSIONBITS_TOP_BITS: i32 = 0x20000000;
+/// Mask of the bits distinguishing version bits from other block versions
+pub const VERSIONBITS_TOP_MASK: i32 = 0xE0000000u32 as i32;
+
+/// When a deployment starts and times out
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Schedule {
+    /// BIP9: starts and times out when the median time past of the last
+    /// block of a window reaches the given timestamps
+    Time {
+        /// Start time
+        start_time: u32,
+        /// Timeout
+        timeout: u32,
+    },
+    /// BIP8: starts and times out with the first block of a window at the
+    /// given heights, which should be multiples of the window size
+    Height {
+        /// Start height
+        start_height: u32,
+        /// Timeout height
+        timeout_height: u32,
+        /// Whether all blocks must signal in the last window before timeout,
+        /// leading to lock-in
+        lock_in_on_timeout: bool,
+    },
+    /// The deployment is always active
+    AlwaysActive,
+    /// The deployment never activates
+    NeverActive,
+}
+
+/// A soft fork deployment
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Deployment {
+    /// Name of the deployment
+    pub name: &'static str,
+    /// Version bit used for signaling, from 0 to 28
+    pub bit: u8,
+    /// Start and timeout of the deployment
+    pub schedule: Schedule,
+    /// Height before which a locked in deployment does not become active
+    pub min_activation_height: u32,
+}
+
+/// Deployment states
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ThresholdState {
+    /// The deployment has not started yet
+    Defined,
+    /// Miners may signal for the deployment
+    Started,
+    /// BIP8 lock-in on timeout: all blocks must signal
+    MustSignal,
+    /// The deployment will become active
+    LockedIn,
+    /// The new consensus rules are enforced
+    Active,
+    /// The deployment timed out without locking in
+    Failed,
+}
+
+/// Signaling statistics of a window
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Statistics {
+    /// Number of blocks in a window
+    pub period: u32,
+    /// Number of signaling blocks needed for lock-in
+    pub threshold: u32,
+    /// Number of blocks of the window so far
+    pub elapsed: u32,
+    /// Number of signaling blocks of the window so far
+    pub count: u32,
+    /// Whether the threshold can still be reached in this window
+    pub possible: bool,
+}
+
+impl Deployment {
+    /// Checks whether a block version signals for the deployment
+    pub fn signals(&self, version: i32) -> bool {
+        version & VERSIONBITS_TOP_MASK == VERSIONBITS_TOP_BITS && (version >> self.bit) & 1 == 1
+    }
+
+    /// Computes the state of the deployment for the block following the one
+    /// at `last_height`, or `None` if a needed header cannot be found. The
+    /// state of the genesis block is obtained with `last_height` `None`.
+    pub fn state<L>(&self, params: &Params, chain: &L, last_height: Option<u32>) -> Option<ThresholdState>
+        where L: HeaderLookup + ?Sized
+    {
+        match self.schedule {
+            Schedule::AlwaysActive => return Some(ThresholdState::Active),
+            Schedule::NeverActive => return Some(ThresholdState::Failed),
+            _ => {},
+        }
+        let window = params.miner_confirmation_window;
+
+        // The state is the same for a whole window, so it is determined by
+        // the last block of the previous window. Walk back to the last window
+        // before the start, then forward through the state transitions.
+        let mut window_ends = vec![];
+        let mut end = last_height.map(|h| h - (h + 1) % window);
+        while let Some(height) = end {
+            if !self.started(chain, height)? {
+                break;
+            }
+            window_ends.push(height);
+            end = height.checked_sub(window);
+        }
+
+        let mut state = ThresholdState::Defined;
+        for &height in window_ends.iter().rev() {
+            state = match state {
+                ThresholdState::Defined => ThresholdState::Started,
+                ThresholdState::Started => {
+                    let mut count = 0;
+                    for h in height + 1 - window..height + 1 {
+                        if self.signals(chain.header_at(h)?.version) {
+                            count += 1;
+                        }
+                    }
+                    if count >= params.rule_change_activation_threshold {
+                        ThresholdState::LockedIn
+                    } else if self.must_signal(window, height) {
+                        ThresholdState::MustSignal
+                    } else if self.timed_out(chain, height)? {
+                        ThresholdState::Failed
+                    } else {
+                        ThresholdState::Started
+                    }
+                },
+                ThresholdState::MustSignal => ThresholdState::LockedIn,
+                ThresholdState::LockedIn if height + 1 >= self.min_activation_height => ThresholdState::Active,
+                state => state,
+            };
+        }
+        Some(state)
+    }
+
+    /// Computes the signaling statistics of the window containing the block
+    /// at `height`, up to and including that block, or `None` if a needed
+    /// header cannot be found.
+    pub fn statistics<L>(&self, params: &Params, chain: &L, height: u32) -> Option<Statistics>
+        where L: HeaderLookup + ?Sized
+    {
+        let period = params.miner_confirmation_window;
+        let threshold = params.rule_change_activation_threshold;
+        let elapsed = height % period + 1;
+        let mut count = 0;
+        for h in height + 1 - elapsed..height + 1 {
+            if self.signals(chain.header_at(h)?.version) {
+                count += 1;
+            }
+        }
+        Some(Statistics {
+            period: period,
+            threshold: threshold,
+            elapsed: elapsed,
+            count: count,
+            possible: period - threshold >= elapsed - count,
+        })
+    }
+
+    fn started<L: HeaderLookup + ?Sized>(&self, chain: &L, window_end: u32) -> Option<bool> {
+        match self.schedule {
+            Schedule::Time { start_time, .. } => Some(median_time_past(chain, window_end)? >= start_time),
+            Schedule::Height { start_height, .. } => Some(window_end + 1 >= start_height),
+            Schedule::AlwaysActive => Some(true),
+            Schedule::NeverActive => Some(false),
+        }
+    }
+
+    fn timed_out<L: HeaderLookup + ?Sized>(&self, chain: &L, window_end: u32) -> Option<bool> {
+        match self.schedule {
+            Schedule::Time { timeout, .. } => Some(median_time_past(chain, window_end)? >= timeout),
+            Schedule::Height { timeout_height, .. } => Some(window_end + 1 >= timeout_height),
+            Schedule::AlwaysActive => Some(false),
+            Schedule::NeverActive => Some(true),
+        }
+    }
+
+    fn must_signal(&self, window: u32, window_end: u32) -> bool {
+        match self.schedule {
+            Schedule::Height { timeout_height, lock_in_on_timeout: true, .. } =>
+                window_end + 1 + window >= timeout_height,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blockdata::block::BlockHeader;
+    use consensus::Params;
+    use network::constants::Network;
+
+    use super::{Deployment, Schedule, Statistics, ThresholdState};
+
+    fn chain(len: u32, signaling: &Fn(u32) -> bool) -> Vec<BlockHeader> {
+        (0..len).map(|height| BlockHeader {
+            version: if signaling(height) { 0x20000004 } else { 0x20000000 },
+            prev_blockhash: Default::default(),
+            merkle_root: Default::default(),
+            time: 1_000_000 + height * 600,
+            bits: 0x207fffff,
+            nonce: 0,
+        }).collect()
+    }
+
+    fn deployment(schedule: Schedule, min_activation_height: u32) -> Deployment {
+        Deployment {
+            name: "test",
+            bit: 2,
+            schedule: schedule,
+            min_activation_height: min_activation_height,
+        }
+    }
+
+    fn states<L>(deployment: &Deployment, chain: &L, windows: u32) -> Vec<ThresholdState>
+        where L: ::consensus::pow::HeaderLookup + ?Sized
+    {
+        let params = Params::new(Network::Regtest);
+        (0..windows).map(|w| deployment.state(&params, chain, (w * 144).checked_sub(1)).unwrap()).collect()
+    }
+
+    #[test]
+    fn signaling() {
+        let deployment = deployment(Schedule::AlwaysActive, 0);
+        assert!(deployment.signals(0x20000004));
+        assert!(deployment.signals(0x3fffffff));
+        assert!(!deployment.signals(0x20000000));
+        assert!(!deployment.signals(0x60000004));
+        assert!(!deployment.signals(4));
+    }
+
+    #[test]
+    fn bip9_state_machine() {
+        use super::ThresholdState::*;
+
+        let time = |height: u32| 1_000_000 + height * 600;
+        // Windows 2 and 3 have enough signaling blocks
+        let chain = chain(1000, &|h| h >= 300 && h < 600);
+        let schedule = Schedule::Time { start_time: time(200), timeout: time(700) };
+        assert_eq!(states(&deployment(schedule, 0), &chain, 7),
+                   vec![Defined, Defined, Started, LockedIn, Active, Active, Active]);
+        assert_eq!(states(&deployment(schedule, 700), &chain, 7),
+                   vec![Defined, Defined, Started, LockedIn, LockedIn, Active, Active]);
+
+        // Within a window, the state is the one of its first block
+        let params = Params::new(Network::Regtest);
+        assert_eq!(deployment(schedule, 0).state(&params, &chain, Some(500)), Some(LockedIn));
+        assert_eq!(deployment(schedule, 0).state(&params, &chain[..400], Some(500)), None);
+
+        // Not enough signaling before the timeout
+        let chain = super::tests::chain(1000, &|h| h % 2 == 0);
+        assert_eq!(states(&deployment(schedule, 0), &chain, 7),
+                   vec![Defined, Defined, Started, Started, Started, Failed, Failed]);
+
+        let params = Params::new(Network::Regtest);
+        assert_eq!(deployment(Schedule::AlwaysActive, 0).state(&params, &chain, None), Some(Active));
+        assert_eq!(deployment(Schedule::NeverActive, 0).state(&params, &chain, Some(10)), Some(Failed));
+    }
+
+    #[test]
+    fn bip8_state_machine() {
+        use super::ThresholdState::*;
+
+        let chain = chain(1000, &|_| false);
+        let lot = Schedule::Height { start_height: 144, timeout_height: 576, lock_in_on_timeout: true };
+        assert_eq!(states(&deployment(lot, 0), &chain, 6),
+                   vec![Defined, Started, Started, MustSignal, LockedIn, Active]);
+        let no_lot = Schedule::Height { start_height: 144, timeout_height: 576, lock_in_on_timeout: false };
+        assert_eq!(states(&deployment(no_lot, 0), &chain, 6),
+                   vec![Defined, Started, Started, Started, Failed, Failed]);
+
+        let chain = super::tests::chain(1000, &|h| h >= 150 && h < 288);
+        assert_eq!(states(&deployment(no_lot, 0), &chain, 6),
+                   vec![Defined, Started, LockedIn, Active, Active, Active]);
+    }
+
+    #[test]
+    fn statistics() {
+        let params = Params::new(Network::Regtest);
+        let chain = chain(300, &|h| h % 4 != 0);
+        let deployment = deployment(Schedule::AlwaysActive, 0);
+        assert_eq!(deployment.statistics(&params, &chain, 153), Some(Statistics {
+            period: 144,
+            threshold: 108,
+            elapsed: 10,
+            count: 7,
+            possible: true,
+        }));
+        let stats = deployment.statistics(&params, &chain, 287).unwrap();
+        assert_eq!((stats.elapsed, stats.count, stats.possible), (144, 108, true));
+        let chain = super::tests::chain(300, &|h| h % 2 == 0);
+        assert!(!deployment.statistics(&params, &chain, 287).unwrap().possible);
+        assert_eq!(deployment.statistics(&params, &chain, 400), None);
+    }
+}
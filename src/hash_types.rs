@@ -16,10 +16,10 @@
 //! to avoid mixing data of the same hash format (like SHA256d) but of different meaning
 //! (transaction id, block hash etc).
 
-use std::io;
+use io;
 use consensus::encode::{Encodable, Decodable, Error};
 use hashes::{Hash, sha256, sha256d, sha256t, ripemd160, hash160};
-use hashes::hex::{FromHex, ToHex};
+use hashes::hex::FromHex;
 use util::key::PublicKey;
 
 #[macro_export]
@@ -27,13 +27,17 @@ use util::key::PublicKey;
 macro_rules! impl_hashencode {
     ($hashtype:ty) => {
         impl $crate::consensus::Encodable for $hashtype {
-            fn consensus_encode<S: ::std::io::Write>(&self, s: S) -> Result<usize, $crate::consensus::encode::Error> {
+            fn consensus_encode<S: $crate::io::Write>(&self, s: S) -> Result<usize, $crate::consensus::encode::Error> {
                 self.into_inner().consensus_encode(s)
             }
+
+            fn encoded_len(&self) -> usize {
+                <$hashtype as $crate::hashes::Hash>::LEN
+            }
         }
 
         impl $crate::consensus::Decodable for $hashtype {
-            fn consensus_decode<D: ::std::io::Read>(d: D) -> Result<Self, $crate::consensus::encode::Error> {
+            fn consensus_decode<D: $crate::io::Read>(d: D) -> Result<Self, $crate::consensus::encode::Error> {
                 use $crate::hashes::Hash;
                 Ok(Self::from_inner(<<$hashtype as $crate::hashes::Hash>::Inner>::consensus_decode(d)?))
             }
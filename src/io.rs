@@ -0,0 +1,312 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # I/O Traits Without `std`
+//!
+//! A minimal replacement for the parts of `std::io` used by the consensus
+//! encoding, for builds without the `std` feature. The traits and types have
+//! the same names and semantics as their `std::io` counterparts, so code
+//! written against `bitcoin::io` compiles with and without `std`.
+//!
+
+use core::{cmp, fmt};
+
+use hashes::{ripemd160, sha1, sha256, sha512, siphash24, Hash, HashEngine, HmacEngine};
+use prelude::*;
+
+/// A list specifying general categories of I/O error
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum ErrorKind {
+    /// An entity was not found
+    NotFound,
+    /// A parameter was incorrect
+    InvalidInput,
+    /// Data not valid for the operation were encountered
+    InvalidData,
+    /// The operation was interrupted and can be retried
+    Interrupted,
+    /// A write returned `Ok(0)`
+    WriteZero,
+    /// The end of the data was reached prematurely
+    UnexpectedEof,
+    /// Any other error
+    Other,
+}
+
+impl ErrorKind {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            ErrorKind::NotFound => "entity not found",
+            ErrorKind::InvalidInput => "invalid input parameter",
+            ErrorKind::InvalidData => "invalid data",
+            ErrorKind::Interrupted => "operation interrupted",
+            ErrorKind::WriteZero => "write zero",
+            ErrorKind::UnexpectedEof => "unexpected end of file",
+            ErrorKind::Other => "other error",
+        }
+    }
+}
+
+/// The error type for I/O operations
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    message: &'static str,
+}
+
+impl Error {
+    /// Creates a new I/O error from a kind and a description
+    pub fn new(kind: ErrorKind, message: &'static str) -> Error {
+        Error {
+            kind: kind,
+            message: message,
+        }
+    }
+
+    /// The kind of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind, kind.as_str())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+/// A specialized `Result` type for I/O operations
+pub type Result<T> = ::core::result::Result<T, Error>;
+
+/// A source of bytes
+pub trait Read {
+    /// Reads some bytes into `buf`, returning how many were read
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Reads exactly enough bytes to fill `buf`
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf) {
+                Ok(0) => return Err(Error::from(ErrorKind::UnexpectedEof)),
+                Ok(n) => {
+                    let tmp = buf;
+                    buf = &mut tmp[n..];
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads all bytes until the end of the data, appending them to `buf`
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let mut chunk = [0u8; 512];
+        let mut len = 0;
+        loop {
+            match self.read(&mut chunk) {
+                Ok(0) => return Ok(len),
+                Ok(n) => {
+                    buf.extend_from_slice(&chunk[..n]);
+                    len += n;
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// A sink of bytes
+pub trait Write {
+    /// Writes some bytes from `buf`, returning how many were written
+    fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+    /// Flushes buffered data
+    fn flush(&mut self) -> Result<()>;
+
+    /// Writes all of `buf`
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf) {
+                Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+                Ok(n) => buf = &buf[n..],
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: Read + ?Sized> Read for &'a mut R {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl<'a> Read for &'a [u8] {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = cmp::min(buf.len(), self.len());
+        let (a, b) = self.split_at(n);
+        buf[..n].copy_from_slice(a);
+        *self = b;
+        Ok(n)
+    }
+}
+
+impl<'a, W: Write + ?Sized> Write for &'a mut W {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        (**self).write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+impl Write for Vec<u8> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An in-memory reader over a buffer, tracking its position
+#[derive(Clone, Debug, Default)]
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> Cursor<T> {
+    /// Creates a cursor at the start of `inner`
+    pub fn new(inner: T) -> Cursor<T> {
+        Cursor {
+            inner: inner,
+            pos: 0,
+        }
+    }
+
+    /// Returns the underlying buffer
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns a reference to the underlying buffer
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// The current position of the cursor
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Sets the position of the cursor
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let inner = self.inner.as_ref();
+        let start = cmp::min(self.pos, inner.len() as u64) as usize;
+        let n = (&inner[start..]).read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+macro_rules! impl_write_for_engine {
+    ($($engine:ty),*) => {
+        $(
+            impl Write for $engine {
+                #[inline]
+                fn write(&mut self, buf: &[u8]) -> Result<usize> {
+                    self.input(buf);
+                    Ok(buf.len())
+                }
+
+                #[inline]
+                fn flush(&mut self) -> Result<()> {
+                    Ok(())
+                }
+            }
+        )*
+    }
+}
+
+// `bitcoin_hashes` only implements `std::io::Write` for its engines
+impl_write_for_engine!(
+    sha1::HashEngine, sha256::HashEngine, sha512::HashEngine, ripemd160::HashEngine,
+    siphash24::HashEngine
+);
+
+impl<T: Hash> Write for HmacEngine<T> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.input(buf);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_write() {
+        let mut cursor = Cursor::new(vec![1u8, 2, 3, 4, 5]);
+        let mut buf = [0u8; 2];
+        cursor.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2]);
+        assert_eq!(cursor.position(), 2);
+
+        let mut rest = Vec::new();
+        assert_eq!(cursor.read_to_end(&mut rest).unwrap(), 3);
+        assert_eq!(rest, vec![3, 4, 5]);
+        assert_eq!(cursor.read_exact(&mut buf).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let mut out = Vec::new();
+        out.write_all(&rest).unwrap();
+        assert_eq!(out, rest);
+
+        let mut engine = sha256::Hash::engine();
+        engine.write_all(b"abc").unwrap();
+        assert_eq!(sha256::Hash::from_engine(engine), sha256::Hash::hash(b"abc"));
+    }
+}
@@ -22,11 +22,28 @@
 //! safety, including ownership and lifetime, for financial and/or cryptographic
 //! software.
 //!
+//! # Features
+//!
+//! The `std` feature is enabled by default. Without it, the `no-std` feature
+//! must be enabled to build the crate with `core` and `alloc` only, in which
+//! case the consensus encoding uses the minimal I/O traits of the `io` module
+//! instead of `std::io`. The network messages and the utilities reading files
+//! are only available with `std`.
+//!
+//! The `derive` feature re-exports `#[derive(Encodable, Decodable)]` from the
+//! `bitcoin_consensus_derive` crate in the `consensus` module.
+//!
+//! With `use-serde`, `consensus::serde` provides serde helpers serializing
+//! objects as their consensus encoding in hex, and in base64 when the
+//! `base64` feature is enabled too.
+//!
 
 #![crate_name = "bitcoin"]
 #![crate_type = "dylib"]
 #![crate_type = "rlib"]
 
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
 // Experimental features we need
 #![cfg_attr(all(test, feature = "unstable"), feature(test))]
 
@@ -50,21 +67,40 @@
 #![allow(bare_trait_objects)]
 #![allow(ellipsis_inclusive_range_patterns)]
 
+#[cfg(not(any(feature = "std", feature = "no-std")))]
+compile_error!("at least one of the `std` or `no-std` features must be enabled");
+
 // Re-exported dependencies.
 #[macro_use] pub extern crate bitcoin_hashes as hashes;
 pub extern crate secp256k1;
 pub extern crate bech32;
 
+#[cfg(feature = "no-std")] #[macro_use] extern crate alloc;
+#[cfg(feature = "hashbrown")] extern crate hashbrown;
+#[cfg(feature = "derive")] extern crate bitcoin_consensus_derive;
+#[cfg(feature = "base64")] extern crate base64;
+#[cfg(any(feature = "std", test))] extern crate core;
+
 #[cfg(feature = "serde")] extern crate serde;
 #[cfg(all(test, feature = "serde"))] #[macro_use] extern crate serde_derive; // for 1.22.0 compat
 #[cfg(all(test, feature = "serde"))] extern crate serde_json;
 #[cfg(all(test, feature = "serde"))] extern crate serde_test;
 #[cfg(all(test, feature = "unstable"))] extern crate test;
 #[cfg(feature="bitcoinconsensus")] extern crate bitcoinconsensus;
+#[cfg(feature = "async")] extern crate bytes;
+#[cfg(feature = "async")] extern crate tokio;
+#[cfg(feature = "async")] extern crate tokio_util;
 
 #[cfg(target_pointer_width = "16")]
 compile_error!("rust-bitcoin cannot be used on 16-bit architectures");
 
+/// I/O traits used by the consensus encoding: `std::io`, or a minimal
+/// replacement without the `std` feature
+#[cfg(feature = "std")]
+pub use std::io;
+#[cfg(not(feature = "std"))]
+pub mod io;
+
 #[cfg(test)]
 #[macro_use]
 mod test_macros;
@@ -101,3 +137,18 @@ pub use util::amount::SignedAmount;
 pub use util::key::PrivateKey;
 pub use util::key::PublicKey;
 pub use util::merkleblock::MerkleBlock;
+
+/// Allocated types, which are not in the `core` prelude
+mod prelude {
+    #[cfg(all(not(feature = "std"), not(test)))]
+    pub use alloc::{borrow::{Cow, ToOwned}, boxed::Box, collections::{btree_map, BTreeMap, VecDeque}, string::{String, ToString}, vec::Vec};
+
+    #[cfg(any(feature = "std", test))]
+    pub use std::{borrow::{Cow, ToOwned}, boxed::Box, collections::{btree_map, BTreeMap, VecDeque}, string::{String, ToString}, vec::Vec};
+
+    #[cfg(feature = "std")]
+    pub use std::collections::{HashMap, HashSet};
+
+    #[cfg(not(feature = "std"))]
+    pub use hashbrown::{HashMap, HashSet};
+}
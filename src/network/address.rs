@@ -18,12 +18,15 @@
 //! network addresses in Bitcoin messages.
 //!
 
-use std::io;
+use std::{error, io};
 use std::fmt;
-use std::net::{SocketAddr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::net::{IpAddr, SocketAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::str::FromStr;
 
 use network::constants::ServiceFlags;
-use consensus::encode::{self, Decodable, Encodable};
+use consensus::encode::{self, Decodable, Encodable, VarInt, WriteExt};
+use consensus::DecodeContext;
+use util::endian;
 
 /// A message which can be sent on the Bitcoin network
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -110,14 +113,456 @@ impl fmt::Debug for Address {
     }
 }
 
+/// Maximum length of an address in an `addrv2` message, in bytes
+pub const MAX_ADDRV2_LEN: usize = 512;
+
+/// Version byte of an onion v3 address
+const ONION_V3_VERSION: u8 = 3;
+
+/// A network address of any of the networks of BIP155
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum AddrV2 {
+    /// IPv4 address
+    Ipv4(Ipv4Addr),
+    /// IPv6 address
+    Ipv6(Ipv6Addr),
+    /// Tor v2 onion address, obsolete
+    TorV2([u8; 10]),
+    /// Tor v3 onion address, the ed25519 public key of the service
+    TorV3([u8; 32]),
+    /// I2P address, the SHA256 hash of the destination
+    I2p([u8; 32]),
+    /// CJDNS address, an IPv6 address which is only valid in `fc00::/8`
+    Cjdns(Ipv6Addr),
+    /// An address of a network unknown to this library, with its network id
+    Unknown(u8, Vec<u8>),
+}
+
+impl AddrV2 {
+    /// The BIP155 network id of the address
+    pub fn network_id(&self) -> u8 {
+        match *self {
+            AddrV2::Ipv4(_) => 1,
+            AddrV2::Ipv6(_) => 2,
+            AddrV2::TorV2(_) => 3,
+            AddrV2::TorV3(_) => 4,
+            AddrV2::I2p(_) => 5,
+            AddrV2::Cjdns(_) => 6,
+            AddrV2::Unknown(network, _) => network,
+        }
+    }
+
+    /// The address as the IP address of a socket, `None` for the networks
+    /// which are not reachable over IP.
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        match *self {
+            AddrV2::Ipv4(addr) => Some(IpAddr::V4(addr)),
+            AddrV2::Ipv6(addr) => Some(IpAddr::V6(addr)),
+            _ => None,
+        }
+    }
+
+    /// Whether the address is valid for its network. IPv6 addresses must not
+    /// embed IPv4 or Tor v2 addresses, which have their own network ids, and
+    /// CJDNS addresses must be in `fc00::/8`.
+    ///
+    /// Invalid addresses are decoded like valid ones, as Bitcoin Core only
+    /// ignores them rather than rejecting the `addrv2` message.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            AddrV2::Ipv6(addr) => {
+                let segments = addr.segments();
+                segments[0..6] != [0, 0, 0, 0, 0, 0xffff] && segments[0..3] != ONION
+            }
+            AddrV2::Cjdns(addr) => addr.octets()[0] == 0xfc,
+            _ => true,
+        }
+    }
+
+    /// Builds an address from its network id and the bytes of its
+    /// encoding, checking they have the length of an address of a known
+    /// network. See [AddrV2::is_valid] for the other checks.
+    pub fn from_network_bytes(network: u8, bytes: Vec<u8>) -> Result<AddrV2, encode::Error> {
+        let invalid_length = encode::Error::ParseFailed("invalid addrv2 address length");
+        Ok(match network {
+            1 => {
+                if bytes.len() != 4 {
+                    return Err(invalid_length);
+                }
+                AddrV2::Ipv4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))
+            }
+            2 => AddrV2::Ipv6(ipv6_from_slice(&bytes).ok_or(invalid_length)?),
+            3 => {
+                if bytes.len() != 10 {
+                    return Err(invalid_length);
+                }
+                let mut key = [0; 10];
+                key.copy_from_slice(&bytes);
+                AddrV2::TorV2(key)
+            }
+            4 | 5 => {
+                if bytes.len() != 32 {
+                    return Err(invalid_length);
+                }
+                let mut key = [0; 32];
+                key.copy_from_slice(&bytes);
+                if network == 4 { AddrV2::TorV3(key) } else { AddrV2::I2p(key) }
+            }
+            6 => AddrV2::Cjdns(ipv6_from_slice(&bytes).ok_or(invalid_length)?),
+            _ => AddrV2::Unknown(network, bytes),
+        })
+    }
+
+    /// The bytes of the encoding of the address, without its network id
+    fn bytes(&self) -> Vec<u8> {
+        match *self {
+            AddrV2::Ipv4(addr) => addr.octets().to_vec(),
+            AddrV2::Ipv6(addr) | AddrV2::Cjdns(addr) => addr.octets().to_vec(),
+            AddrV2::TorV2(ref key) => key.to_vec(),
+            AddrV2::TorV3(ref key) | AddrV2::I2p(ref key) => key.to_vec(),
+            AddrV2::Unknown(_, ref bytes) => bytes.clone(),
+        }
+    }
+}
+
+fn ipv6_from_slice(bytes: &[u8]) -> Option<Ipv6Addr> {
+    if bytes.len() != 16 {
+        return None;
+    }
+    let mut octets = [0; 16];
+    octets.copy_from_slice(bytes);
+    Some(Ipv6Addr::from(octets))
+}
+
+/// The checksum of an onion v3 address
+fn onion_v3_checksum(key: &[u8; 32]) -> [u8; 2] {
+    let mut data = b".onion checksum".to_vec();
+    data.extend_from_slice(key);
+    data.push(ONION_V3_VERSION);
+    let hash = sha3_256(&data);
+    [hash[0], hash[1]]
+}
+
+impl From<IpAddr> for AddrV2 {
+    fn from(addr: IpAddr) -> AddrV2 {
+        match addr {
+            IpAddr::V4(addr) => AddrV2::Ipv4(addr),
+            IpAddr::V6(addr) => match addr.to_ipv4() {
+                Some(ipv4) if addr.segments()[5] == 0xffff => AddrV2::Ipv4(ipv4),
+                _ => AddrV2::Ipv6(addr),
+            },
+        }
+    }
+}
+
+impl fmt::Display for AddrV2 {
+    /// Formats IP and CJDNS addresses like IPv4 and IPv6 addresses, Tor
+    /// addresses as `.onion` hosts and I2P addresses as `.b32.i2p` hosts.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddrV2::Ipv4(ref addr) => fmt::Display::fmt(addr, f),
+            AddrV2::Ipv6(ref addr) | AddrV2::Cjdns(ref addr) => fmt::Display::fmt(addr, f),
+            AddrV2::TorV2(ref key) => write!(f, "{}.onion", base32_encode(key)),
+            AddrV2::TorV3(ref key) => {
+                let mut data = key.to_vec();
+                data.extend_from_slice(&onion_v3_checksum(key));
+                data.push(ONION_V3_VERSION);
+                write!(f, "{}.onion", base32_encode(&data))
+            }
+            AddrV2::I2p(ref key) => write!(f, "{}.b32.i2p", base32_encode(key)),
+            AddrV2::Unknown(network, ref bytes) => {
+                write!(f, "unknown network {}: ", network)?;
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An error parsing an [AddrV2] from a string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseAddrV2Error {
+    /// Neither an IP address, an `.onion` nor a `.b32.i2p` host
+    UnknownFormat,
+    /// The host of an onion or I2P address is not valid base32
+    InvalidBase32,
+    /// The host of an onion or I2P address has the wrong length
+    InvalidLength(usize),
+    /// The version of an onion v3 address is not 3
+    InvalidOnionVersion(u8),
+    /// The checksum of an onion v3 address does not match its public key
+    InvalidOnionChecksum,
+}
+
+impl fmt::Display for ParseAddrV2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseAddrV2Error::UnknownFormat => f.write_str("not an IP, onion or I2P address"),
+            ParseAddrV2Error::InvalidBase32 => f.write_str("invalid base32 host"),
+            ParseAddrV2Error::InvalidLength(len) => write!(f, "host of invalid length {} bytes", len),
+            ParseAddrV2Error::InvalidOnionVersion(v) => write!(f, "invalid onion address version {}", v),
+            ParseAddrV2Error::InvalidOnionChecksum => f.write_str("invalid onion address checksum"),
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl error::Error for ParseAddrV2Error {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+impl FromStr for AddrV2 {
+    type Err = ParseAddrV2Error;
+
+    /// Parses IP addresses, Tor `.onion` hosts and I2P `.b32.i2p` hosts. An
+    /// IPv6 address in `fc00::/8` is parsed as an IPv6 address, not as a
+    /// CJDNS address.
+    fn from_str(s: &str) -> Result<AddrV2, ParseAddrV2Error> {
+        if s.ends_with(".onion") {
+            let data = base32_decode(&s[..s.len() - 6]).ok_or(ParseAddrV2Error::InvalidBase32)?;
+            match data.len() {
+                10 => {
+                    let mut key = [0; 10];
+                    key.copy_from_slice(&data);
+                    Ok(AddrV2::TorV2(key))
+                }
+                35 => {
+                    if data[34] != ONION_V3_VERSION {
+                        return Err(ParseAddrV2Error::InvalidOnionVersion(data[34]));
+                    }
+                    let mut key = [0; 32];
+                    key.copy_from_slice(&data[..32]);
+                    if data[32..34] != onion_v3_checksum(&key) {
+                        return Err(ParseAddrV2Error::InvalidOnionChecksum);
+                    }
+                    Ok(AddrV2::TorV3(key))
+                }
+                len => Err(ParseAddrV2Error::InvalidLength(len)),
+            }
+        } else if s.ends_with(".b32.i2p") {
+            let data = base32_decode(&s[..s.len() - 8]).ok_or(ParseAddrV2Error::InvalidBase32)?;
+            if data.len() != 32 {
+                return Err(ParseAddrV2Error::InvalidLength(data.len()));
+            }
+            let mut key = [0; 32];
+            key.copy_from_slice(&data);
+            Ok(AddrV2::I2p(key))
+        } else {
+            IpAddr::from_str(s).map(AddrV2::from).map_err(|_| ParseAddrV2Error::UnknownFormat)
+        }
+    }
+}
+
+impl Encodable for AddrV2 {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let bytes = self.bytes();
+        let len = self.network_id().consensus_encode(&mut s)?
+            + VarInt(bytes.len() as u64).consensus_encode(&mut s)?;
+        s.emit_slice(&bytes)?;
+        Ok(len + bytes.len())
+    }
+}
+
+impl Decodable for AddrV2 {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        let network = u8::consensus_decode(&mut d)?;
+        let len = VarInt::consensus_decode(&mut d)?.0;
+        if len > MAX_ADDRV2_LEN as u64 {
+            return Err(encode::Error::ParseFailed("addrv2 address too long"));
+        }
+        let bytes = encode::decode_bytes(d, len as usize, ctx)?;
+        AddrV2::from_network_bytes(network, bytes)
+    }
+}
+
+/// An address of a BIP155 `addrv2` message
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AddrV2Message {
+    /// The last time the peer was seen, as a UNIX timestamp
+    pub time: u32,
+    /// Services provided by the peer whose address this is
+    pub services: ServiceFlags,
+    /// The address of the peer
+    pub addr: AddrV2,
+    /// Network port, 0 for the networks without ports
+    pub port: u16,
+}
+
+impl AddrV2Message {
+    /// Extract socket address from an addrv2 message.
+    /// This will return io::Error ErrorKind::AddrNotAvailable if the address is not an IP address.
+    pub fn socket_addr(&self) -> Result<SocketAddr, io::Error> {
+        match self.addr.ip_addr() {
+            Some(ip) => Ok(SocketAddr::new(ip, self.port)),
+            None => Err(io::Error::from(io::ErrorKind::AddrNotAvailable)),
+        }
+    }
+}
+
+impl Encodable for AddrV2Message {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let len = self.time.consensus_encode(&mut s)?
+            + VarInt(self.services.as_u64()).consensus_encode(&mut s)?
+            + self.addr.consensus_encode(&mut s)?
+            + self.port.to_be().consensus_encode(s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for AddrV2Message {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        Ok(AddrV2Message {
+            time: Decodable::consensus_decode(&mut d)?,
+            services: ServiceFlags::from(VarInt::consensus_decode(&mut d)?.0),
+            addr: AddrV2::consensus_decode_limited(&mut d, ctx)?,
+            port: u16::from_be(Decodable::consensus_decode(d)?),
+        })
+    }
+}
+
+static BASE32_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// RFC 4648 base32 in lowercase without padding, as used by Tor and I2P
+fn base32_encode(data: &[u8]) -> String {
+    let mut ret = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8 | *byte as u32) & 0xfff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            ret.push(BASE32_CHARS[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        ret.push(BASE32_CHARS[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    ret
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut ret = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let value = match c {
+            b'a'...b'z' => c - b'a',
+            b'A'...b'Z' => c - b'A',
+            b'2'...b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5 | value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            ret.push((buffer >> bits) as u8);
+        }
+    }
+    // The remaining bits are padding and must be zero
+    if bits >= 5 || buffer & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+    Some(ret)
+}
+
+/// The SHA3-256 hash of `data`, for the checksum of onion v3 addresses
+fn sha3_256(data: &[u8]) -> [u8; 32] {
+    const RATE: usize = 136;
+
+    let mut state = [0u64; 25];
+    let mut padded = data.to_vec();
+    padded.push(0x06);
+    while padded.len() % RATE != 0 {
+        padded.push(0);
+    }
+    let last = padded.len() - 1;
+    padded[last] |= 0x80;
+
+    for block in padded.chunks(RATE) {
+        for (lane, word) in state.iter_mut().zip(block.chunks(8)) {
+            *lane ^= endian::slice_to_u64_le(word);
+        }
+        keccak_f1600(&mut state);
+    }
+
+    let mut ret = [0; 32];
+    for (chunk, lane) in ret.chunks_mut(8).zip(state.iter()) {
+        chunk.copy_from_slice(&endian::u64_to_array_le(*lane));
+    }
+    ret
+}
+
+fn keccak_f1600(a: &mut [u64; 25]) {
+    const ROUND_CONSTANTS: [u64; 24] = [
+        0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+        0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+        0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+        0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+        0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+        0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+    ];
+    const ROTATIONS: [u32; 24] = [
+        1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+    ];
+    const LANES: [usize; 24] = [
+        10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+    ];
+
+    for round_constant in ROUND_CONSTANTS.iter() {
+        // Theta
+        let mut c = [0u64; 5];
+        for x in 0..5 {
+            c[x] = a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20];
+        }
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                a[5 * y + x] ^= d;
+            }
+        }
+        // Rho and pi
+        let mut last = a[1];
+        for i in 0..24 {
+            let lane = a[LANES[i]];
+            a[LANES[i]] = last.rotate_left(ROTATIONS[i]);
+            last = lane;
+        }
+        // Chi
+        for y in 0..5 {
+            let row = [a[5 * y], a[5 * y + 1], a[5 * y + 2], a[5 * y + 3], a[5 * y + 4]];
+            for x in 0..5 {
+                a[5 * y + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+        // Iota
+        a[0] ^= *round_constant;
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
-    use super::Address;
+    use super::{Address, AddrV2, AddrV2Message, ParseAddrV2Error, sha3_256};
     use network::constants::ServiceFlags;
     use std::net::{SocketAddr, IpAddr, Ipv4Addr, Ipv6Addr};
 
     use consensus::encode::{deserialize, serialize};
+    use hashes::hex::{FromHex, ToHex};
 
     #[test]
     fn serialize_address_test() {
@@ -191,5 +636,138 @@ mod test {
         let addr = Address::new(&onionaddr, ServiceFlags::NONE);
         assert!(addr.socket_addr().is_err());
     }
-}
 
+    #[test]
+    fn sha3_256_test() {
+        // Computed with Python's hashlib.sha3_256, across the 136 bytes block size
+        let vectors = [
+            (0, "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"),
+            (3, "1186d49a4ad620618f760f29da2c593b2ec2cc2ced69dc16817390d861e62253"),
+            (135, "fded8fd9d6551c601eeb3b7c6bc5e5cfd8aad1d015b7e9aaa9c9b9475231d5e2"),
+            (136, "cf3ccff92480a29160c2d38317c430e14749bfee1788106957dfe73f8c4930e5"),
+            (137, "ce9d7dc90913ee5d92745019479a5352c6d6279bef18ed07dc0a83ee8084daca"),
+            (300, "815c06bbeb8520ce61add33a5f47bc558bf00e6361a5640c972d5d4634c58101"),
+        ];
+        for &(len, hash) in vectors.iter() {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            assert_eq!(sha3_256(&data).to_hex(), hash);
+        }
+    }
+
+    #[test]
+    fn addrv2_serialize_test() {
+        // Test vectors of Bitcoin Core
+        let vectors = [
+            ("010401020304", "1.2.3.4"),
+            ("02100102030405060708090a0b0c0d0e0f10", "102:304:506:708:90a:b0c:d0e:f10"),
+            ("042079bcc625184b05194975c28b66b66b0469f7f6556fb1ac3189a79b40dda32f1f",
+             "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion"),
+            ("0520a2894dabaec08c0051a481a6dac88b64f98232ae42d4b6fd2fa81952dfe36a87",
+             "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p"),
+            ("0610fc000001000200030004000500060007", "fc00:1:2:3:4:5:6:7"),
+        ];
+        for &(hex, string) in vectors.iter() {
+            let bytes = Vec::from_hex(hex).unwrap();
+            let addr: AddrV2 = deserialize(&bytes).unwrap();
+            assert!(addr.is_valid());
+            assert_eq!(addr.to_string(), string);
+            assert_eq!(serialize(&addr), bytes);
+        }
+
+        let addr = AddrV2::TorV2([0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(addr.to_string(), "aaaqeayeaudaocaj.onion");
+        assert_eq!(deserialize::<AddrV2>(&serialize(&addr)).unwrap(), addr);
+
+        let addr = AddrV2::Unknown(170, vec![1, 2]);
+        assert_eq!(serialize(&addr), vec![170, 2, 1, 2]);
+        assert_eq!(deserialize::<AddrV2>(&[170, 2, 1, 2]).unwrap(), addr);
+    }
+
+    #[test]
+    fn addrv2_deserialize_invalid_test() {
+        let invalid = [
+            // Wrong lengths for the network
+            "0105010203040506",
+            "02040102030405",
+            "0420010203",
+            "06040a0b0c0d",
+        ];
+        for hex in invalid.iter() {
+            assert!(deserialize::<AddrV2>(&Vec::from_hex(hex).unwrap()).is_err(), "{}", hex);
+        }
+
+        // Decoded, but not valid addresses
+        let invalid = [
+            // IPv4 and Tor v2 embedded in IPv6
+            "021000000000000000000000ffff01020304",
+            "0210fd87d87eeb4300000000000000000000",
+            // CJDNS outside of fc00::/8
+            "0610fd000001000200030004000500060007",
+        ];
+        for hex in invalid.iter() {
+            let bytes = Vec::from_hex(hex).unwrap();
+            let addr: AddrV2 = deserialize(&bytes).unwrap();
+            assert!(!addr.is_valid(), "{}", hex);
+            assert_eq!(serialize(&addr), bytes);
+        }
+
+        // Too long, whatever the network
+        let mut bytes = vec![170, 0xfd, 0x01, 0x02];
+        bytes.extend(vec![0; 513]);
+        assert!(deserialize::<AddrV2>(&bytes).is_err());
+    }
+
+    #[test]
+    fn addrv2_from_str_test() {
+        for string in [
+            "1.2.3.4",
+            "102:304:506:708:90a:b0c:d0e:f10",
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion",
+            "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion",
+            "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p",
+            "aaaqeayeaudaocaj.onion",
+        ].iter() {
+            assert_eq!(AddrV2::from_str(string).unwrap().to_string(), *string);
+        }
+        assert_eq!(AddrV2::from_str("::ffff:1.2.3.4").unwrap(), AddrV2::Ipv4(Ipv4Addr::new(1, 2, 3, 4)));
+        assert_eq!(
+            AddrV2::from_str("PG6MMJIYJMCRSSLVYKFWNNTLARU7P5SVN6Y2YMMJU6NUBXNDF4PSCRYD.onion").unwrap().to_string(),
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion"
+        );
+
+        assert_eq!(AddrV2::from_str("example.com"), Err(ParseAddrV2Error::UnknownFormat));
+        assert_eq!(AddrV2::from_str("pg6mmjiyjmcr1.onion"), Err(ParseAddrV2Error::InvalidBase32));
+        assert_eq!(AddrV2::from_str("aaaqeayeaudaocajaa.onion"), Err(ParseAddrV2Error::InvalidLength(11)));
+        // Last character changed, which changes the version
+        assert_eq!(
+            AddrV2::from_str("pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscrya.onion"),
+            Err(ParseAddrV2Error::InvalidOnionVersion(0))
+        );
+        // First character changed, which changes the public key
+        assert_eq!(
+            AddrV2::from_str("ag6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion"),
+            Err(ParseAddrV2Error::InvalidOnionChecksum)
+        );
+        assert_eq!(
+            AddrV2::from_str("ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkd.b32.i2p"),
+            Err(ParseAddrV2Error::InvalidBase32)
+        );
+    }
+
+    #[test]
+    fn addrv2_message_test() {
+        let msg = AddrV2Message {
+            time: 0x5f5e100,
+            services: ServiceFlags::NETWORK | ServiceFlags::WITNESS,
+            addr: AddrV2::Ipv4(Ipv4Addr::new(1, 2, 3, 4)),
+            port: 8333,
+        };
+        let bytes = serialize(&msg);
+        assert_eq!(bytes.to_hex(), "00e1f50509010401020304208d");
+        assert_eq!(deserialize::<AddrV2Message>(&bytes).unwrap(), msg);
+        assert_eq!(msg.socket_addr().unwrap(), SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), 8333));
+
+        let msg = AddrV2Message { addr: AddrV2::I2p([0; 32]), port: 0, ..msg };
+        assert!(msg.socket_addr().is_err());
+    }
+}
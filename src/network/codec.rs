@@ -0,0 +1,520 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Network message codec
+//!
+//! Splits a byte stream into [`RawNetworkMessage`]s without blocking: bytes
+//! are buffered by the caller and a message is decoded once all of it has
+//! been received. The magic, checksum and payload size are validated from the
+//! message header, so oversized messages are rejected before their payload
+//! is buffered.
+//!
+//! With the `async` feature, [`MessageCodec`] implements the `Decoder` and
+//! `Encoder` traits of `tokio-util`, so that messages can be read from an
+//! `AsyncRead` and written to an `AsyncWrite` with `tokio_util::codec::Framed`:
+//!
+//! ```ignore
+//! let mut framed = Framed::new(tcp_stream, MessageCodec::new(Network::Bitcoin.magic()));
+//! framed.send(RawNetworkMessage { magic: Network::Bitcoin.magic(), payload: NetworkMessage::Verack }).await?;
+//! while let Some(message) = framed.next().await {
+//!     println!("received {:?}", message?.payload);
+//! }
+//! ```
+//!
+//! Single messages can also be read and written without any framing with
+//! [`MessageCodec::read_message`] and [`MessageCodec::write_message`]. Both
+//! return plain futures, so this crate does not depend on any runtime:
+//!
+//! ```ignore
+//! let codec = MessageCodec::new(Network::Bitcoin.magic());
+//! let verack = RawNetworkMessage { magic: Network::Bitcoin.magic(), payload: NetworkMessage::Verack };
+//! codec.write_message(&mut tcp_stream, &verack).await?;
+//! let message = codec.read_message(&mut tcp_stream).await?;
+//! ```
+//!
+
+use std::{error, fmt, io};
+#[cfg(feature = "async")] use std::future::Future;
+#[cfg(feature = "async")] use std::pin::Pin;
+#[cfg(feature = "async")] use std::task::{Context, Poll};
+
+#[cfg(feature = "async")] use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use hashes::{sha256d, Hash};
+
+use consensus::encode::{self, Encodable};
+use network::message::RawNetworkMessage;
+use util::endian;
+
+/// Size of the header preceding message payloads
+pub const HEADER_SIZE: usize = 24;
+
+/// Default maximum payload size, as in Bitcoin Core
+pub const MAX_PAYLOAD_SIZE: usize = 4_000_000;
+
+/// Message codec errors. The stream cannot be decoded any further after an
+/// error, since message boundaries are lost.
+#[derive(Debug)]
+pub enum Error {
+    /// I/O error of the underlying stream
+    Io(io::Error),
+    /// The message could not be decoded
+    Encode(encode::Error),
+    /// The message magic does not match the network
+    WrongMagic {
+        /// The network magic
+        expected: u32,
+        /// The magic of the message
+        actual: u32,
+    },
+    /// The payload size exceeds the maximum
+    PayloadTooLarge {
+        /// The payload size of the message
+        size: usize,
+        /// The maximum payload size
+        max: usize,
+    },
+    /// The checksum does not match the payload
+    BadChecksum {
+        /// The checksum of the payload
+        expected: [u8; 4],
+        /// The checksum of the message header
+        actual: [u8; 4],
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Encode(ref e) => write!(f, "decoding error: {}", e),
+            Error::WrongMagic { expected, actual } => write!(f,
+                "wrong network magic: expected {:#010x}, got {:#010x}", expected, actual),
+            Error::PayloadTooLarge { size, max } => write!(f,
+                "payload of {} bytes exceeds maximum of {} bytes", size, max),
+            Error::BadChecksum { expected, actual } => write!(f,
+                "invalid checksum: expected {:?}, got {:?}", expected, actual),
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Encode(ref e) => Some(e),
+            Error::WrongMagic { .. } | Error::PayloadTooLarge { .. } | Error::BadChecksum { .. } => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+#[doc(hidden)]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+/// Codec of the messages of a network
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MessageCodec {
+    magic: u32,
+    max_payload_size: usize,
+}
+
+impl MessageCodec {
+    /// Creates a codec for messages with the given network magic and
+    /// payloads of at most [`MAX_PAYLOAD_SIZE`] bytes.
+    pub fn new(magic: u32) -> MessageCodec {
+        MessageCodec {
+            magic: magic,
+            max_payload_size: MAX_PAYLOAD_SIZE,
+        }
+    }
+
+    /// Sets the maximum payload size.
+    pub fn max_payload_size(mut self, max_payload_size: usize) -> MessageCodec {
+        self.max_payload_size = max_payload_size;
+        self
+    }
+
+    /// Returns the network magic.
+    pub fn magic(&self) -> u32 {
+        self.magic
+    }
+
+    /// Decodes the message at the start of `buf`, returning it with its size,
+    /// or `None` if `buf` does not contain the whole message yet.
+    pub fn decode_message(&self, buf: &[u8]) -> Result<Option<(RawNetworkMessage, usize)>, Error> {
+        if buf.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let magic = endian::slice_to_u32_le(&buf[0..4]);
+        if magic != self.magic {
+            return Err(Error::WrongMagic { expected: self.magic, actual: magic });
+        }
+        let size = endian::slice_to_u32_le(&buf[16..20]) as usize;
+        if size > self.max_payload_size {
+            return Err(Error::PayloadTooLarge { size: size, max: self.max_payload_size });
+        }
+        if buf.len() < HEADER_SIZE + size {
+            return Ok(None);
+        }
+        let payload = &buf[HEADER_SIZE..HEADER_SIZE + size];
+        let mut expected = [0u8; 4];
+        expected.copy_from_slice(&sha256d::Hash::hash(payload)[0..4]);
+        if expected != buf[20..24] {
+            let mut actual = [0u8; 4];
+            actual.copy_from_slice(&buf[20..24]);
+            return Err(Error::BadChecksum { expected: expected, actual: actual });
+        }
+        let message = encode::deserialize(&buf[..HEADER_SIZE + size])?;
+        Ok(Some((message, HEADER_SIZE + size)))
+    }
+
+    /// Encodes a message at the end of `buf`.
+    pub fn encode_message(&self, message: &RawNetworkMessage, buf: &mut Vec<u8>) -> Result<(), Error> {
+        if message.magic != self.magic {
+            return Err(Error::WrongMagic { expected: self.magic, actual: message.magic });
+        }
+        let start = buf.len();
+        message.consensus_encode(&mut *buf)?;
+        let size = buf.len() - start - HEADER_SIZE;
+        if size > self.max_payload_size {
+            buf.truncate(start);
+            return Err(Error::PayloadTooLarge { size: size, max: self.max_payload_size });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl MessageCodec {
+    /// Reads a single message from `reader`. No byte past the end of the
+    /// message is read, so that messages can be read one after the other.
+    /// The end of the stream is reported as an `UnexpectedEof` I/O error.
+    pub fn read_message<'a, R>(&self, reader: &'a mut R) -> ReadMessage<'a, R>
+        where R: AsyncRead + Unpin + ?Sized
+    {
+        ReadMessage {
+            codec: *self,
+            reader: reader,
+            buf: vec![],
+            filled: 0,
+        }
+    }
+
+    /// Writes a single message to `writer`, and flushes it.
+    pub fn write_message<'a, W>(&self, writer: &'a mut W, message: &RawNetworkMessage) -> WriteMessage<'a, W>
+        where W: AsyncWrite + Unpin + ?Sized
+    {
+        let mut buf = vec![];
+        let error = self.encode_message(message, &mut buf).err();
+        WriteMessage {
+            writer: writer,
+            buf: buf,
+            written: 0,
+            error: error,
+        }
+    }
+}
+
+/// Future returned by [`MessageCodec::read_message`]
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct ReadMessage<'a, R: 'a + ?Sized> {
+    codec: MessageCodec,
+    reader: &'a mut R,
+    buf: Vec<u8>,
+    filled: usize,
+}
+
+#[cfg(feature = "async")]
+impl<'a, R: AsyncRead + Unpin + ?Sized> Future for ReadMessage<'a, R> {
+    type Output = Result<RawNetworkMessage, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            match this.codec.decode_message(&this.buf[..this.filled]) {
+                Ok(Some((message, _))) => return Poll::Ready(Ok(message)),
+                Ok(None) => {},
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+            // The payload size has been validated if the header is there
+            let size = if this.filled < HEADER_SIZE {
+                HEADER_SIZE
+            } else {
+                HEADER_SIZE + endian::slice_to_u32_le(&this.buf[16..20]) as usize
+            };
+            this.buf.resize(size, 0);
+
+            let mut buf = ReadBuf::new(&mut this.buf[this.filled..]);
+            match Pin::new(&mut *this.reader).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::Io(e))),
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => {
+                    return Poll::Ready(Err(Error::Io(io::ErrorKind::UnexpectedEof.into())));
+                },
+                Poll::Ready(Ok(())) => this.filled += buf.filled().len(),
+            }
+        }
+    }
+}
+
+/// Future returned by [`MessageCodec::write_message`]
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct WriteMessage<'a, W: 'a + ?Sized> {
+    writer: &'a mut W,
+    buf: Vec<u8>,
+    written: usize,
+    error: Option<Error>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, W: AsyncWrite + Unpin + ?Sized> Future for WriteMessage<'a, W> {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Some(e) = this.error.take() {
+            return Poll::Ready(Err(e));
+        }
+        while this.written < this.buf.len() {
+            match Pin::new(&mut *this.writer).poll_write(cx, &this.buf[this.written..]) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::Io(e))),
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(Error::Io(io::ErrorKind::WriteZero.into()))),
+                Poll::Ready(Ok(n)) => this.written += n,
+            }
+        }
+        match Pin::new(&mut *this.writer).poll_flush(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Err(Error::Io(e))),
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl ::tokio_util::codec::Decoder for MessageCodec {
+    type Item = RawNetworkMessage;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut ::bytes::BytesMut) -> Result<Option<RawNetworkMessage>, Error> {
+        match self.decode_message(&src[..])? {
+            Some((message, size)) => {
+                ::bytes::Buf::advance(src, size);
+                Ok(Some(message))
+            },
+            None => {
+                // The payload size has been validated if the header is there
+                if src.len() >= HEADER_SIZE {
+                    let size = endian::slice_to_u32_le(&src[16..20]) as usize;
+                    src.reserve(HEADER_SIZE + size - src.len());
+                }
+                Ok(None)
+            },
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl ::tokio_util::codec::Encoder<RawNetworkMessage> for MessageCodec {
+    type Error = Error;
+
+    fn encode(&mut self, message: RawNetworkMessage, dst: &mut ::bytes::BytesMut) -> Result<(), Error> {
+        let mut buf = vec![];
+        self.encode_message(&message, &mut buf)?;
+        dst.extend_from_slice(&buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use consensus::encode::serialize;
+    use network::constants::Network;
+    use network::message::{NetworkMessage, RawNetworkMessage};
+
+    use super::{Error, MessageCodec};
+
+    fn ping(nonce: u64) -> RawNetworkMessage {
+        RawNetworkMessage {
+            magic: Network::Bitcoin.magic(),
+            payload: NetworkMessage::Ping(nonce),
+        }
+    }
+
+    #[test]
+    fn decode_stream() {
+        let codec = MessageCodec::new(Network::Bitcoin.magic());
+        let mut stream = vec![];
+        codec.encode_message(&ping(1), &mut stream).unwrap();
+        codec.encode_message(&ping(2), &mut stream).unwrap();
+        assert_eq!(stream, [serialize(&ping(1)), serialize(&ping(2))].concat());
+
+        // Partial messages need more data
+        for len in 0..32 {
+            assert!(codec.decode_message(&stream[..len]).unwrap().is_none());
+        }
+        let (first, size) = codec.decode_message(&stream).unwrap().unwrap();
+        assert_eq!((first, size), (ping(1), 32));
+        let (second, size) = codec.decode_message(&stream[32..]).unwrap().unwrap();
+        assert_eq!((second, size), (ping(2), 32));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let codec = MessageCodec::new(Network::Bitcoin.magic());
+        let message = serialize(&ping(1));
+
+        match MessageCodec::new(Network::Testnet.magic()).decode_message(&message) {
+            Err(Error::WrongMagic { expected: 0x0709110B, actual: 0xD9B4BEF9 }) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        match codec.max_payload_size(7).decode_message(&message[..24]) {
+            Err(Error::PayloadTooLarge { size: 8, max: 7 }) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut bad_checksum = message.clone();
+        bad_checksum[31] ^= 1;
+        match codec.decode_message(&bad_checksum) {
+            Err(Error::BadChecksum { .. }) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut unknown = message.clone();
+        unknown[4..8].copy_from_slice(b"pung");
+        match codec.decode_message(&unknown) {
+            Ok(Some((RawNetworkMessage { payload: NetworkMessage::Unknown { ref command, ref payload }, .. }, 32)))
+                if command.as_ref() == "pung" && payload[..] == message[24..] => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut buf = vec![1, 2, 3];
+        match codec.max_payload_size(7).encode_message(&ping(1), &mut buf) {
+            Err(Error::PayloadTooLarge { size: 8, max: 7 }) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(buf, vec![1, 2, 3]);
+        assert!(MessageCodec::new(Network::Testnet.magic()).encode_message(&ping(1), &mut buf).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn tokio_codec() {
+        use bytes::BytesMut;
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut codec = MessageCodec::new(Network::Bitcoin.magic());
+        let mut buf = BytesMut::new();
+        codec.encode(ping(1), &mut buf).unwrap();
+        codec.encode(ping(2), &mut buf).unwrap();
+        let mut stream = buf.split_off(20);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.unsplit(stream.split_to(12));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(ping(1)));
+        assert!(buf.is_empty());
+        buf.unsplit(stream);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(ping(2)));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    }
+
+    #[cfg(feature = "async")]
+    fn block_on<F: ::std::future::Future + Unpin>(mut future: F) -> F::Output {
+        use std::pin::Pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_read_write() {
+        use std::io;
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+        use tokio::io::{AsyncRead, ReadBuf};
+
+        // Yields a single byte every other poll
+        struct Trickle<'a> {
+            data: &'a [u8],
+            pending: bool,
+        }
+        impl<'a> AsyncRead for Trickle<'a> {
+            fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+                self.pending = !self.pending;
+                if self.pending {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                let data = self.data;
+                if let Some((first, rest)) = data.split_first() {
+                    buf.put_slice(&[*first]);
+                    self.data = rest;
+                }
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let codec = MessageCodec::new(Network::Bitcoin.magic());
+        let mut stream = vec![];
+        block_on(codec.write_message(&mut stream, &ping(1))).unwrap();
+        block_on(codec.write_message(&mut stream, &ping(2))).unwrap();
+        assert_eq!(stream, [serialize(&ping(1)), serialize(&ping(2))].concat());
+        match block_on(codec.max_payload_size(7).write_message(&mut stream, &ping(3))) {
+            Err(Error::PayloadTooLarge { size: 8, max: 7 }) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(stream.len(), 64);
+
+        let mut reader = Trickle { data: &stream[..60], pending: false };
+        assert_eq!(block_on(codec.read_message(&mut reader)).unwrap(), ping(1));
+        assert_eq!(reader.data.len(), 28);
+        match block_on(codec.read_message(&mut reader)) {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match block_on(MessageCodec::new(Network::Testnet.magic()).read_message(&mut &stream[..])) {
+            Err(Error::WrongMagic { expected: 0x0709110B, actual: 0xD9B4BEF9 }) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -37,7 +37,8 @@
 //! assert_eq!(&bytes[..], &[0xF9, 0xBE, 0xB4, 0xD9]);
 //! ```
 
-use std::{fmt, io, ops};
+use core::{fmt, ops};
+use io;
 
 use consensus::encode::{self, Encodable, Decodable};
 
@@ -18,13 +18,18 @@
 //! Bitcoin data (blocks and transactions) around.
 //!
 
+use std::collections::HashSet;
 use std::io;
 
 use hashes::sha256d;
 
 use network::constants;
 use consensus::encode::{self, Decodable, Encodable};
-use hash_types::{BlockHash, Txid};
+use hash_types::{BlockHash, Txid, Wtxid};
+
+/// The flag set in the type of the inventories requesting witness data, as
+/// defined in BIP144
+pub const WITNESS_FLAG: u32 = 1 << 30;
 
 /// An inventory item.
 #[derive(PartialEq, Eq, Clone, Debug, Copy, Hash)]
@@ -39,6 +44,52 @@ pub enum Inventory {
     WitnessTransaction(Txid),
     /// Witness Block
     WitnessBlock(BlockHash),
+    /// Block answered with a `merkleblock` message, as defined in BIP37
+    FilteredBlock(BlockHash),
+    /// Block answered with a `merkleblock` message and witness transactions
+    WitnessFilteredBlock(BlockHash),
+    /// Block answered with a `cmpctblock` message, as defined in BIP152
+    CompactBlock(BlockHash),
+    /// Transaction identified by its wtxid, as defined in BIP339
+    WTx(Wtxid),
+    /// Inventory of a type unknown to this library
+    Unknown {
+        /// The type of the inventory
+        inv_type: u32,
+        /// The hash of the inventory item
+        hash: [u8; 32],
+    },
+}
+
+impl Inventory {
+    /// The type of the inventory, with [WITNESS_FLAG] set for the witness
+    /// inventories
+    pub fn inv_type(&self) -> u32 {
+        match *self {
+            Inventory::Error => 0,
+            Inventory::Transaction(_) => 1,
+            Inventory::Block(_) => 2,
+            Inventory::WitnessTransaction(_) => 1 | WITNESS_FLAG,
+            Inventory::WitnessBlock(_) => 2 | WITNESS_FLAG,
+            Inventory::FilteredBlock(_) => 3,
+            Inventory::WitnessFilteredBlock(_) => 3 | WITNESS_FLAG,
+            Inventory::CompactBlock(_) => 4,
+            Inventory::WTx(_) => 5,
+            Inventory::Unknown { inv_type, .. } => inv_type,
+        }
+    }
+
+    /// Returns the inventory requesting the witness data of a transaction, a
+    /// block or a filtered block in a `getdata` message, the other
+    /// inventories are returned unchanged.
+    pub fn with_witness(self) -> Inventory {
+        match self {
+            Inventory::Transaction(txid) => Inventory::WitnessTransaction(txid),
+            Inventory::Block(hash) => Inventory::WitnessBlock(hash),
+            Inventory::FilteredBlock(hash) => Inventory::WitnessFilteredBlock(hash),
+            inv => inv,
+        }
+    }
 }
 
 impl Encodable for Inventory {
@@ -53,12 +104,18 @@ impl Encodable for Inventory {
                 $item.consensus_encode(&mut s)?
             }
         }
+        let inv_type = self.inv_type();
         Ok(match *self {
-            Inventory::Error => encode_inv!(0, sha256d::Hash::default()),
-            Inventory::Transaction(ref t) => encode_inv!(1, t),
-            Inventory::Block(ref b) => encode_inv!(2, b),
-            Inventory::WitnessTransaction(ref t) => encode_inv!(0x40000001, t),
-            Inventory::WitnessBlock(ref b) => encode_inv!(0x40000002, b),
+            Inventory::Error => encode_inv!(inv_type, sha256d::Hash::default()),
+            Inventory::Transaction(ref t) => encode_inv!(inv_type, t),
+            Inventory::Block(ref b) => encode_inv!(inv_type, b),
+            Inventory::WitnessTransaction(ref t) => encode_inv!(inv_type, t),
+            Inventory::WitnessBlock(ref b) => encode_inv!(inv_type, b),
+            Inventory::FilteredBlock(ref b) => encode_inv!(inv_type, b),
+            Inventory::WitnessFilteredBlock(ref b) => encode_inv!(inv_type, b),
+            Inventory::CompactBlock(ref b) => encode_inv!(inv_type, b),
+            Inventory::WTx(ref w) => encode_inv!(inv_type, w),
+            Inventory::Unknown { ref hash, .. } => encode_inv!(inv_type, hash),
         })
     }
 }
@@ -68,12 +125,23 @@ impl Decodable for Inventory {
     fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
         let inv_type: u32 = Decodable::consensus_decode(&mut d)?;
         Ok(match inv_type {
-            0 => Inventory::Error,
+            0 => {
+                // The hash of an error inventory is meaningless but still encoded
+                let _: sha256d::Hash = Decodable::consensus_decode(&mut d)?;
+                Inventory::Error
+            }
             1 => Inventory::Transaction(Decodable::consensus_decode(&mut d)?),
             2 => Inventory::Block(Decodable::consensus_decode(&mut d)?),
+            3 => Inventory::FilteredBlock(Decodable::consensus_decode(&mut d)?),
+            4 => Inventory::CompactBlock(Decodable::consensus_decode(&mut d)?),
+            5 => Inventory::WTx(Decodable::consensus_decode(&mut d)?),
             0x40000001 => Inventory::WitnessTransaction(Decodable::consensus_decode(&mut d)?),
             0x40000002 => Inventory::WitnessBlock(Decodable::consensus_decode(&mut d)?),
-            tp => return Err(encode::Error::UnknownInventoryType(tp)),
+            0x40000003 => Inventory::WitnessFilteredBlock(Decodable::consensus_decode(&mut d)?),
+            tp => Inventory::Unknown {
+                inv_type: tp,
+                hash: Decodable::consensus_decode(&mut d)?,
+            },
         })
     }
 }
@@ -132,15 +200,83 @@ impl GetHeadersMessage {
 
 impl_consensus_encoding!(GetHeadersMessage, version, locator_hashes, stop_hash);
 
+/// Builds a block locator for `getblocks` and `getheaders` messages from a
+/// chain of block hashes indexed by height, as Bitcoin Core does: the ten
+/// most recent blocks, then blocks at exponentially increasing distances
+/// from the tip, and finally the genesis block.
+pub fn block_locator(chain: &[BlockHash]) -> Vec<BlockHash> {
+    let mut locator = vec![];
+    if chain.is_empty() {
+        return locator;
+    }
+    let mut height = chain.len() - 1;
+    let mut step = 1;
+    loop {
+        locator.push(chain[height]);
+        if height == 0 {
+            break;
+        }
+        height = height.saturating_sub(step);
+        if locator.len() > 10 {
+            step *= 2;
+        }
+    }
+    locator
+}
+
+/// Finds the fork point of a peer chain described by a block locator in a
+/// chain of block hashes indexed by height, i.e. the height of the highest
+/// block of the chain which is also in the locator. Returns `None` if the
+/// chains have no block in common, not even the genesis block.
+pub fn find_fork_point(chain: &[BlockHash], locator: &[BlockHash]) -> Option<usize> {
+    let locator: HashSet<&BlockHash> = locator.iter().collect();
+    chain.iter().rposition(|hash| locator.contains(hash))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{GetHeadersMessage, GetBlocksMessage};
+    use super::{block_locator, find_fork_point, GetHeadersMessage, GetBlocksMessage, Inventory};
 
     use hashes::hex::FromHex;
 
     use consensus::encode::{deserialize, serialize};
+    use hash_types::{BlockHash, Txid, Wtxid};
+    use hashes::Hash;
     use std::default::Default;
 
+    #[test]
+    fn inventory_test() {
+        let hash = [0xab; 32];
+        let block_hash = BlockHash::from_inner(hash);
+        let txid = Txid::from_inner(hash);
+        let invs = [
+            (Inventory::Error, 0u32),
+            (Inventory::Transaction(txid), 1),
+            (Inventory::Block(block_hash), 2),
+            (Inventory::FilteredBlock(block_hash), 3),
+            (Inventory::CompactBlock(block_hash), 4),
+            (Inventory::WTx(Wtxid::from_inner(hash)), 5),
+            (Inventory::WitnessTransaction(txid), 0x40000001),
+            (Inventory::WitnessBlock(block_hash), 0x40000002),
+            (Inventory::WitnessFilteredBlock(block_hash), 0x40000003),
+            (Inventory::Unknown { inv_type: 0x40000004, hash: hash }, 0x40000004),
+        ];
+        for &(inv, inv_type) in invs.iter() {
+            assert_eq!(inv.inv_type(), inv_type);
+            let bytes = serialize(&inv);
+            assert_eq!(bytes[..4], serialize(&inv_type)[..]);
+            if inv != Inventory::Error {
+                assert_eq!(bytes[4..], hash[..]);
+            }
+            assert_eq!(deserialize::<Inventory>(&bytes).unwrap(), inv);
+        }
+
+        assert_eq!(Inventory::Transaction(txid).with_witness(), Inventory::WitnessTransaction(txid));
+        assert_eq!(Inventory::Block(block_hash).with_witness(), Inventory::WitnessBlock(block_hash));
+        assert_eq!(Inventory::FilteredBlock(block_hash).with_witness(), Inventory::WitnessFilteredBlock(block_hash));
+        assert_eq!(Inventory::CompactBlock(block_hash).with_witness(), Inventory::CompactBlock(block_hash));
+    }
+
     #[test]
     fn getblocks_message_test() {
         let from_sat = Vec::from_hex("72110100014a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b0000000000000000000000000000000000000000000000000000000000000000").unwrap();
@@ -172,5 +308,25 @@ mod tests {
 
         assert_eq!(serialize(&real_decode), from_sat);
     }
-}
 
+    #[test]
+    fn locator_test() {
+        let chain: Vec<BlockHash> = (0..100u8).map(|i| BlockHash::hash(&[i])).collect();
+        assert_eq!(block_locator(&[]), vec![]);
+        assert_eq!(block_locator(&chain[..1]), vec![chain[0]]);
+
+        let heights: Vec<usize> = block_locator(&chain).iter()
+            .map(|hash| chain.iter().position(|h| h == hash).unwrap())
+            .collect();
+        assert_eq!(heights, vec![99, 98, 97, 96, 95, 94, 93, 92, 91, 90, 89, 88, 86, 82, 74, 58, 26, 0]);
+
+        // The peer chain forks from ours after height 80, its locator only has
+        // height 69 in common with our chain
+        let mut peer = chain[..81].to_vec();
+        peer.extend((0..30u8).map(|i| BlockHash::hash(&[i, 1])));
+        assert_eq!(find_fork_point(&chain, &block_locator(&peer)), Some(69));
+        assert_eq!(find_fork_point(&chain, &block_locator(&chain[..50])), Some(49));
+        assert_eq!(find_fork_point(&chain[..50], &block_locator(&chain)), Some(26));
+        assert_eq!(find_fork_point(&chain[1..], &block_locator(&chain[..1])), None);
+    }
+}
@@ -0,0 +1,21 @@
+//!
+//! BIP37  Connection Bloom filtering network messages
+//!
+
+use util::bloom::BloomFilter;
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// filterload message
+pub struct FilterLoad {
+    /// The filter the peer should match transactions against
+    pub filter: BloomFilter,
+}
+impl_consensus_encoding!(FilterLoad, filter);
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// filteradd message
+pub struct FilterAdd {
+    /// The element to add to the loaded filter, at most 520 bytes
+    pub data: Vec<u8>,
+}
+impl_consensus_encoding!(FilterAdd, data);
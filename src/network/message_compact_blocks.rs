@@ -0,0 +1,39 @@
+//!
+//! BIP152  Compact Blocks network messages
+//!
+
+use util::bip152;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+/// sendcmpct message
+pub struct SendCmpct {
+    /// Whether new blocks should be announced with `cmpctblock` messages
+    pub send_compact: bool,
+    /// The compact block version, 1 for txid or 2 for wtxid short ids
+    pub version: u64,
+}
+impl_consensus_encoding!(SendCmpct, send_compact, version);
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// cmpctblock message
+pub struct CmpctBlock {
+    /// The compact block
+    pub compact_block: bip152::HeaderAndShortIds,
+}
+impl_consensus_encoding!(CmpctBlock, compact_block);
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// getblocktxn message
+pub struct GetBlockTxn {
+    /// The requested block transactions
+    pub txs_request: bip152::BlockTransactionsRequest,
+}
+impl_consensus_encoding!(GetBlockTxn, txs_request);
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// blocktxn message
+pub struct BlockTxn {
+    /// The requested block transactions
+    pub transactions: bip152::BlockTransactions,
+}
+impl_consensus_encoding!(BlockTxn, transactions);
@@ -18,18 +18,31 @@
 //! of Bitcoin data and network messages.
 //!
 
-use std::fmt;
-use std::io;
-use std::error;
+use core::fmt;
+use io;
+#[cfg(feature = "std")] use std::error;
 
 pub mod constants;
 
+#[cfg(feature = "std")]
 pub mod address;
+#[cfg(feature = "std")]
 pub use self::address::Address;
+#[cfg(feature = "std")]
+pub mod codec;
+#[cfg(feature = "std")]
 pub mod message;
+#[cfg(feature = "std")]
 pub mod message_blockdata;
+#[cfg(feature = "std")]
 pub mod message_network;
+#[cfg(feature = "std")]
 pub mod message_filter;
+#[cfg(feature = "std")]
+pub mod message_compact_blocks;
+#[cfg(feature = "std")]
+pub mod message_bloom;
+#[cfg(feature = "std")]
 pub mod stream_reader;
 
 /// Network error
@@ -60,6 +73,7 @@ impl From<io::Error> for Error {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn description(&self) -> &str {
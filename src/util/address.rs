@@ -36,8 +36,10 @@
 //! let address = Address::p2pkh(&public_key, Network::Bitcoin);
 //! ```
 
-use std::fmt::{self, Display, Formatter};
-use std::str::FromStr;
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
 
 use bech32;
 use hashes::Hash;
@@ -86,9 +88,10 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
-impl ::std::error::Error for Error {
-    fn cause(&self) -> Option<&::std::error::Error> {
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
         match *self {
             Error::Base58(ref e) => Some(e),
             Error::Bech32(ref e) => Some(e),
@@ -388,7 +391,7 @@ impl Display for Address {
                     Network::Testnet | Network::Signet  => "tb",
                     Network::Regtest => "bcrt",
                 };
-                let mut bech32_writer = bech32::Bech32Writer::new(hrp, fmt)?;
+                let mut bech32_writer = bech32::Bech32Writer::new(hrp, bech32::Variant::Bech32, fmt)?;
                 bech32::WriteBase32::write_u5(&mut bech32_writer, ver)?;
                 bech32::ToBase32::write_base32(&prog, &mut bech32_writer)
             }
@@ -420,7 +423,11 @@ impl FromStr for Address {
         };
         if let Some(network) = bech32_network {
             // decode as bech32
-            let (_, payload) = bech32::decode(s)?;
+            let (_, payload, variant) = bech32::decode(s)?;
+            // BIP173 addresses only, bech32m strings fail the checksum
+            if variant != bech32::Variant::Bech32 {
+                return Err(Error::Bech32(bech32::Error::InvalidChecksum));
+            }
             if payload.is_empty() {
                 return Err(Error::EmptyBech32Payload);
             }
@@ -489,8 +496,8 @@ impl FromStr for Address {
     }
 }
 
-impl ::std::fmt::Debug for Address {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+impl ::core::fmt::Debug for Address {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
         write!(f, "{}", self.to_string())
     }
 }
@@ -14,12 +14,13 @@
 //! We refer to the documentation on the types for more information.
 //!
 
-use std::default;
-use std::error;
-use std::fmt::{self, Write};
-use std::ops;
-use std::str::FromStr;
-use std::cmp::Ordering;
+use core::default;
+#[cfg(feature = "std")] use std::error;
+use core::fmt::{self, Write};
+use core::ops;
+use core::str::FromStr;
+use core::cmp::Ordering;
+use prelude::*;
 
 /// A set of denominations in which amounts can be expressed.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -115,6 +116,7 @@ impl fmt::Display for ParseAmountError {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for ParseAmountError {
     fn description(&self) -> &str {
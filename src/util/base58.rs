@@ -14,7 +14,9 @@
 
 //! Base58 encoder and decoder
 
-use std::{error, fmt, str, slice, iter};
+use core::{fmt, str, slice, iter};
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
 
 use hashes::{sha256d, Hash};
 
@@ -52,6 +54,7 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn description(&self) -> &str {
@@ -0,0 +1,320 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP125 Implementation
+//!
+//! Implementation of BIP125 opt-in full replace-by-fee signaling and of the
+//! replacement rules Bitcoin Core applies to transactions conflicting with
+//! the ones already in its mempool, as defined at
+//! https://github.com/bitcoin/bips/blob/master/bip-0125.mediawiki
+//!
+//! Mempool contents are provided by the caller through a lookup function
+//! returning the unconfirmed transaction for a given txid, if any.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
+
+use blockdata::constants::WITNESS_SCALE_FACTOR;
+use blockdata::transaction::{OutPoint, Transaction};
+use hash_types::Txid;
+
+/// The maximum sequence number which still signals replaceability
+pub const MAX_BIP125_RBF_SEQUENCE: u32 = 0xFFFFFFFD;
+/// The maximum number of transactions a replacement may evict from the mempool
+pub const MAX_REPLACEMENT_CANDIDATES: usize = 100;
+/// Default incremental relay fee rate of Bitcoin Core, in satoshis per 1000
+/// virtual bytes
+pub const DEFAULT_INCREMENTAL_RELAY_FEE: u64 = 1000;
+
+/// Reasons for which a replacement transaction would be rejected
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A directly conflicting transaction does not signal replaceability,
+    /// neither explicitly nor through its unconfirmed ancestors
+    NotReplaceable(Txid),
+    /// The replacement spends an output of a transaction it would evict
+    SpendsConflictingTransaction(Txid),
+    /// The replacement spends an unconfirmed output of a transaction which
+    /// was not already spent from by the transactions it replaces
+    NewUnconfirmedInput(OutPoint),
+    /// The replacement would evict too many transactions
+    TooManyReplacements(usize),
+    /// The fee rate of the replacement is not higher than the fee rate of a
+    /// directly conflicting transaction
+    InsufficientFeeRate {
+        /// The conflicting transaction
+        txid: Txid,
+        /// The fee rate of the conflicting transaction, in sat per 1000 vbytes
+        original: u64,
+        /// The fee rate of the replacement, in sat per 1000 vbytes
+        replacement: u64,
+    },
+    /// The replacement pays less absolute fee than the transactions it evicts
+    InsufficientFee {
+        /// The total fee of the evicted transactions
+        required: u64,
+        /// The fee of the replacement
+        actual: u64,
+    },
+    /// The additional fee paid by the replacement does not cover its own
+    /// relay at the incremental relay fee rate
+    InsufficientRelayFee {
+        /// The minimal additional fee
+        required: u64,
+        /// The additional fee paid
+        actual: u64,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotReplaceable(ref txid) => write!(f,
+                "conflicting transaction {} is not replaceable", txid),
+            Error::SpendsConflictingTransaction(ref txid) => write!(f,
+                "replacement spends conflicting transaction {}", txid),
+            Error::NewUnconfirmedInput(ref outpoint) => write!(f,
+                "replacement adds new unconfirmed input {}", outpoint),
+            Error::TooManyReplacements(count) => write!(f,
+                "replacement evicts {} transactions, maximum {}", count, MAX_REPLACEMENT_CANDIDATES),
+            Error::InsufficientFeeRate { ref txid, original, replacement } => write!(f,
+                "replacement fee rate {} is not higher than fee rate {} of {}", replacement, original, txid),
+            Error::InsufficientFee { required, actual } => write!(f,
+                "replacement fee {} is less than evicted fee {}", actual, required),
+            Error::InsufficientRelayFee { required, actual } => write!(f,
+                "replacement additional fee {} is less than relay fee {}", actual, required),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+/// Computes the virtual size of a transaction, i.e. its weight divided by
+/// four and rounded up.
+pub fn vsize(tx: &Transaction) -> usize {
+    (tx.get_weight() + WITNESS_SCALE_FACTOR - 1) / WITNESS_SCALE_FACTOR
+}
+
+/// Computes the fee rate in satoshis per 1000 virtual bytes the same way
+/// Bitcoin Core's `CFeeRate` does, rounding down.
+pub fn fee_rate(fee: u64, vsize: usize) -> u64 {
+    if vsize == 0 { 0 } else { fee.saturating_mul(1000) / vsize as u64 }
+}
+
+/// Computes the fee required at the given rate in satoshis per 1000 virtual
+/// bytes, with the same rounding as Bitcoin Core's `CFeeRate::GetFee`.
+pub fn fee_at_rate(fee_rate: u64, vsize: usize) -> u64 {
+    let fee = fee_rate.saturating_mul(vsize as u64) / 1000;
+    if fee == 0 && vsize != 0 && fee_rate > 0 { 1 } else { fee }
+}
+
+/// Checks whether the transaction explicitly signals replaceability, i.e.
+/// has at least one input with a sequence number not above
+/// `MAX_BIP125_RBF_SEQUENCE`.
+pub fn signals_rbf(tx: &Transaction) -> bool {
+    tx.input.iter().any(|input| input.sequence <= MAX_BIP125_RBF_SEQUENCE)
+}
+
+/// Checks whether the transaction is replaceable, either because it signals
+/// replaceability itself or because one of its unconfirmed ancestors does.
+/// The `mempool` function must return the unconfirmed transaction with the
+/// given txid, or `None` if it is confirmed or unknown.
+pub fn is_replaceable<'a, F>(tx: &Transaction, mempool: F) -> bool
+    where F: Fn(&Txid) -> Option<&'a Transaction>
+{
+    if signals_rbf(tx) {
+        return true;
+    }
+    let mut visited = HashSet::new();
+    let mut queue: Vec<Txid> = tx.input.iter().map(|input| input.previous_output.txid).collect();
+    while let Some(txid) = queue.pop() {
+        if !visited.insert(txid) {
+            continue;
+        }
+        if let Some(ancestor) = mempool(&txid) {
+            if signals_rbf(ancestor) {
+                return true;
+            }
+            queue.extend(ancestor.input.iter().map(|input| input.previous_output.txid));
+        }
+    }
+    false
+}
+
+/// Validates a replacement against the mempool transactions it would evict
+/// according to the BIP125 rules, as enforced by Bitcoin Core.
+///
+/// The `originals` must contain every transaction evicted by the replacement,
+/// i.e. the directly conflicting ones together with all of their descendants,
+/// each paired with its fee. The `mempool` function must return the
+/// unconfirmed transaction with the given txid, or `None` if it is confirmed
+/// or unknown. The incremental relay fee rate is expressed in satoshis per 1000
+/// virtual bytes, see `DEFAULT_INCREMENTAL_RELAY_FEE`.
+pub fn check_replacement<'a, F>(
+    replacement: &Transaction,
+    replacement_fee: u64,
+    originals: &[(&Transaction, u64)],
+    mempool: F,
+    incremental_relay_fee: u64,
+) -> Result<(), Error>
+    where F: Fn(&Txid) -> Option<&'a Transaction>
+{
+    let spent: HashSet<OutPoint> = replacement.input.iter().map(|input| input.previous_output).collect();
+    let replacement_vsize = vsize(replacement);
+    let replacement_rate = fee_rate(replacement_fee, replacement_vsize);
+
+    let evicted: HashSet<Txid> = originals.iter().map(|&(tx, _)| tx.txid()).collect();
+    let mut conflicts_spent = HashSet::new();
+    for &(original, fee) in originals {
+        if !original.input.iter().any(|input| spent.contains(&input.previous_output)) {
+            continue;
+        }
+        let txid = original.txid();
+        if !is_replaceable(original, &mempool) {
+            return Err(Error::NotReplaceable(txid));
+        }
+        let original_rate = fee_rate(fee, vsize(original));
+        if replacement_rate <= original_rate {
+            return Err(Error::InsufficientFeeRate {
+                txid: txid,
+                original: original_rate,
+                replacement: replacement_rate,
+            });
+        }
+        conflicts_spent.extend(original.input.iter().map(|input| input.previous_output.txid));
+    }
+
+    if originals.len() > MAX_REPLACEMENT_CANDIDATES {
+        return Err(Error::TooManyReplacements(originals.len()));
+    }
+
+    for input in &replacement.input {
+        let txid = input.previous_output.txid;
+        if evicted.contains(&txid) {
+            return Err(Error::SpendsConflictingTransaction(txid));
+        }
+        if !conflicts_spent.contains(&txid) && mempool(&txid).is_some() {
+            return Err(Error::NewUnconfirmedInput(input.previous_output));
+        }
+    }
+
+    let evicted_fee = originals.iter().fold(0u64, |sum, &(_, fee)| sum.saturating_add(fee));
+    if replacement_fee < evicted_fee {
+        return Err(Error::InsufficientFee { required: evicted_fee, actual: replacement_fee });
+    }
+    let additional_fee = replacement_fee - evicted_fee;
+    let relay_fee = fee_at_rate(incremental_relay_fee, replacement_vsize);
+    if additional_fee < relay_fee {
+        return Err(Error::InsufficientRelayFee { required: relay_fee, actual: additional_fee });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use hash_types::Txid;
+    use hashes::Hash;
+
+    use super::*;
+
+    fn tx(prevouts: &[(Txid, u32)], sequence: u32, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: prevouts.iter().map(|&(txid, vout)| TxIn {
+                previous_output: OutPoint::new(txid, vout),
+                script_sig: Script::new(),
+                sequence: sequence,
+                witness: vec![],
+            }).collect(),
+            output: vec![TxOut { value: value, script_pubkey: Script::from(vec![0x51; 22]) }],
+        }
+    }
+
+    fn confirmed(n: u8) -> Txid {
+        Txid::hash(&[n])
+    }
+
+    #[test]
+    fn signaling() {
+        let parent = tx(&[(confirmed(0), 0)], 0xFFFFFFFD, 10000);
+        let child = tx(&[(parent.txid(), 0)], 0xFFFFFFFF, 9000);
+        let grandchild = tx(&[(child.txid(), 0)], 0xFFFFFFFE, 8000);
+        assert!(signals_rbf(&parent));
+        assert!(!signals_rbf(&child));
+
+        let mut mempool = HashMap::new();
+        assert!(!is_replaceable(&grandchild, |txid| mempool.get(txid)));
+        mempool.insert(child.txid(), child.clone());
+        assert!(!is_replaceable(&grandchild, |txid| mempool.get(txid)));
+        mempool.insert(parent.txid(), parent.clone());
+        assert!(is_replaceable(&grandchild, |txid| mempool.get(txid)));
+    }
+
+    #[test]
+    fn replacement_rules() {
+        let original = tx(&[(confirmed(0), 0)], 0xFFFFFFFD, 10000);
+        let descendant = tx(&[(original.txid(), 0)], 0xFFFFFFFF, 9000);
+        let unrelated = tx(&[(confirmed(1), 0)], 0xFFFFFFFF, 5000);
+        let mut mempool = HashMap::new();
+        for t in &[&original, &descendant, &unrelated] {
+            mempool.insert(t.txid(), (*t).clone());
+        }
+        let originals = [(&original, 1000), (&descendant, 1000)];
+        let size = vsize(&original) as u64;
+
+        let replacement = tx(&[(confirmed(0), 0)], 0xFFFFFFFD, 9000);
+        assert_eq!(check_replacement(&replacement, 2000 + size, &originals, |t| mempool.get(t), 1000), Ok(()));
+        assert_eq!(check_replacement(&replacement, 2000 + size - 1, &originals, |t| mempool.get(t), 1000),
+                   Err(Error::InsufficientRelayFee { required: size, actual: size - 1 }));
+        assert_eq!(check_replacement(&replacement, 1999, &originals, |t| mempool.get(t), 0),
+                   Err(Error::InsufficientFee { required: 2000, actual: 1999 }));
+        assert_eq!(check_replacement(&replacement, 1000, &originals, |t| mempool.get(t), 0),
+                   Err(Error::InsufficientFeeRate {
+                       txid: original.txid(),
+                       original: fee_rate(1000, size as usize),
+                       replacement: fee_rate(1000, size as usize),
+                   }));
+
+        let non_signaling = tx(&[(confirmed(0), 0)], 0xFFFFFFFE, 10000);
+        assert_eq!(check_replacement(&replacement, 100000, &[(&non_signaling, 1000)], |t| mempool.get(t), 1000),
+                   Err(Error::NotReplaceable(non_signaling.txid())));
+
+        let new_unconfirmed = tx(&[(confirmed(0), 0), (unrelated.txid(), 0)], 0xFFFFFFFD, 9000);
+        assert_eq!(check_replacement(&new_unconfirmed, 100000, &originals, |t| mempool.get(t), 1000),
+                   Err(Error::NewUnconfirmedInput(OutPoint::new(unrelated.txid(), 0))));
+
+        let spends_conflict = tx(&[(confirmed(0), 0), (original.txid(), 1)], 0xFFFFFFFD, 9000);
+        assert_eq!(check_replacement(&spends_conflict, 100000, &originals, |t| mempool.get(t), 1000),
+                   Err(Error::SpendsConflictingTransaction(original.txid())));
+
+        let many: Vec<_> = (0..MAX_REPLACEMENT_CANDIDATES).map(|_| (&descendant, 1)).collect();
+        let mut too_many = vec![(&original, 1000)];
+        too_many.extend(many);
+        assert_eq!(check_replacement(&replacement, 100000, &too_many, |t| mempool.get(t), 1000),
+                   Err(Error::TooManyReplacements(MAX_REPLACEMENT_CANDIDATES + 1)));
+    }
+}
@@ -25,7 +25,8 @@ use blockdata::script::Script;
 use blockdata::transaction::{Transaction, TxIn, SigHashType};
 use consensus::encode::Encodable;
 
-use std::ops::{Deref, DerefMut};
+use core::ops::{Deref, DerefMut};
+use prelude::*;
 
 /// Parts of a sighash which are common across inputs or signatures, and which are
 /// sufficient (in conjunction with a private key) to sign the transaction
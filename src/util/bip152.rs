@@ -0,0 +1,539 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP152 Compact Blocks
+//!
+//! Implementation of the compact block relay data structures, as defined at
+//! https://github.com/bitcoin/bips/blob/master/bip-0152.mediawiki
+//!
+//! A compact block carries the block header, a few prefilled transactions
+//! and 6-byte short identifiers of all other transactions, which the receiver
+//! looks up in its mempool. Transactions which cannot be found are then
+//! requested with `getblocktxn` and delivered with `blocktxn`.
+//!
+//! Version 1 short identifiers are computed from txids, version 2 ones from
+//! wtxids.
+//!
+
+use core::{fmt, mem, u16};
+use io;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
+
+use hashes::{sha256, sha256d, siphash24, Hash, HashEngine};
+use blockdata::block::{Block, BlockHeader};
+use blockdata::transaction::Transaction;
+use consensus::encode::{self, Decodable, Encodable, VarInt};
+use consensus::{DecodeContext, Limit};
+use hash_types::BlockHash;
+use util::endian;
+
+/// Compact block errors
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The compact block version is neither 1 nor 2
+    UnknownVersion(u64),
+    /// Prefilled transaction indexes are out of range or not ascending
+    InvalidPrefill,
+    /// Several transactions of the compact block have the same short id
+    ShortIdCollision,
+    /// The number of transactions provided does not match the number of
+    /// missing ones
+    InvalidBlockTxn,
+    /// The reconstructed block does not match the merkle root of its header
+    BadMerkleRoot,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownVersion(version) => write!(f, "unknown compact block version {}", version),
+            Error::InvalidPrefill => f.write_str("invalid prefilled transaction indexes"),
+            Error::ShortIdCollision => f.write_str("short transaction id collision"),
+            Error::InvalidBlockTxn => f.write_str("wrong number of missing transactions"),
+            Error::BadMerkleRoot => f.write_str("reconstructed block has a bad merkle root"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+/// A short transaction identifier
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default, Debug)]
+pub struct ShortId(pub [u8; 6]);
+
+impl ShortId {
+    /// Computes the SipHash keys used for the short identifiers of a compact
+    /// block with the given header and nonce.
+    pub fn keys(header: &BlockHeader, nonce: u64) -> (u64, u64) {
+        let mut engine = sha256::Hash::engine();
+        header.consensus_encode(&mut engine).expect("engines don't error");
+        engine.input(&endian::u64_to_array_le(nonce));
+        let hash = sha256::Hash::from_engine(engine);
+        (endian::slice_to_u64_le(&hash[0..8]), endian::slice_to_u64_le(&hash[8..16]))
+    }
+
+    /// Computes the short identifier of a transaction hash, which is the txid
+    /// or the wtxid depending on the compact block version.
+    pub fn with_keys(keys: (u64, u64), tx_hash: &sha256d::Hash) -> ShortId {
+        let hash = siphash24::Hash::hash_to_u64_with_keys(keys.0, keys.1, &tx_hash[..]);
+        let mut id = [0u8; 6];
+        id.copy_from_slice(&endian::u64_to_array_le(hash)[0..6]);
+        ShortId(id)
+    }
+}
+
+impl Encodable for ShortId {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, encode::Error> {
+        self.0.consensus_encode(s)
+    }
+}
+
+impl Decodable for ShortId {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Ok(ShortId(Decodable::consensus_decode(d)?))
+    }
+}
+
+/// A transaction sent in full within a compact block
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PrefilledTransaction {
+    /// Index of the transaction in the block. On the wire indexes are
+    /// differentially encoded, relative to the previous prefilled transaction.
+    pub idx: u16,
+    /// The transaction
+    pub tx: Transaction,
+}
+
+/// A compact block, the payload of the `cmpctblock` message
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct HeaderAndShortIds {
+    /// The block header
+    pub header: BlockHeader,
+    /// Nonce used to derive the short identifiers
+    pub nonce: u64,
+    /// Short identifiers of the transactions which are not prefilled, in
+    /// block order
+    pub short_ids: Vec<ShortId>,
+    /// Prefilled transactions in block order, always including the coinbase
+    pub prefilled_txs: Vec<PrefilledTransaction>,
+}
+
+/// A compact block being reconstructed. Transactions which were neither
+/// prefilled nor found in the mempool are `None`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PartialBlock {
+    /// The block header
+    pub header: BlockHeader,
+    /// The block transactions
+    pub txdata: Vec<Option<Transaction>>,
+}
+
+fn tx_hash(tx: &Transaction, version: u64) -> sha256d::Hash {
+    if version == 1 {
+        tx.txid().as_hash()
+    } else {
+        tx.wtxid().as_hash()
+    }
+}
+
+fn check_version(version: u64) -> Result<(), Error> {
+    match version {
+        1 | 2 => Ok(()),
+        _ => Err(Error::UnknownVersion(version)),
+    }
+}
+
+impl HeaderAndShortIds {
+    /// Creates a compact block of the given version. The coinbase is always
+    /// prefilled, `prefill` lists the indexes of further transactions which
+    /// the receiver is unlikely to have, in ascending order.
+    pub fn from_block(block: &Block, nonce: u64, version: u64, prefill: &[usize]) -> Result<HeaderAndShortIds, Error> {
+        check_version(version)?;
+        if block.txdata.is_empty() || block.txdata.len() > u16::MAX as usize + 1 ||
+            prefill.iter().any(|&idx| idx == 0 || idx >= block.txdata.len()) ||
+            prefill.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::InvalidPrefill);
+        }
+
+        let keys = ShortId::keys(&block.header, nonce);
+        let mut short_ids = Vec::with_capacity(block.txdata.len() - 1 - prefill.len());
+        let mut prefilled_txs = Vec::with_capacity(1 + prefill.len());
+        let mut prefill = prefill.iter().peekable();
+        for (idx, tx) in block.txdata.iter().enumerate() {
+            let prefilled = if prefill.peek() == Some(&&idx) {
+                prefill.next();
+                true
+            } else {
+                idx == 0
+            };
+            if prefilled {
+                prefilled_txs.push(PrefilledTransaction { idx: idx as u16, tx: tx.clone() });
+            } else {
+                short_ids.push(ShortId::with_keys(keys, &tx_hash(tx, version)));
+            }
+        }
+        Ok(HeaderAndShortIds {
+            header: block.header,
+            nonce: nonce,
+            short_ids: short_ids,
+            prefilled_txs: prefilled_txs,
+        })
+    }
+
+    /// Starts the reconstruction of the block, filling in the prefilled
+    /// transactions and those found in `mempool` by their short id. If
+    /// several mempool transactions have the same short id, none of them is
+    /// used and the transaction has to be requested.
+    pub fn reconstruct<'a, I>(&self, version: u64, mempool: I) -> Result<PartialBlock, Error>
+        where I: IntoIterator<Item = &'a Transaction>
+    {
+        check_version(version)?;
+        let total = self.short_ids.len() + self.prefilled_txs.len();
+        if total > u16::MAX as usize + 1 {
+            return Err(Error::InvalidPrefill);
+        }
+
+        let mut txdata: Vec<Option<Transaction>> = vec![None; total];
+        for prefilled in &self.prefilled_txs {
+            let idx = prefilled.idx as usize;
+            if idx >= total || txdata[idx].is_some() {
+                return Err(Error::InvalidPrefill);
+            }
+            txdata[idx] = Some(prefilled.tx.clone());
+        }
+
+        let mut positions = HashMap::with_capacity(self.short_ids.len());
+        let mut short_ids = self.short_ids.iter();
+        for (idx, tx) in txdata.iter().enumerate() {
+            if tx.is_none() {
+                let short_id = short_ids.next().expect("one short id per remaining slot");
+                if positions.insert(*short_id, idx).is_some() {
+                    return Err(Error::ShortIdCollision);
+                }
+            }
+        }
+
+        let keys = ShortId::keys(&self.header, self.nonce);
+        for tx in mempool {
+            let short_id = ShortId::with_keys(keys, &tx_hash(tx, version));
+            let idx = match positions.get(&short_id) {
+                Some(&idx) => idx,
+                None => continue,
+            };
+            if txdata[idx].is_some() {
+                // Two mempool transactions match, so the right one is unknown
+                txdata[idx] = None;
+                positions.remove(&short_id);
+            } else {
+                txdata[idx] = Some(tx.clone());
+            }
+        }
+
+        Ok(PartialBlock {
+            header: self.header,
+            txdata: txdata,
+        })
+    }
+}
+
+impl Encodable for HeaderAndShortIds {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = 0;
+        len += self.header.consensus_encode(&mut s)?;
+        len += self.nonce.consensus_encode(&mut s)?;
+        len += self.short_ids.consensus_encode(&mut s)?;
+        len += VarInt(self.prefilled_txs.len() as u64).consensus_encode(&mut s)?;
+        let mut next = 0u64;
+        for prefilled in &self.prefilled_txs {
+            let idx = prefilled.idx as u64;
+            if idx < next {
+                return Err(encode::Error::ParseFailed("prefilled transactions are not in ascending order"));
+            }
+            len += VarInt(idx - next).consensus_encode(&mut s)?;
+            len += prefilled.tx.consensus_encode(&mut s)?;
+            next = idx + 1;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for HeaderAndShortIds {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        let header = Decodable::consensus_decode(&mut d)?;
+        let nonce = Decodable::consensus_decode(&mut d)?;
+        let short_ids = Decodable::consensus_decode_limited(&mut d, ctx)?;
+        let count = VarInt::consensus_decode(&mut d)?.0;
+        ctx.check(Limit::VecLength, count as usize)?;
+        let mut prefilled_txs = vec![];
+        let mut next = 0u64;
+        for _ in 0..count {
+            let idx = next.saturating_add(VarInt::consensus_decode(&mut d)?.0);
+            if idx > u16::MAX as u64 {
+                return Err(encode::Error::ParseFailed("prefilled transaction index overflowed 16 bits"));
+            }
+            prefilled_txs.push(PrefilledTransaction {
+                idx: idx as u16,
+                tx: Decodable::consensus_decode_limited(&mut d, ctx)?,
+            });
+            next = idx + 1;
+        }
+        Ok(HeaderAndShortIds {
+            header: header,
+            nonce: nonce,
+            short_ids: short_ids,
+            prefilled_txs: prefilled_txs,
+        })
+    }
+}
+
+/// A request for block transactions, the payload of the `getblocktxn` message
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct BlockTransactionsRequest {
+    /// Hash of the block
+    pub block_hash: BlockHash,
+    /// Ascending indexes of the requested transactions in the block. On the
+    /// wire indexes are differentially encoded.
+    pub indexes: Vec<u16>,
+}
+
+impl Encodable for BlockTransactionsRequest {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = 0;
+        len += self.block_hash.consensus_encode(&mut s)?;
+        len += VarInt(self.indexes.len() as u64).consensus_encode(&mut s)?;
+        let mut next = 0u64;
+        for &idx in &self.indexes {
+            let idx = idx as u64;
+            if idx < next {
+                return Err(encode::Error::ParseFailed("requested indexes are not in ascending order"));
+            }
+            len += VarInt(idx - next).consensus_encode(&mut s)?;
+            next = idx + 1;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for BlockTransactionsRequest {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        let block_hash = Decodable::consensus_decode(&mut d)?;
+        let count = VarInt::consensus_decode(&mut d)?.0;
+        ctx.check(Limit::VecLength, count as usize)?;
+        ctx.allocate((count as usize).saturating_mul(mem::size_of::<u16>()))?;
+        let mut indexes = vec![];
+        let mut next = 0u64;
+        for _ in 0..count {
+            let idx = next.saturating_add(VarInt::consensus_decode(&mut d)?.0);
+            if idx > u16::MAX as u64 {
+                return Err(encode::Error::ParseFailed("requested index overflowed 16 bits"));
+            }
+            indexes.push(idx as u16);
+            next = idx + 1;
+        }
+        Ok(BlockTransactionsRequest {
+            block_hash: block_hash,
+            indexes: indexes,
+        })
+    }
+}
+
+/// Requested block transactions, the payload of the `blocktxn` message
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct BlockTransactions {
+    /// Hash of the block
+    pub block_hash: BlockHash,
+    /// The requested transactions, in the order of the request
+    pub transactions: Vec<Transaction>,
+}
+impl_consensus_encoding!(BlockTransactions, block_hash, transactions);
+
+impl PartialBlock {
+    /// Returns the indexes of the transactions still missing
+    pub fn missing(&self) -> Vec<u16> {
+        self.txdata.iter().enumerate().filter(|&(_, tx)| tx.is_none()).map(|(idx, _)| idx as u16).collect()
+    }
+
+    /// Builds the `getblocktxn` payload requesting the missing transactions
+    pub fn request(&self) -> BlockTransactionsRequest {
+        BlockTransactionsRequest {
+            block_hash: self.header.block_hash(),
+            indexes: self.missing(),
+        }
+    }
+
+    /// Completes the block with the missing transactions, as received in a
+    /// `blocktxn` message answering [`PartialBlock::request`]. The caller
+    /// is responsible for matching the block hash of that message.
+    pub fn fill(self, transactions: Vec<Transaction>) -> Result<Block, Error> {
+        let mut transactions = transactions.into_iter();
+        let mut txdata = Vec::with_capacity(self.txdata.len());
+        for tx in self.txdata {
+            match tx.or_else(|| transactions.next()) {
+                Some(tx) => txdata.push(tx),
+                None => return Err(Error::InvalidBlockTxn),
+            }
+        }
+        if transactions.next().is_some() {
+            return Err(Error::InvalidBlockTxn);
+        }
+
+        let block = Block {
+            header: self.header,
+            txdata: txdata,
+        };
+        if !block.check_merkle_root() {
+            return Err(Error::BadMerkleRoot);
+        }
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashes::hex::FromHex;
+
+    use blockdata::block::Block;
+    use blockdata::transaction::Transaction;
+    use consensus::encode::{deserialize, serialize, Encodable};
+    use hash_types::BlockHash;
+    use hashes::Hash;
+
+    use super::{BlockTransactions, BlockTransactionsRequest, Error, HeaderAndShortIds, ShortId};
+
+    fn block() -> Block {
+        // A testnet block with segwit transactions
+        deserialize(&Vec::from_hex("000000202aa2f2ca794ccbd40c16e2f3333f6b8b683f9e7179b2c4d7490600000000000010bc26e70a2f672ad420a6153dd0c28b40a6002c55531bfc99bf8994a8e8f67e5503bd5750d4061a4ed90a700f010000000001010000000000000000000000000000000000000000000000000000000000000000ffffffff3603da1b0e00045503bd5704c7dd8a0d0ced13bb5785010800000000000a636b706f6f6c122f4e696e6a61506f6f6c2f5345475749542fffffffff02b4e5a212000000001976a914876fbb82ec05caa6af7a3b5e5a983aae6c6cc6d688ac0000000000000000266a24aa21a9edf91c46b49eb8a29089980f02ee6b57e7d63d33b18b4fddac2bcd7db2a3983704012000000000000000000000000000000000000000000000000000000000000000000000000001000000017e4f81175332a733e26d4ba4e29f53f67b7a5d7c2adebb276e447ca71d130b55000000006b483045022100cac809cd1a3d9ad5d5e31a84e2e1d8ec5542841e4d14c6b52e8b38cbe1ff1728022064470b7fb0c2efeccb2e84bfa36ec5f9e434c84b1101c00f7ee32f726371b7410121020e62280798b6b8c37f068df0915b0865b63fabc401c2457cbc3ef96887dd3647ffffffff02ca2f780c000000001976a914c6b5545b3592cb477d709896fa705592c9b6113a88ac663b2a06000000001976a914e7c1345fc8f87c68170b3aa798a956c2fe6a9eff88ac0000000001000000011e99f5a785e677e017d36b50aa4fd10010ffd039f38f42f447ca8895250e121f01000000d90047304402200d3d296ad641a281dd5c0d68b9ab0d1ad5f7052bec148c1fb81fb1ba69181ec502201a372bb16fb8e054ee9bef41e300d292153830f841a4db0ab7f7407f6581b9bc01473044022002584f313ae990236b6bebb82fbbb006a2b02a448dd5c93434428991eae960d60220491d67d2660c4dde19025cf86e5164a559e2c79c3b98b40e146fab974acd24690147522102632178d046673c9729d828cfee388e121f497707f810c131e0d3fc0fe0bd66d62103a0951ec7d3a9da9de171617026442fcd30f34d66100fab539853b43f508787d452aeffffffff0240420f000000000017a9140ffdcf96700455074292a821c74922e8652993998788997bc60000000017a9148ce5408cfeaddb7ccb2545ded41ef478109454848700000000010000000113100b09e6a78d63ec4850654ab0f68806de29710b09172eddfef730652b155501000000da00473044022015389408e3446a3f36a05060e0e4a3c8b92ff3901ba2511aa944ec91a537a1cb022045a33b6ec47605b1718ed2e753263e54918edbf6126508ff039621fb928d28a001483045022100bb952fde81f216f7063575c0bb2bedc050ce08c96d9b437ea922f5eb98c882da02201b7cbf3a2f94ea4c5eb7f0df3af2ebcafa8705af7f410ab5d3d4bac13d6bc6120147522102632178d046673c9729d828cfee388e121f497707f810c131e0d3fc0fe0bd66d62103a0951ec7d3a9da9de171617026442fcd30f34d66100fab539853b43f508787d452aeffffffff0240420f000000000017a914d3db9a20312c3ab896a316eb108dbd01e47e17d687e0ba7ac60000000017a9148ce5408cfeaddb7ccb2545ded41ef47810945484870000000001000000016e3cca1599cde54878e2f27f434df69df0afd1f313cb6e38c08d3ffb57f97a6c01000000da0048304502210095623b70ec3194fa4037a1c1106c2580caedc390e25e5b330bbeb3111e8184bc02205ae973c4a4454be2a3a03beb66297143c1044a3c4743742c5cdd1d516a1ad3040147304402202f3d6d89996f5b42773dd6ebaf367f1af1f3a95c7c7b487ec040131c40f4a4a30220524ffbb0b563f37b3eb1341228f792e8f84111b7c4a9f49cdd998e052ee42efa0147522102632178d046673c9729d828cfee388e121f497707f810c131e0d3fc0fe0bd66d62103a0951ec7d3a9da9de171617026442fcd30f34d66100fab539853b43f508787d452aeffffffff0240420f000000000017a9141ade6b95896dde8ec4dee9e59af8849d3797348e8728af7ac60000000017a9148ce5408cfeaddb7ccb2545ded41ef47810945484870000000001000000011d9dc3a5df9b5b2eeb2bd11a2db243be9e8cc23e2f180bf317d32a499904c15501000000db00483045022100ebbd1c9a8ce626edbb1a7881df81e872ef8c6424feda36faa8a5745157400c6a02206eb463bc8acd5ea06a289e86115e1daae0c2cf10d9cbbd199e1311170d5543ef01483045022100809411a917dc8cf4f3a777f0388fdea6de06243ef7691e500c60abd1c7f19ae602205255d2b1191d8adedb77b814ccb66471eb8486cb4ff8727824254ee5589f176b0147522102632178d046673c9729d828cfee388e121f497707f810c131e0d3fc0fe0bd66d62103a0951ec7d3a9da9de171617026442fcd30f34d66100fab539853b43f508787d452aeffffffff0240420f000000000017a914759a49c772347be81c49517f9e1e6def6a88d4dd87800b85c60000000017a9148ce5408cfeaddb7ccb2545ded41ef47810945484870000000001000000018c51902affd8e5247dfcc2e5d0528a3815f53c8b6d2c200ff290b2b2b486d7704f0000006a47304402201be0d485f6a3ce871be80064c593c5327b3fd7e450f05ab7fae38385bc40cfbe02206e2a6c9970b5d1d10207892376733757486634fce4f352e772149c486857612101210350c33bc9a790c9495195761577b34912a949b73d5bc5ae5343f5ba08b33220ccffffffff0110270000000000001976a9142ab1c62710a7bdfdb4bb6394bbedc58b32b4d5a388ac0000000001000000018c51902affd8e5247dfcc2e5d0528a3815f53c8b6d2c200ff290b2b2b486d7704e0000006b483045022100ccc8c0ac90bdb0402842aec91830c765cdead7a728552a6a34de7d13a6dab28e02206c96f8640cf3444054e9632b197be30598a09c3d5defcd95750bdb922a60d64801210350c33bc9a790c9495195761577b34912a949b73d5bc5ae5343f5ba08b33220ccffffffff0110270000000000001976a9142ab1c62710a7bdfdb4bb6394bbedc58b32b4d5a388ac0000000001000000011b436669c06cbf3442e21a2fe3edc20cd3cf13c358c53234bc4d88bfd8c4bd2a000000006a47304402204a63410ee13db52c7609ab08e25b7fe3c608cc21cc1755ad13460685eb55193202204cd1ea80c06a81571119be0b8cccd96ef7cdd90f62c1fe2d538622feb08e22ba0121024baa8b67cc9ed8a97d90895e3716b25469b67cb26d3324d7aff213f507764765ffffffff010000000000000000306a2e516d64523365345261445653324d436a736e536171734a5753324465655446624238354541794a4d5843784c7934000000000100000001be4a95ed36316cada5118b1982e4cb4a07f93e7a4153e227466f1cb0776de995000000006b483045022100a22d5251deea0470806bab817013d675a63cd52218d6e477ab0c9d601d018b7f022042121b46afcdcd0c66f189398212b66085e88c6973ae560f1810c13e55e2bee40121024baa8b67cc9ed8a97d90895e3716b25469b67cb26d3324d7aff213f507764765ffffffff010000000000000000306a2e516d57484d57504e5248515872504c7338554c586b4d483746745356413675366b5a6b4a4e3851796e4e583751340000000001000000016c061a65b49edec21acdbc22f97dc853aa872302aeef13fabf0bf6807de1b8bd010000006b483045022100dd80381f2d158b4dad7f98d2d97317c533fb36e737542473feb05fa74d0b73bb02207097d4331196069167e525b61d132532292fd75cc039a5839c04c2545d427e2b0121035e9a597df8b417bef66811882a2844604fc591c427f642628f0fef46be19a4c9feffffff0280a4bf07000000001976a914573b9106e16ee0b5c143dc40f0724f77dd0e282088ac9533b22c000000001976a9149c4da607efb1d759d33da71778bc6cafa56acb5988acd31b0e0001000000017dae20994b69b28534e5b22f3d7c50f9d7541348cbf6f43fcc654263ebaf8f68000000006b483045022100a85300eb94b24b044877d0b0d61e08e16dbc82ec7d69c723a8a45519f95c35b002203d78376e6bee31b455c097557af7fe4d6b620bc74269e9a75e2aad2b545abddb012103b0d08aba2a5ac6cf2788fda941c386040e35e49d3a57d2aefb16c0438fb98acbfeffffff022222305f000000001976a914cfda30dd836b596db6a9c230c45ae2179107f04888ac80a4bf07000000001976a91442dfcf5823aacb185844e663873c35fb98bfd21b88acd31b0e000100000002ad3e85e4af30678a330f8941ed7a9ca17cd0236368d238cac4e9ff09c466fed1020000006b483045022100d1196c48a0392e09592f1b96b4aec32ab0cecb6fd17b1d0c85ab3250a2fe45d9022059217c82f684fcdecdbe660a2077ea956dfbbb964d2648bc1e8ae0f0fe565449012103b64e32e5f62e03701428fb1e3151e9a57f149c67708f6164a235c8199fe17cc2ffffffff34f0a71c1c2cd610522e9c18c67931cded5e9647d4419c49b99715e2a0795f3d020000006a4730440220316e81d8242abf3c5f885d200feca12c3adb63cf2cd4dc74602f7b8b0cba50340220210d525758df77ccdca6908311c1895275e07bbb29b45963a19252acde55873f012103b64e32e5f62e03701428fb1e3151e9a57f149c67708f6164a235c8199fe17cc2ffffffff0510270000000000001976a914449d2394dde057bc199f23fb8aa2e400f344611788ac10270000000000001976a914449d2394dde057bc199f23fb8aa2e400f344611788aca0860100000000001976a91413d35ad337dd80a055757e5ea0a45b59fee3060c88ac70110100000000001976a91413d35ad337dd80a055757e5ea0a45b59fee3060c88ac0000000000000000026a000000000001000000018e33fecc2ddbd86c5ea919f7bd5a5acf8a09f3e0cdaaaf4f08c5ef095161ef1100000000fdfe0000483045022100d2489b225d39b7d8b6767a6928c8029a2a1297c08fdf00d683ba0c1987e7d7000220176cb66c8a243806bb7421f658325a69a51c82c0c3314e37f2400f33626390210148304502210096cfa57662a545830d0e29610becd41ea031e256339913718ce18dbb1a27bdb00220482911c851d15adcd37097dff99a9ff1f97d953bcebc528835118f447412553e014c695221028d9889862b29430278c084b5c4090b7b807b31e047bcd212ebc2c4e43fc0e3c52103160949a7c8c81f2c25d7763f57eb1cb407d867c5b7c290331bd2dc4b1182c6d32103fbef3b60914bda9173765902013a251ec89450c75d0b5a96a143db1dabf98d9553aeffffffff0220e8891c0100000017a914d996715e081c50f8f6b1b4e7fb6ca214f9924fdf87809698000000000017a9145611d812263f32960228cb5f85329bce4770a218870000000001000000017720507dcbe6c69f652b0c0ce19406f482372d1a8abc05d45fb7acf97fb80eec00000000fdfe00004830450221009821d8e117de44b1202c829c0f5063997acf007cf9b561c6fb8d1212cddb6c40022010ff5067b0d9d4eca2da0ceb876e9a16f1a2142da866d3042a7bae8968813e8001483045022100dea759d14a8a1c5da5f3dcc5509871aaa2c1e3be03752c1b858d80fa4227163702205183d70cc28dcb6df9b037714c8b6442ef84e0ddce07711a30c731e9f0925090014c695221028d70ea66fe7a7def282df7b2b498007e5072933e42c18f63ce85975dcbcf1a8821037e8f842b1e47e21d88002c5aab2559212a4c2c9dbe5ef5347f2a29afd0510ec1210251259cb9fd4f6206488408286e4475c9c9fe887e57a3e32ae4da222778a2aedf53aeffffffff023380cb020000000017a9143b5a7e85b22656a34d43187ac8dd09acd7109d2487809698000000000017a914b9b4b555f594a34deec3ad61d5c5f3738b17ee158700000000").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn short_ids() {
+        // Computed independently with a SipHash-2-4 implementation checked
+        // against the reference vectors of the SipHash paper
+        let block = block();
+        let keys = ShortId::keys(&block.header, 42);
+        assert_eq!(keys, (0xb18b1d8fb7ac9c9e, 0xab7d67e264503b18));
+
+        let coinbase = &block.txdata[0];
+        assert_eq!(ShortId::with_keys(keys, &coinbase.txid().as_hash()).0, [0xb3, 0x56, 0x44, 0xb0, 0xf6, 0x39]);
+        assert_eq!(ShortId::with_keys(keys, &coinbase.wtxid().as_hash()).0, [0x02, 0x2e, 0x33, 0xc1, 0xe3, 0xad]);
+
+        let short_id = ShortId([0x53, 0x23, 0xc6, 0x9f, 0xb1, 0x30]);
+        assert_eq!(ShortId::with_keys(keys, &block.txdata[1].txid().as_hash()), short_id);
+        for &version in &[1, 2] {
+            let compact = HeaderAndShortIds::from_block(&block, 42, version, &[]).unwrap();
+            assert_eq!(compact.short_ids[0], short_id);
+        }
+    }
+
+    #[test]
+    fn compact_block_roundtrip() {
+        let block = block();
+        assert_eq!(HeaderAndShortIds::from_block(&block, 0, 3, &[]), Err(Error::UnknownVersion(3)));
+        assert_eq!(HeaderAndShortIds::from_block(&block, 0, 2, &[0]), Err(Error::InvalidPrefill));
+        assert_eq!(HeaderAndShortIds::from_block(&block, 0, 2, &[3, 2]), Err(Error::InvalidPrefill));
+        assert_eq!(HeaderAndShortIds::from_block(&block, 0, 2, &[block.txdata.len()]), Err(Error::InvalidPrefill));
+
+        let compact = HeaderAndShortIds::from_block(&block, 42, 2, &[2, 5]).unwrap();
+        assert_eq!(compact.short_ids.len(), block.txdata.len() - 3);
+        let prefilled: Vec<u16> = compact.prefilled_txs.iter().map(|p| p.idx).collect();
+        assert_eq!(prefilled, vec![0, 2, 5]);
+
+        let encoded = serialize(&compact);
+        // Three prefilled transactions after header, nonce and short ids, the
+        // first one at differential index 0
+        let prefill_start = 80 + 8 + 1 + 6 * compact.short_ids.len();
+        assert_eq!(encoded[prefill_start..prefill_start + 2], [3, 0]);
+        assert_eq!(deserialize::<HeaderAndShortIds>(&encoded).unwrap(), compact);
+    }
+
+    #[test]
+    fn reconstruct() {
+        let block = block();
+        for &version in &[1, 2] {
+            let compact = HeaderAndShortIds::from_block(&block, 7, version, &[]).unwrap();
+            assert_eq!(compact.reconstruct(5, &[]), Err(Error::UnknownVersion(5)));
+
+            let partial = compact.reconstruct(version, &block.txdata[1..]).unwrap();
+            assert!(partial.missing().is_empty());
+            assert_eq!(partial.fill(vec![]).unwrap(), block);
+
+            // Transactions 3 and 7 are not in the mempool, but unrelated ones are
+            let unrelated = Transaction { version: 9, lock_time: 0, input: vec![], output: vec![] };
+            let mempool: Vec<&Transaction> = block.txdata.iter().enumerate()
+                .filter(|&(i, _)| i != 3 && i != 7)
+                .map(|(_, tx)| tx)
+                .chain(Some(&unrelated))
+                .collect();
+            let partial = compact.reconstruct(version, mempool).unwrap();
+            let request = partial.request();
+            assert_eq!(request, BlockTransactionsRequest { block_hash: block.block_hash(), indexes: vec![3, 7] });
+            assert_eq!(partial.clone().fill(vec![block.txdata[3].clone()]), Err(Error::InvalidBlockTxn));
+            assert_eq!(partial.clone().fill(vec![block.txdata[7].clone(), block.txdata[3].clone()]),
+                       Err(Error::BadMerkleRoot));
+            assert_eq!(partial.fill(vec![block.txdata[3].clone(), block.txdata[7].clone()]).unwrap(), block);
+        }
+
+        let mut compact = HeaderAndShortIds::from_block(&block, 7, 2, &[]).unwrap();
+        compact.short_ids[1] = compact.short_ids[0];
+        assert_eq!(compact.reconstruct(2, &[]), Err(Error::ShortIdCollision));
+        compact.prefilled_txs[0].idx = 100;
+        assert_eq!(compact.reconstruct(2, &[]), Err(Error::InvalidPrefill));
+    }
+
+    #[test]
+    fn short_id_collision_in_mempool() {
+        let block = block();
+        let compact = HeaderAndShortIds::from_block(&block, 0, 1, &[]).unwrap();
+        // The same transaction twice stands in for two colliding ones
+        let mempool = vec![&block.txdata[1], &block.txdata[1]];
+        let partial = compact.reconstruct(1, mempool).unwrap();
+        assert!(partial.txdata[1].is_none());
+        assert_eq!(partial.missing().len(), block.txdata.len() - 1);
+    }
+
+    #[test]
+    fn block_transactions_encoding() {
+        let request = BlockTransactionsRequest { block_hash: BlockHash::hash(&[1]), indexes: vec![0, 1, 5, 6, 300] };
+        let encoded = serialize(&request);
+        assert_eq!(encoded[32..], [5, 0, 0, 3, 0, 0xfd, 0x25, 0x01]);
+        assert_eq!(deserialize::<BlockTransactionsRequest>(&encoded).unwrap(), request);
+
+        let mut overflow = encoded[..33].to_vec();
+        overflow.extend_from_slice(&[0xfe, 0x00, 0x00, 0x01, 0x00]);
+        assert!(deserialize::<BlockTransactionsRequest>(&overflow).is_err());
+
+        let unordered = BlockTransactionsRequest { block_hash: BlockHash::hash(&[1]), indexes: vec![5, 1] };
+        assert!(unordered.consensus_encode(&mut vec![]).is_err());
+
+        let txs = BlockTransactions { block_hash: BlockHash::hash(&[1]), transactions: block().txdata };
+        assert_eq!(deserialize::<BlockTransactions>(&serialize(&txs)).unwrap(), txs);
+    }
+}
@@ -44,12 +44,13 @@
 //!
 //!
 
-use std::{cmp, fmt, io};
-use std::collections::HashSet;
-use std::error;
-use std::fmt::{Display, Formatter};
-use std::io::Cursor;
-use std::cmp::Ordering;
+use core::{cmp, fmt};
+use io;
+#[cfg(feature = "std")] use std::error;
+use core::fmt::{Display, Formatter};
+use io::Cursor;
+use core::cmp::Ordering;
+use prelude::*;
 
 
 use hashes::{Hash, siphash24};
@@ -75,6 +76,7 @@ pub enum Error {
     Io(io::Error),
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn description(&self) -> &str {
@@ -122,14 +124,14 @@ impl BlockFilter {
     /// Compute a SCRIPT_FILTER that contains spent and output scripts
     pub fn new_script_filter<M>(block: &Block, script_for_coin: M) -> Result<BlockFilter, Error>
         where M: Fn(&OutPoint) -> Result<Script, Error> {
-        let mut out = Cursor::new(Vec::new());
+        let mut out = Vec::new();
         {
             let mut writer = BlockFilterWriter::new(&mut out, block);
             writer.add_output_scripts();
             writer.add_input_scripts(script_for_coin)?;
             writer.finish()?;
         }
-        Ok(BlockFilter { content: out.into_inner() })
+        Ok(BlockFilter { content: out })
     }
 
     /// match any query pattern
@@ -373,9 +375,9 @@ impl<'a> GCSFilterWriter<'a> {
         mapped.sort();
 
         // write number of elements as varint
-        let mut encoder = io::Cursor::new(Vec::new());
+        let mut encoder = Vec::new();
         VarInt(mapped.len() as u64).consensus_encode(&mut encoder).unwrap();
-        let mut wrote = self.writer.write(encoder.into_inner().as_slice())?;
+        let mut wrote = self.writer.write(encoder.as_slice())?;
 
         // write out deltas of sorted values into a Golonb-Rice coded bit stream
         let mut writer = BitStreamWriter::new(self.writer);
@@ -521,7 +523,7 @@ impl<'a> BitStreamWriter<'a> {
 #[cfg(test)]
 mod test {
     use std::collections::{HashSet, HashMap};
-    use std::io::Cursor;
+    use io::Cursor;
 
     use hash_types::BlockHash;
     use hashes::hex::FromHex;
@@ -620,7 +622,7 @@ mod test {
         patterns.insert(hex::decode("eeeeee").unwrap());
         patterns.insert(hex::decode("ffffff").unwrap());
 
-        let mut out = Cursor::new(Vec::new());
+        let mut out = Vec::new();
         {
             let mut writer = GCSFilterWriter::new(&mut out, 0, 0, M, P);
             for p in &patterns {
@@ -629,7 +631,7 @@ mod test {
             writer.finish().unwrap();
         }
 
-        let bytes = out.into_inner();
+        let bytes = out;
 
         {
             let mut query = Vec::new();
@@ -672,7 +674,7 @@ mod test {
 
     #[test]
     fn test_bit_stream() {
-        let mut out = Cursor::new(Vec::new());
+        let mut out = Vec::new();
         {
             let mut writer = BitStreamWriter::new(&mut out);
             writer.write(0, 1).unwrap(); // 0
@@ -684,7 +686,7 @@ mod test {
             writer.write(7, 7).unwrap(); // 0000111
             writer.flush().unwrap();
         }
-        let bytes = out.into_inner();
+        let bytes = out;
         assert_eq!("01011010110000110000000001110000", format!("{:08b}{:08b}{:08b}{:08b}", bytes[0], bytes[1], bytes[2], bytes[3]));
         {
             let mut input = Cursor::new(bytes);
@@ -16,9 +16,11 @@
 //! Implementation of BIP32 hierarchical deterministic wallets, as defined
 //! at https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
 
-use std::default::Default;
-use std::{error, fmt};
-use std::str::FromStr;
+use core::default::Default;
+use core::fmt;
+#[cfg(feature = "std")] use std::error;
+use core::str::FromStr;
+use prelude::*;
 #[cfg(feature = "serde")] use serde;
 
 use hash_types::XpubIdentifier;
@@ -112,7 +114,7 @@ pub struct KeyVersion([u8; 4]);
 /// Trait which must be implemented by helpers which do construction,
 /// interpretation, verification and cross-conversion of extended public and
 /// private key version magic bytes from [KeyVersion]
-pub trait VersionResolver: Copy + Clone + PartialEq + Eq + PartialOrd + Ord + ::std::hash::Hash + fmt::Debug {
+pub trait VersionResolver: Copy + Clone + PartialEq + Eq + PartialOrd + Ord + ::core::hash::Hash + fmt::Debug {
     /// Type that defines recognized network options
     type Network;
 
@@ -256,7 +258,7 @@ pub struct ExtendedPrivKey<R: VersionResolver = DefaultResolver> {
     pub private_key: PrivateKey,
     /// Chain code
     pub chain_code: ChainCode,
-    _marker: ::std::marker::PhantomData<R>
+    _marker: ::core::marker::PhantomData<R>
 }
 serde_string_impl!(ExtendedPrivKey<R: VersionResolver>, "a BIP-32 extended private key");
 
@@ -276,7 +278,7 @@ pub struct ExtendedPubKey<R: VersionResolver = DefaultResolver> {
     pub public_key: PublicKey,
     /// Chain code
     pub chain_code: ChainCode,
-    _marker: ::std::marker::PhantomData<R>
+    _marker: ::core::marker::PhantomData<R>
 }
 serde_string_impl!(ExtendedPubKey<R: VersionResolver>, "a BIP-32 extended public key");
 
@@ -450,15 +452,15 @@ impl<'a> From<&'a [ChildNumber]> for DerivationPath {
     }
 }
 
-impl ::std::iter::FromIterator<ChildNumber> for DerivationPath {
+impl ::core::iter::FromIterator<ChildNumber> for DerivationPath {
     fn from_iter<T>(iter: T) -> Self where T: IntoIterator<Item = ChildNumber> {
         DerivationPath(Vec::from_iter(iter))
     }
 }
 
-impl<'a> ::std::iter::IntoIterator for &'a DerivationPath {
+impl<'a> ::core::iter::IntoIterator for &'a DerivationPath {
     type Item = &'a ChildNumber;
-    type IntoIter = ::std::slice::Iter<'a, ChildNumber>;
+    type IntoIter = ::core::slice::Iter<'a, ChildNumber>;
     fn into_iter(self) -> Self::IntoIter {
         self.0.iter()
     }
@@ -617,6 +619,7 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
        if let Error::Ecdsa(ref e) = *self {
@@ -0,0 +1,187 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP69 Implementation
+//!
+//! Implementation of BIP69 lexicographical indexing of transaction inputs and
+//! outputs, as defined at
+//! https://github.com/bitcoin/bips/blob/master/bip-0069.mediawiki
+//!
+//! Inputs are ordered by the previous transaction hash, compared as in its
+//! usual (byte-reversed) hex representation, and then by the previous output
+//! index. Outputs are ordered by their amount and then by their scriptPubkey
+//! bytes.
+//!
+
+use core::cmp::Ordering;
+use prelude::*;
+
+use blockdata::transaction::{Transaction, TxIn, TxOut};
+use util::psbt::PartiallySignedTransaction;
+
+/// Compares two transaction inputs according to BIP69.
+pub fn cmp_inputs(a: &TxIn, b: &TxIn) -> Ordering {
+    let a_txid = a.previous_output.txid[..].iter().rev();
+    let b_txid = b.previous_output.txid[..].iter().rev();
+    a_txid.cmp(b_txid)
+        .then(a.previous_output.vout.cmp(&b.previous_output.vout))
+}
+
+/// Compares two transaction outputs according to BIP69.
+pub fn cmp_outputs(a: &TxOut, b: &TxOut) -> Ordering {
+    a.value.cmp(&b.value)
+        .then_with(|| a.script_pubkey[..].cmp(&b.script_pubkey[..]))
+}
+
+/// Sorts inputs and outputs of the transaction according to BIP69.
+pub fn sort_transaction(tx: &mut Transaction) {
+    tx.input.sort_by(cmp_inputs);
+    tx.output.sort_by(cmp_outputs);
+}
+
+/// Checks whether inputs and outputs of the transaction are ordered
+/// according to BIP69.
+pub fn is_sorted(tx: &Transaction) -> bool {
+    tx.input.windows(2).all(|w| cmp_inputs(&w[0], &w[1]) != Ordering::Greater) &&
+        tx.output.windows(2).all(|w| cmp_outputs(&w[0], &w[1]) != Ordering::Greater)
+}
+
+/// Sorts inputs and outputs of the unsigned transaction of a PSBT according
+/// to BIP69, reordering the PSBT input and output maps in lockstep so that
+/// each map keeps describing the same transaction input or output.
+///
+/// # Panics
+/// Panics if the number of PSBT input or output maps does not match the
+/// number of inputs or outputs in the unsigned transaction.
+pub fn sort_psbt(psbt: &mut PartiallySignedTransaction) {
+    let tx = &mut psbt.global.unsigned_tx;
+    assert_eq!(tx.input.len(), psbt.inputs.len());
+    assert_eq!(tx.output.len(), psbt.outputs.len());
+
+    let mut inputs: Vec<_> = tx.input.drain(..).zip(psbt.inputs.drain(..)).collect();
+    inputs.sort_by(|a, b| cmp_inputs(&a.0, &b.0));
+    for (txin, input) in inputs {
+        tx.input.push(txin);
+        psbt.inputs.push(input);
+    }
+
+    let mut outputs: Vec<_> = tx.output.drain(..).zip(psbt.outputs.drain(..)).collect();
+    outputs.sort_by(|a, b| cmp_outputs(&a.0, &b.0));
+    for (txout, output) in outputs {
+        tx.output.push(txout);
+        psbt.outputs.push(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use hashes::hex::FromHex;
+
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, SigHashType, Transaction, TxIn, TxOut};
+    use hash_types::Txid;
+    use util::psbt::{Global, Input, Output, PartiallySignedTransaction};
+
+    use super::{is_sorted, sort_psbt, sort_transaction};
+
+    fn txin(txid: &str, vout: u32) -> TxIn {
+        TxIn {
+            previous_output: OutPoint::new(Txid::from_hex(txid).unwrap(), vout),
+            ..Default::default()
+        }
+    }
+
+    fn txout(value: u64, script: &str) -> TxOut {
+        TxOut {
+            value: value,
+            script_pubkey: Script::from(Vec::from_hex(script).unwrap()),
+        }
+    }
+
+    fn unsorted_tx() -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![
+                txin("0e53ec5dfb2cb8a71fec32dc9a634a35b7e24799295ddd5278217822e0b31f57", 1),
+                txin("0100000000000000000000000000000000000000000000000000000000000000", 0),
+                txin("0e53ec5dfb2cb8a71fec32dc9a634a35b7e24799295ddd5278217822e0b31f57", 0),
+                // Greatest in internal byte order, but least in hex
+                txin("00000000000000000000000000000000000000000000000000000000000000ff", 0),
+            ],
+            output: vec![
+                txout(40000000000, "76a9145be32612930b8323add2212a4ec03c1562084f8488ac"),
+                txout(400057456, "76a9144a5fba237213a062f6f57978f796390bdcf8d01588ac"),
+                txout(400057456, "0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            ],
+        }
+    }
+
+    #[test]
+    fn sort_tx() {
+        let mut tx = unsorted_tx();
+        assert!(!is_sorted(&tx));
+        sort_transaction(&mut tx);
+        assert!(is_sorted(&tx));
+
+        let order: Vec<_> = tx.input.iter().map(|i| i.previous_output).collect();
+        assert_eq!(order, vec![
+            OutPoint::new(Txid::from_hex("00000000000000000000000000000000000000000000000000000000000000ff").unwrap(), 0),
+            OutPoint::new(Txid::from_hex("0100000000000000000000000000000000000000000000000000000000000000").unwrap(), 0),
+            OutPoint::new(Txid::from_hex("0e53ec5dfb2cb8a71fec32dc9a634a35b7e24799295ddd5278217822e0b31f57").unwrap(), 0),
+            OutPoint::new(Txid::from_hex("0e53ec5dfb2cb8a71fec32dc9a634a35b7e24799295ddd5278217822e0b31f57").unwrap(), 1),
+        ]);
+        assert_eq!(tx.output, vec![
+            txout(400057456, "0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            txout(400057456, "76a9144a5fba237213a062f6f57978f796390bdcf8d01588ac"),
+            txout(40000000000, "76a9145be32612930b8323add2212a4ec03c1562084f8488ac"),
+        ]);
+    }
+
+    #[test]
+    fn sort_psbt_lockstep() {
+        let tx = unsorted_tx();
+        let inputs: Vec<_> = tx.input.iter().map(|txin| Input {
+            redeem_script: Some(Script::from(txin.previous_output.txid[..].to_vec())),
+            sighash_type: Some(SigHashType::All),
+            ..Default::default()
+        }).collect();
+        let outputs: Vec<_> = tx.output.iter().map(|txout| Output {
+            redeem_script: Some(txout.script_pubkey.clone()),
+            ..Default::default()
+        }).collect();
+        let mut psbt = PartiallySignedTransaction {
+            global: Global {
+                unsigned_tx: tx,
+                xpub: BTreeMap::new(),
+                version: 0,
+                proprietary: BTreeMap::new(),
+                unknown: BTreeMap::new(),
+            },
+            inputs: inputs,
+            outputs: outputs,
+        };
+
+        sort_psbt(&mut psbt);
+        assert!(is_sorted(&psbt.global.unsigned_tx));
+        for (txin, input) in psbt.global.unsigned_tx.input.iter().zip(psbt.inputs.iter()) {
+            assert_eq!(input.redeem_script, Some(Script::from(txin.previous_output.txid[..].to_vec())));
+        }
+        for (txout, output) in psbt.global.unsigned_tx.output.iter().zip(psbt.outputs.iter()) {
+            assert_eq!(output.redeem_script.as_ref(), Some(&txout.script_pubkey));
+        }
+    }
+}
@@ -0,0 +1,308 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Block files
+//!
+//! Reading blocks out of the `blocks/blk*.dat` files of a Bitcoin Core data
+//! directory. Each block is stored after the network magic and its length as
+//! little-endian 32-bit integers. Files are preallocated, so they may end with
+//! zero padding, and since Bitcoin Core 28.0 they are obfuscated by XORing
+//! them with the 8 bytes key stored in `blocks/xor.dat`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use std::path::Path;
+//! use bitcoin::util::blockfile::{self, BlockFileReader};
+//!
+//! let blocks_dir = Path::new("/home/user/.bitcoin/blocks");
+//! let key = blockfile::read_xor_key(blocks_dir).unwrap();
+//! let file = File::open(blockfile::block_file_path(blocks_dir, 0)).unwrap();
+//! for raw in BlockFileReader::with_xor_key(BufReader::new(file), key) {
+//!     let raw = raw.unwrap();
+//!     let block = raw.block().unwrap();
+//!     println!("{} at offset {}", block.block_hash(), raw.offset);
+//! }
+//! ```
+//!
+
+use std::{error, fmt, fs, io};
+use std::path::{Path, PathBuf};
+
+use blockdata::block::Block;
+use blockdata::constants::MAX_BLOCK_WEIGHT;
+use consensus::encode;
+use network::constants::Network;
+use util::endian;
+
+/// Block file reading errors
+#[derive(Debug)]
+pub enum Error {
+    /// I/O error, including files ending in the middle of a block
+    Io(io::Error),
+    /// A block length is below the header size or above the maximum block size
+    BadLength {
+        /// Offset of the length in the file
+        offset: u64,
+        /// The invalid length
+        length: u32,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::BadLength { offset, length } => write!(f, "invalid block length {} at offset {}", length, offset),
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::BadLength { .. } => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+#[doc(hidden)]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Returns the path of the block file with the given index in a `blocks`
+/// directory.
+pub fn block_file_path(blocks_dir: &Path, index: u32) -> PathBuf {
+    blocks_dir.join(format!("blk{:05}.dat", index))
+}
+
+/// Reads the obfuscation key of the block files of a `blocks` directory.
+/// Directories of nodes which predate obfuscation have no key file, which is
+/// equivalent to an all zero key.
+pub fn read_xor_key(blocks_dir: &Path) -> io::Result<[u8; 8]> {
+    let mut key = [0; 8];
+    match fs::File::open(blocks_dir.join("xor.dat")) {
+        Ok(mut file) => io::Read::read_exact(&mut file, &mut key)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e),
+    }
+    Ok(key)
+}
+
+/// A serialized block read from a block file
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RawBlock {
+    /// The network magic preceding the block
+    pub magic: u32,
+    /// Offset of the serialized block in the file
+    pub offset: u64,
+    /// The serialized block
+    pub data: Vec<u8>,
+}
+
+impl RawBlock {
+    /// Returns the network of the block, if its magic is a known one.
+    pub fn network(&self) -> Option<Network> {
+        Network::from_magic(self.magic)
+    }
+
+    /// Decodes the block.
+    pub fn block(&self) -> Result<Block, encode::Error> {
+        encode::deserialize(&self.data)
+    }
+}
+
+/// Iterator over the blocks of a block file, read from its beginning
+pub struct BlockFileReader<R> {
+    reader: R,
+    key: [u8; 8],
+    offset: u64,
+    done: bool,
+}
+
+impl<R: io::Read> BlockFileReader<R> {
+    /// Creates a reader of a block file without obfuscation.
+    pub fn new(reader: R) -> BlockFileReader<R> {
+        BlockFileReader::with_xor_key(reader, [0; 8])
+    }
+
+    /// Creates a reader of a block file obfuscated with `key`.
+    pub fn with_xor_key(reader: R, key: [u8; 8]) -> BlockFileReader<R> {
+        BlockFileReader {
+            reader: reader,
+            key: key,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Fills `buf`, returning `false` if the file ends before any byte could
+    /// be read.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated block file")),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        for byte in buf.iter_mut() {
+            *byte ^= self.key[(self.offset % 8) as usize];
+            self.offset += 1;
+        }
+        Ok(true)
+    }
+
+    /// Reads the next block, skipping zero padding.
+    fn read_block(&mut self) -> Result<Option<RawBlock>, Error> {
+        let mut magic = [0u8; 4];
+        loop {
+            if !self.read(&mut magic[..1])? {
+                return Ok(None);
+            }
+            if magic[0] != 0 {
+                break;
+            }
+        }
+        if !self.read(&mut magic[1..])? {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated block file")));
+        }
+        let mut length = [0u8; 4];
+        let length_offset = self.offset;
+        if !self.read(&mut length)? {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated block file")));
+        }
+        let length = endian::slice_to_u32_le(&length);
+        if length < 80 || length > MAX_BLOCK_WEIGHT {
+            return Err(Error::BadLength { offset: length_offset, length: length });
+        }
+
+        let offset = self.offset;
+        let mut data = vec![0; length as usize];
+        if !self.read(&mut data)? {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated block file")));
+        }
+        Ok(Some(RawBlock {
+            magic: endian::slice_to_u32_le(&magic),
+            offset: offset,
+            data: data,
+        }))
+    }
+}
+
+impl<R: io::Read> Iterator for BlockFileReader<R> {
+    type Item = Result<RawBlock, Error>;
+
+    /// Returns the next block, or an error after which iteration stops.
+    fn next(&mut self) -> Option<Result<RawBlock, Error>> {
+        if self.done {
+            return None;
+        }
+        match self.read_block() {
+            Ok(Some(block)) => Some(Ok(block)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use blockdata::constants::genesis_block;
+    use consensus::encode::serialize;
+    use network::constants::Network;
+    use util::endian::u32_to_array_le;
+
+    use super::{BlockFileReader, Error, RawBlock};
+
+    fn record(network: Network) -> Vec<u8> {
+        let block = serialize(&genesis_block(network));
+        let mut data = u32_to_array_le(network.magic()).to_vec();
+        data.extend_from_slice(&u32_to_array_le(block.len() as u32));
+        data.extend(block);
+        data
+    }
+
+    fn file() -> Vec<u8> {
+        let mut file = record(Network::Bitcoin);
+        file.extend(record(Network::Regtest));
+        file.extend(vec![0; 100]);
+        file
+    }
+
+    #[test]
+    fn read_blocks() {
+        let blocks: Vec<RawBlock> = BlockFileReader::new(io::Cursor::new(file())).map(Result::unwrap).collect();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].offset, 8);
+        assert_eq!(blocks[0].network(), Some(Network::Bitcoin));
+        assert_eq!(blocks[0].block().unwrap(), genesis_block(Network::Bitcoin));
+        assert_eq!(blocks[1].offset, 8 + 285 + 8);
+        assert_eq!(blocks[1].network(), Some(Network::Regtest));
+        assert_eq!(blocks[1].block().unwrap(), genesis_block(Network::Regtest));
+
+        let key = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+        let obfuscated: Vec<u8> = file().iter().enumerate().map(|(i, b)| b ^ key[i % 8]).collect();
+        let unobfuscated: Vec<RawBlock> = BlockFileReader::with_xor_key(io::Cursor::new(obfuscated), key)
+            .map(Result::unwrap).collect();
+        assert_eq!(unobfuscated, blocks);
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut truncated = file();
+        truncated.truncate(8 + 285 + 50);
+        let mut reader = BlockFileReader::new(io::Cursor::new(truncated));
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(Error::Io(ref e))) if e.kind() == io::ErrorKind::UnexpectedEof => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(reader.next().is_none());
+
+        let mut bad_length = file();
+        bad_length[4..8].copy_from_slice(&[0x10, 0, 0, 0]);
+        match BlockFileReader::new(io::Cursor::new(bad_length)).next() {
+            Some(Err(Error::BadLength { offset: 4, length: 16 })) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,439 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! BIP37 Bloom Filters
+//!
+//! Implementation of the bloom filters used by SPV clients to ask their
+//! peers to only relay the transactions they are interested in, as
+//! defined in BIP37. A filter is sent to a peer with a `filterload`
+//! message, after which the peer answers `getdata` requests for blocks
+//! with `merkleblock` messages.
+//!
+
+use io;
+use prelude::*;
+
+use blockdata::opcodes;
+use blockdata::script::{Instruction, Script};
+use blockdata::transaction::{OutPoint, Transaction};
+use consensus::encode::{self, Decodable, Encodable};
+use util::endian;
+
+/// Maximum size of a filter in bytes
+pub const MAX_BLOOM_FILTER_SIZE: usize = 36_000;
+/// Maximum number of hash functions of a filter
+pub const MAX_HASH_FUNCS: u32 = 50;
+
+/// Multiplier of the index of the hash function in the seed of MurmurHash3
+const HASH_SEED_MULTIPLIER: u32 = 0xfba4_c795;
+
+/// How a filter is updated by the peer when an output matches
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum BloomFlags {
+    /// The filter is never updated
+    None,
+    /// The outpoint of any matching output is added to the filter
+    All,
+    /// The outpoint of a matching output is added to the filter only if the
+    /// output is a pay-to-pubkey or a bare multisig
+    PubkeyOnly,
+}
+
+impl Encodable for BloomFlags {
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, encode::Error> {
+        let flags: u8 = match *self {
+            BloomFlags::None => 0,
+            BloomFlags::All => 1,
+            BloomFlags::PubkeyOnly => 2,
+        };
+        flags.consensus_encode(s)
+    }
+
+    fn encoded_len(&self) -> usize {
+        1
+    }
+}
+
+/// Only the two low bits select the update mode, as in Bitcoin Core, which
+/// ignores the other bits and never updates the filter for mode 3.
+impl Decodable for BloomFlags {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        match u8::consensus_decode(d)? & 3 {
+            1 => Ok(BloomFlags::All),
+            2 => Ok(BloomFlags::PubkeyOnly),
+            _ => Ok(BloomFlags::None),
+        }
+    }
+}
+
+/// A BIP37 bloom filter, as sent in a `filterload` message
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct BloomFilter {
+    /// The bit field of the filter
+    pub content: Vec<u8>,
+    /// The number of hash functions
+    pub hash_funcs: u32,
+    /// The random value added to the seed of the hash functions
+    pub tweak: u32,
+    /// How the filter is updated by the peer when an output matches
+    pub flags: BloomFlags,
+}
+impl_consensus_encoding!(BloomFilter, content, hash_funcs, tweak, flags);
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `elements` elements with the given
+    /// false positive rate, the size being capped by [MAX_BLOOM_FILTER_SIZE]
+    /// and [MAX_HASH_FUNCS] like Bitcoin Core does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `elements` is zero or `fp_rate` is not between 0 and 1.
+    #[cfg(feature = "std")]
+    pub fn new(elements: u32, fp_rate: f64, tweak: u32, flags: BloomFlags) -> BloomFilter {
+        assert!(elements > 0, "a bloom filter needs at least one element");
+        assert!(fp_rate > 0.0 && fp_rate < 1.0, "the false positive rate must be between 0 and 1");
+
+        use core::cmp;
+
+        let ln2 = ::std::f64::consts::LN_2;
+        let bits = (-1.0 / (ln2 * ln2) * elements as f64 * fp_rate.ln()) as u64;
+        let size = cmp::min(bits, MAX_BLOOM_FILTER_SIZE as u64 * 8) as usize / 8;
+        let hash_funcs = ((size as u64 * 8 / elements as u64) as f64 * ln2) as u32;
+        BloomFilter {
+            content: vec![0; size],
+            hash_funcs: cmp::min(hash_funcs, MAX_HASH_FUNCS),
+            tweak: tweak,
+            flags: flags,
+        }
+    }
+
+    /// Whether the filter is within the limits of BIP37, peers refuse
+    /// filters that are not.
+    pub fn is_within_size_constraints(&self) -> bool {
+        self.content.len() <= MAX_BLOOM_FILTER_SIZE && self.hash_funcs <= MAX_HASH_FUNCS
+    }
+
+    /// The index of the bit set by the hash function `n` for `data`
+    fn bit_index(&self, n: u32, data: &[u8]) -> usize {
+        let seed = n.wrapping_mul(HASH_SEED_MULTIPLIER).wrapping_add(self.tweak);
+        murmur3(seed, data) as usize % (self.content.len() * 8)
+    }
+
+    /// Adds `data` to the filter.
+    pub fn insert(&mut self, data: &[u8]) {
+        if self.content.is_empty() {
+            return;
+        }
+        for n in 0..self.hash_funcs {
+            let index = self.bit_index(n, data);
+            self.content[index >> 3] |= 1 << (index & 7);
+        }
+    }
+
+    /// Whether `data` matches the filter. An empty filter matches anything.
+    pub fn contains(&self, data: &[u8]) -> bool {
+        if self.content.is_empty() {
+            return true;
+        }
+        (0..self.hash_funcs).all(|n| {
+            let index = self.bit_index(n, data);
+            self.content[index >> 3] & (1 << (index & 7)) != 0
+        })
+    }
+
+    /// Adds an outpoint to the filter, for matching the transactions spending it.
+    pub fn insert_outpoint(&mut self, outpoint: &OutPoint) {
+        self.insert(&encode::serialize(outpoint));
+    }
+
+    /// Whether an outpoint matches the filter.
+    pub fn contains_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.contains(&encode::serialize(outpoint))
+    }
+
+    /// Whether a transaction matches the filter, updating the filter with the
+    /// outpoints of the matching outputs according to its flags.
+    ///
+    /// This is what a peer does with every transaction it relays or includes
+    /// in a `merkleblock` message. A transaction matches if its txid, a data
+    /// push of one of its output scripts, one of its inputs' outpoints or a
+    /// data push of one of its input scripts matches.
+    pub fn is_relevant_and_update(&mut self, tx: &Transaction) -> bool {
+        if self.content.is_empty() {
+            return true;
+        }
+
+        let txid = tx.txid();
+        let mut found = self.contains(&txid[..]);
+        for (vout, output) in tx.output.iter().enumerate() {
+            if !self.matches_script(&output.script_pubkey) {
+                continue;
+            }
+            found = true;
+            let update = match self.flags {
+                BloomFlags::None => false,
+                BloomFlags::All => true,
+                BloomFlags::PubkeyOnly => {
+                    output.script_pubkey.is_p2pk() || is_bare_multisig(&output.script_pubkey)
+                }
+            };
+            if update {
+                self.insert_outpoint(&OutPoint::new(txid, vout as u32));
+            }
+        }
+        if found {
+            return true;
+        }
+
+        tx.input.iter().any(|input| {
+            self.contains_outpoint(&input.previous_output) || self.matches_script(&input.script_sig)
+        })
+    }
+
+    /// Whether any data pushed by the script matches the filter
+    fn matches_script(&self, script: &Script) -> bool {
+        for instruction in script.instructions() {
+            match instruction {
+                Ok(Instruction::PushBytes(data)) => {
+                    if !data.is_empty() && self.contains(data) {
+                        return true;
+                    }
+                }
+                Ok(Instruction::Op(_)) => {}
+                Err(_) => break,
+            }
+        }
+        false
+    }
+}
+
+/// Whether a script is `m <pubkey>... n OP_CHECKMULTISIG`
+fn is_bare_multisig(script: &Script) -> bool {
+    let instructions: Vec<_> = match script.instructions().collect() {
+        Ok(instructions) => instructions,
+        Err(_) => return false,
+    };
+    if instructions.len() < 4 {
+        return false;
+    }
+    let push_num = |instruction: &Instruction| match *instruction {
+        Instruction::Op(op) => match op.classify() {
+            opcodes::Class::PushNum(n) if n >= 1 => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    };
+    let pubkeys = &instructions[1..instructions.len() - 2];
+    let (required, total) = match (push_num(&instructions[0]), push_num(&instructions[instructions.len() - 2])) {
+        (Some(required), Some(total)) => (required, total),
+        _ => return false,
+    };
+    required <= total
+        && total == pubkeys.len()
+        && instructions[instructions.len() - 1] == Instruction::Op(opcodes::all::OP_CHECKMULTISIG)
+        && pubkeys.iter().all(|instruction| match *instruction {
+            Instruction::PushBytes(data) => match data.len() {
+                33 => data[0] == 0x02 || data[0] == 0x03,
+                65 => data[0] == 0x04 || data[0] == 0x06 || data[0] == 0x07,
+                _ => false,
+            },
+            _ => false,
+        })
+}
+
+/// The 32-bit MurmurHash3 of `data`
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h = seed;
+    let mut chunks = data.chunks(4);
+    let mut tail: &[u8] = &[];
+    for chunk in &mut chunks {
+        if chunk.len() < 4 {
+            tail = chunk;
+            break;
+        }
+        let k = endian::slice_to_u32_le(chunk).wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h ^= k;
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, byte) in tail.iter().enumerate() {
+            k ^= (*byte as u32) << (8 * i);
+        }
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    use consensus::encode::{deserialize, serialize};
+    use hashes::hex::FromHex;
+
+    #[test]
+    fn murmur3_test() {
+        // Test vectors of Bitcoin Core
+        let vectors = [
+            (0x00000000, 0x00000000, ""),
+            (0x6a396f08, 0xfba4c795, ""),
+            (0x81f16f39, 0xffffffff, ""),
+            (0x514e28b7, 0x00000000, "00"),
+            (0xea3f0b17, 0xfba4c795, "00"),
+            (0xfd6cf10d, 0x00000000, "ff"),
+            (0x16c6b7ab, 0x00000000, "0011"),
+            (0x8eb51c3d, 0x00000000, "001122"),
+            (0xb4471bf8, 0x00000000, "00112233"),
+            (0xe2301fa8, 0x00000000, "0011223344"),
+            (0xfc2e4a15, 0x00000000, "001122334455"),
+            (0xb074502c, 0x00000000, "00112233445566"),
+            (0x8034d2a0, 0x00000000, "0011223344556677"),
+            (0xb4698def, 0x00000000, "001122334455667788"),
+        ];
+        for &(expected, seed, data) in vectors.iter() {
+            assert_eq!(murmur3(seed, &Vec::from_hex(data).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn insert_serialize_test() {
+        // Test vectors of Bitcoin Core
+        let vectors = [(0, "03614e9b050000000000000001"), (2147483649, "03ce4299050000000100008001")];
+        for &(tweak, expected) in vectors.iter() {
+            let mut filter = BloomFilter::new(3, 0.01, tweak, BloomFlags::All);
+            assert!(filter.is_within_size_constraints());
+
+            let data = Vec::from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap();
+            filter.insert(&data);
+            assert!(filter.contains(&data));
+            assert!(!filter.contains(&Vec::from_hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap()));
+
+            filter.insert(&Vec::from_hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee").unwrap());
+            filter.insert(&Vec::from_hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5").unwrap());
+            let bytes = Vec::from_hex(expected).unwrap();
+            assert_eq!(serialize(&filter), bytes);
+            assert_eq!(filter.encoded_len(), bytes.len());
+            assert_eq!(deserialize::<BloomFilter>(&bytes).unwrap(), filter);
+        }
+
+        // Unknown flags are masked instead of rejected
+        let vectors = [(0x03, BloomFlags::None), (0x05, BloomFlags::All), (0xfe, BloomFlags::PubkeyOnly)];
+        for &(flags, expected) in vectors.iter() {
+            assert_eq!(deserialize::<BloomFlags>(&[flags]).unwrap(), expected);
+        }
+        let filter = deserialize::<BloomFilter>(&Vec::from_hex("00000000000000000003").unwrap()).unwrap();
+        assert_eq!(filter.flags, BloomFlags::None);
+    }
+
+    #[test]
+    fn size_constraints_test() {
+        let filter = BloomFilter::new(1_000_000, 0.000_001, 0, BloomFlags::None);
+        assert_eq!(filter.content.len(), MAX_BLOOM_FILTER_SIZE);
+        assert_eq!(filter.hash_funcs, 0);
+        assert!(filter.is_within_size_constraints());
+
+        let filter = BloomFilter::new(1, 1e-20, 0, BloomFlags::None);
+        assert_eq!(filter.hash_funcs, MAX_HASH_FUNCS);
+
+        let filter = BloomFilter { content: vec![0; MAX_BLOOM_FILTER_SIZE + 1], ..filter };
+        assert!(!filter.is_within_size_constraints());
+    }
+
+    #[test]
+    fn is_relevant_and_update_test() {
+        // Block 80000
+        let block: ::Block = deserialize(&Vec::from_hex("01000000ba8b9cda965dd8e536670f9ddec10e53aab14b20bacad27b9137190000000000190760b278fe7b8565fda3b968b9\
+            18d5fd997f993b23674c0af3b6fde300b38f33a5914ce6ed5b1b01e32f570201000000010000000000000000000000000000\
+            000000000000000000000000000000000000ffffffff0704e6ed5b1b014effffffff0100f2052a01000000434104b68a50ea\
+            a0287eff855189f949c1c6e5f58b37c88231373d8a59809cbae83059cc6469d65c665ccfd1cfeb75c6e8e19413bba7fbff9b\
+            c762419a76d87b16086eac000000000100000001a6b97044d03da79c005b20ea9c0e1a6d9dc12d9f7b91a5911c9030a439ee\
+            d8f5000000004948304502206e21798a42fae0e854281abd38bacd1aeed3ee3738d9e1446618c4571d1090db022100e2ac98\
+            0643b0b82c0e88ffdfec6b64e3e6ba35e7ba5fdd7d5d6cc8d25c6b241501ffffffff0100f2052a010000001976a914404371\
+            705fa9bd789a2fcd52d2c580b65d35549d88ac00000000").unwrap()).unwrap();
+        let coinbase = &block.txdata[0];
+        let spend = &block.txdata[1];
+        let pubkey = match coinbase.output[0].script_pubkey.instructions().next() {
+            Some(Ok(Instruction::PushBytes(pubkey))) => pubkey.to_vec(),
+            _ => panic!("p2pk output"),
+        };
+        let coinbase_outpoint = OutPoint::new(coinbase.txid(), 0);
+
+        // Matching the txid
+        let mut filter = BloomFilter::new(10, 0.000_001, 0, BloomFlags::All);
+        filter.insert(&spend.txid()[..]);
+        assert!(filter.is_relevant_and_update(spend));
+        assert!(!filter.is_relevant_and_update(coinbase));
+
+        // Matching an output script, which adds its outpoint depending on the flags
+        for &(flags, updated) in [(BloomFlags::None, false), (BloomFlags::All, true), (BloomFlags::PubkeyOnly, true)].iter() {
+            let mut filter = BloomFilter::new(10, 0.000_001, 0, flags);
+            filter.insert(&pubkey);
+            assert!(!filter.contains_outpoint(&coinbase_outpoint));
+            assert!(filter.is_relevant_and_update(coinbase));
+            assert_eq!(filter.contains_outpoint(&coinbase_outpoint), updated);
+            assert!(!filter.is_relevant_and_update(spend));
+        }
+
+        // Only pay-to-pubkey and bare multisig outputs are added with PubkeyOnly
+        let mut filter = BloomFilter::new(10, 0.000_001, 0, BloomFlags::PubkeyOnly);
+        let pubkey_hash = match spend.output[0].script_pubkey.instructions().nth(2) {
+            Some(Ok(Instruction::PushBytes(pubkey_hash))) => pubkey_hash.to_vec(),
+            _ => panic!("p2pkh output"),
+        };
+        filter.insert(&pubkey_hash);
+        assert!(filter.is_relevant_and_update(spend));
+        assert!(!filter.contains_outpoint(&OutPoint::new(spend.txid(), 0)));
+
+        // Matching an input outpoint or signature
+        let mut filter = BloomFilter::new(10, 0.000_001, 0, BloomFlags::None);
+        filter.insert_outpoint(&spend.input[0].previous_output);
+        assert!(filter.is_relevant_and_update(spend));
+        let mut filter = BloomFilter::new(10, 0.000_001, 0, BloomFlags::None);
+        let sig = match spend.input[0].script_sig.instructions().next() {
+            Some(Ok(Instruction::PushBytes(sig))) => sig.to_vec(),
+            _ => panic!("signature push"),
+        };
+        filter.insert(&sig);
+        assert!(filter.is_relevant_and_update(spend));
+    }
+
+    #[test]
+    fn bare_multisig_test() {
+        let pk = Vec::from_hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+        let script = |required: u8, keys: usize, total: u8| {
+            let mut builder = ::blockdata::script::Builder::new().push_int(required as i64);
+            for _ in 0..keys {
+                builder = builder.push_slice(&pk);
+            }
+            builder.push_int(total as i64).push_opcode(opcodes::all::OP_CHECKMULTISIG).into_script()
+        };
+        assert!(is_bare_multisig(&script(1, 2, 2)));
+        assert!(is_bare_multisig(&script(2, 2, 2)));
+        assert!(!is_bare_multisig(&script(3, 2, 2)));
+        assert!(!is_bare_multisig(&script(1, 2, 3)));
+        assert!(!is_bare_multisig(&Script::new()));
+    }
+}
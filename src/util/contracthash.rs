@@ -26,7 +26,9 @@ use PublicKey;
 use hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
 use blockdata::{opcodes, script};
 
-use std::{error, fmt};
+use core::fmt;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
 
 use hash_types::ScriptHash;
 use network::constants::Network;
@@ -71,6 +73,7 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
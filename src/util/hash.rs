@@ -15,8 +15,9 @@
 //!
 //! Utility functions related to hashing data, including merkleization
 
-use std::cmp::min;
-use std::io;
+use core::cmp::min;
+use io;
+use prelude::*;
 
 use hashes::Hash;
 use consensus::encode::Encodable;
@@ -40,10 +41,7 @@ pub fn bitcoin_merkle_root_inline<T>(data: &mut [T]) -> T
     for idx in 0..((data.len() + 1) / 2) {
         let idx1 = 2 * idx;
         let idx2 = min(idx1 + 1, data.len() - 1);
-        let mut encoder = T::engine();
-        data[idx1].consensus_encode(&mut encoder).unwrap();
-        data[idx2].consensus_encode(&mut encoder).unwrap();
-        data[idx] = T::from_engine(encoder);
+        data[idx] = merkle_node(&data[idx1], &data[idx2]);
     }
     let half_len = data.len() / 2 + data.len() % 2;
     bitcoin_merkle_root_inline(&mut data[0..half_len])
@@ -68,10 +66,18 @@ pub fn bitcoin_merkle_root<T, I>(mut iter: I) -> T
     while let Some(hash1) = iter.next() {
         // If the size is odd, use the last element twice.
         let hash2 = iter.next().unwrap_or(hash1);
-        let mut encoder = T::engine();
-        hash1.consensus_encode(&mut encoder).unwrap();
-        hash2.consensus_encode(&mut encoder).unwrap();
-        alloc.push(T::from_engine(encoder));
+        alloc.push(merkle_node(&hash1, &hash2));
     }
     bitcoin_merkle_root_inline(&mut alloc)
 }
+
+/// Calculates the hash of an inner merkle tree node from its children.
+pub(crate) fn merkle_node<T>(left: &T, right: &T) -> T
+    where T: Hash + Encodable,
+          <T as Hash>::Engine: io::Write,
+{
+    let mut encoder = T::engine();
+    left.consensus_encode(&mut encoder).unwrap();
+    right.consensus_encode(&mut encoder).unwrap();
+    T::from_engine(encoder)
+}
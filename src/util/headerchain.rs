@@ -0,0 +1,493 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Header chain
+//!
+//! A store of block headers as needed by SPV clients. Headers may be added in
+//! any order: headers whose parent is not known yet are kept aside until it
+//! arrives, up to a maximum number of such orphans. Every connected header
+//! has its proof of work fully verified, including the required difficulty.
+//! All forks are tracked and the chain with the most cumulative work is
+//! selected as the active chain; changes of the active chain are reported as
+//! lists of disconnected and connected blocks.
+//!
+//! The store can be persisted as a flat file of consensus-encoded headers.
+//!
+
+use core::fmt;
+use io;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
+
+use blockdata::block::BlockHeader;
+use blockdata::constants::genesis_block;
+use consensus::encode::{self, deserialize, Encodable};
+use consensus::pow::{self, HeaderLookup};
+use consensus::Params;
+use hash_types::BlockHash;
+use util::uint::Uint256;
+
+/// Default maximum number of orphan headers kept by a [`HeaderChain`]
+pub const MAX_ORPHANS: usize = 1000;
+
+/// Header chain errors
+#[derive(Debug)]
+pub enum Error {
+    /// The header has an invalid proof of work
+    ProofOfWork(pow::Error),
+    /// A loaded header does not connect to the previously loaded ones
+    OrphanHeader(BlockHash),
+    /// I/O error while reading or writing the flat file
+    Io(io::Error),
+    /// The flat file contains an invalid header
+    Encode(encode::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ProofOfWork(ref e) => fmt::Display::fmt(e, f),
+            Error::OrphanHeader(ref hash) => write!(f, "header {} does not connect to the chain", hash),
+            Error::Io(ref e) => fmt::Display::fmt(e, f),
+            Error::Encode(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::ProofOfWork(ref e) => Some(e),
+            Error::OrphanHeader(_) => None,
+            Error::Io(ref e) => Some(e),
+            Error::Encode(ref e) => Some(e),
+        }
+    }
+
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+#[doc(hidden)]
+impl From<pow::Error> for Error {
+    fn from(e: pow::Error) -> Error {
+        Error::ProofOfWork(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+/// A header connected to the chain
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct StoredHeader {
+    /// The header
+    pub header: BlockHeader,
+    /// Height of the block, the genesis block having height zero
+    pub height: u32,
+    /// Total work of the chain up to and including this block
+    pub chain_work: Uint256,
+}
+
+/// A change of the active chain
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Reorg {
+    /// Blocks removed from the active chain, starting with the old tip
+    pub disconnected: Vec<BlockHash>,
+    /// Blocks added to the active chain, ending with the new tip
+    pub connected: Vec<BlockHash>,
+}
+
+/// A store of block headers tracking all forks
+#[derive(Clone, Debug)]
+pub struct HeaderChain {
+    params: Params,
+    headers: HashMap<BlockHash, StoredHeader>,
+    active: Vec<BlockHash>,
+    orphans: HashMap<BlockHash, Vec<BlockHeader>>,
+    /// The parent and hash of the orphans, oldest first
+    orphan_queue: VecDeque<(BlockHash, BlockHash)>,
+    max_orphans: usize,
+}
+
+impl HeaderChain {
+    /// Creates a chain containing only the genesis block of the network
+    pub fn new(params: Params) -> HeaderChain {
+        let genesis = genesis_block(params.network).header;
+        let hash = genesis.block_hash();
+        let mut headers = HashMap::new();
+        headers.insert(hash, StoredHeader {
+            header: genesis,
+            height: 0,
+            chain_work: genesis.work(),
+        });
+        HeaderChain {
+            params: params,
+            headers: headers,
+            active: vec![hash],
+            orphans: HashMap::new(),
+            orphan_queue: VecDeque::new(),
+            max_orphans: MAX_ORPHANS,
+        }
+    }
+
+    /// Sets the maximum number of orphan headers, [`MAX_ORPHANS`] by default.
+    /// The oldest orphans are dropped when there are more.
+    pub fn max_orphans(mut self, max_orphans: usize) -> HeaderChain {
+        self.max_orphans = max_orphans;
+        self.evict_orphans();
+        self
+    }
+
+    /// Returns the number of headers whose parent is not known yet
+    pub fn orphan_count(&self) -> usize {
+        self.orphan_queue.len()
+    }
+
+    /// Returns the consensus parameters of the chain
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Returns the tip of the active chain
+    pub fn tip(&self) -> &StoredHeader {
+        &self.headers[self.active.last().expect("genesis is always present")]
+    }
+
+    /// Returns the height of the tip of the active chain
+    pub fn height(&self) -> u32 {
+        (self.active.len() - 1) as u32
+    }
+
+    /// Returns the hashes of the active chain, indexed by height
+    pub fn active_chain(&self) -> &[BlockHash] {
+        &self.active
+    }
+
+    /// Returns a connected header, whether it is in the active chain or not
+    pub fn get(&self, hash: &BlockHash) -> Option<&StoredHeader> {
+        self.headers.get(hash)
+    }
+
+    /// Checks whether the block is part of the active chain
+    pub fn is_active(&self, hash: &BlockHash) -> bool {
+        match self.headers.get(hash) {
+            Some(entry) => self.active.get(entry.height as usize) == Some(hash),
+            None => false,
+        }
+    }
+
+    /// Adds a header. Headers with an unknown parent are kept until the parent
+    /// is added, when they are connected together with it; the oldest of them
+    /// are dropped beyond the maximum number of orphans. Returns the change
+    /// of the active chain, if any. Adding a known header has no effect.
+    ///
+    /// Headers only replace the active chain if they have strictly more work,
+    /// so the first seen of two equal-work chains stays active.
+    pub fn add_header(&mut self, header: BlockHeader) -> Result<Option<Reorg>, Error> {
+        let hash = header.block_hash();
+        if self.headers.contains_key(&hash) {
+            return Ok(None);
+        }
+        if !self.headers.contains_key(&header.prev_blockhash) {
+            // The required difficulty is unknown, but the header must at
+            // least satisfy its own target
+            if header.target() > self.params.pow_limit || header.validate_pow(&header.target()).is_err() {
+                return Err(Error::ProofOfWork(pow::Error::BadProofOfWork));
+            }
+            let siblings = self.orphans.entry(header.prev_blockhash).or_insert_with(Vec::new);
+            if !siblings.contains(&header) {
+                siblings.push(header);
+                self.orphan_queue.push_back((header.prev_blockhash, hash));
+                self.evict_orphans();
+            }
+            return Ok(None);
+        }
+
+        self.connect(header)?;
+        let mut best = hash;
+        let mut queue = vec![hash];
+        while let Some(parent) = queue.pop() {
+            let children = match self.orphans.remove(&parent) {
+                Some(children) => children,
+                None => continue,
+            };
+            self.orphan_queue.retain(|&(prev, _)| prev != parent);
+            for child in children {
+                // Invalid orphans are dropped, so their descendants never connect
+                if self.connect(child).is_ok() {
+                    let child_hash = child.block_hash();
+                    if self.headers[&child_hash].chain_work > self.headers[&best].chain_work {
+                        best = child_hash;
+                    }
+                    queue.push(child_hash);
+                }
+            }
+        }
+        Ok(self.update_tip(best))
+    }
+
+    /// Writes all connected headers except the genesis block to a flat file,
+    /// ordered by height and then by hash.
+    pub fn save<W: io::Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut entries: Vec<(&BlockHash, &StoredHeader)> = self.headers.iter().filter(|&(_, e)| e.height > 0).collect();
+        entries.sort_by_key(|&(hash, e)| (e.height, *hash));
+        for (_, entry) in entries {
+            entry.header.consensus_encode(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Loads a chain from a flat file written by [`HeaderChain::save`],
+    /// verifying all headers again.
+    pub fn load<R: io::Read>(params: Params, mut reader: R) -> Result<HeaderChain, Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        if data.len() % 80 != 0 {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated header file")));
+        }
+        let mut chain = HeaderChain::new(params);
+        for chunk in data.chunks(80) {
+            let header: BlockHeader = deserialize(chunk)?;
+            chain.add_header(header)?;
+            if !chain.headers.contains_key(&header.block_hash()) {
+                return Err(Error::OrphanHeader(header.block_hash()));
+            }
+        }
+        Ok(chain)
+    }
+
+    fn connect(&mut self, header: BlockHeader) -> Result<(), Error> {
+        let parent = self.headers[&header.prev_blockhash];
+        let height = parent.height + 1;
+        {
+            let branch = Branch { chain: self, tip: header.prev_blockhash };
+            pow::check_proof_of_work(&self.params, &branch, height, &header)?;
+        }
+        self.headers.insert(header.block_hash(), StoredHeader {
+            header: header,
+            height: height,
+            chain_work: parent.chain_work + header.work(),
+        });
+        Ok(())
+    }
+
+    fn evict_orphans(&mut self) {
+        while self.orphan_queue.len() > self.max_orphans {
+            let (prev, hash) = self.orphan_queue.pop_front().expect("queue is not empty");
+            let empty = {
+                let siblings = self.orphans.get_mut(&prev).expect("orphans are in the queue");
+                siblings.retain(|header| header.block_hash() != hash);
+                siblings.is_empty()
+            };
+            if empty {
+                self.orphans.remove(&prev);
+            }
+        }
+    }
+
+    fn update_tip(&mut self, best: BlockHash) -> Option<Reorg> {
+        if self.headers[&best].chain_work <= self.tip().chain_work {
+            return None;
+        }
+
+        let mut connected = vec![];
+        let mut hash = best;
+        while !self.is_active(&hash) {
+            connected.push(hash);
+            hash = self.headers[&hash].header.prev_blockhash;
+        }
+        connected.reverse();
+
+        let fork_height = self.headers[&hash].height as usize;
+        let mut disconnected = self.active.split_off(fork_height + 1);
+        disconnected.reverse();
+        self.active.extend_from_slice(&connected);
+        Some(Reorg {
+            disconnected: disconnected,
+            connected: connected,
+        })
+    }
+}
+
+/// Headers of the active chain
+impl HeaderLookup for HeaderChain {
+    fn header_at(&self, height: u32) -> Option<BlockHeader> {
+        self.active.get(height as usize).map(|hash| self.headers[hash].header)
+    }
+}
+
+/// Headers of the branch ending with `tip`, which may be a fork
+struct Branch<'a> {
+    chain: &'a HeaderChain,
+    tip: BlockHash,
+}
+
+impl<'a> HeaderLookup for Branch<'a> {
+    fn header_at(&self, height: u32) -> Option<BlockHeader> {
+        let mut hash = self.tip;
+        let mut entry = self.chain.headers.get(&hash)?;
+        if height > entry.height {
+            return None;
+        }
+        // Walk back to the active chain, whose headers are indexed by height
+        while !self.chain.is_active(&hash) {
+            if entry.height == height {
+                return Some(entry.header);
+            }
+            hash = entry.header.prev_blockhash;
+            entry = self.chain.headers.get(&hash)?;
+        }
+        self.chain.header_at(height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use io;
+
+    use blockdata::block::BlockHeader;
+    use blockdata::constants::genesis_block;
+    use consensus::Params;
+    use network::constants::Network;
+
+    use super::{Error, HeaderChain, Reorg};
+
+    fn mine(prev: &BlockHeader, time_offset: u32) -> BlockHeader {
+        let mut header = BlockHeader {
+            version: 0x20000000,
+            prev_blockhash: prev.block_hash(),
+            merkle_root: Default::default(),
+            time: prev.time + time_offset,
+            bits: 0x207fffff,
+            nonce: 0,
+        };
+        while header.validate_pow(&header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn reorg(disconnected: &[BlockHeader], connected: &[BlockHeader]) -> Option<Reorg> {
+        Some(Reorg {
+            disconnected: disconnected.iter().map(BlockHeader::block_hash).collect(),
+            connected: connected.iter().map(BlockHeader::block_hash).collect(),
+        })
+    }
+
+    #[test]
+    fn header_chain() {
+        let mut chain = HeaderChain::new(Params::new(Network::Regtest));
+        let genesis = genesis_block(Network::Regtest).header;
+        assert_eq!(chain.height(), 0);
+        assert_eq!(chain.tip().header, genesis);
+
+        let b1 = mine(&genesis, 600);
+        let b2 = mine(&b1, 600);
+        let b3 = mine(&b2, 600);
+        assert_eq!(chain.add_header(b1).unwrap(), reorg(&[], &[b1]));
+        assert_eq!(chain.add_header(b2).unwrap(), reorg(&[], &[b2]));
+        assert_eq!(chain.add_header(b3).unwrap(), reorg(&[], &[b3]));
+        assert_eq!(chain.add_header(b2).unwrap(), None);
+        assert_eq!(chain.height(), 3);
+        assert_eq!(chain.tip().chain_work, genesis.work() + b1.work() + b2.work() + b3.work());
+
+        // A fork with equal work does not replace the active chain
+        let f2 = mine(&b1, 601);
+        let f3 = mine(&f2, 600);
+        let f4 = mine(&f3, 600);
+        assert_eq!(chain.add_header(f3).unwrap(), None);
+        assert_eq!(chain.add_header(f2).unwrap(), None);
+        assert_eq!(chain.tip().header, b3);
+        assert!(chain.get(&f3.block_hash()).is_some());
+        assert!(!chain.is_active(&f3.block_hash()));
+
+        // Out of order headers are connected once their parent arrives
+        let f5 = mine(&f4, 600);
+        assert_eq!(chain.add_header(f5).unwrap(), None);
+        assert_eq!(chain.add_header(f4).unwrap(), reorg(&[b3, b2], &[f2, f3, f4, f5]));
+        assert_eq!(chain.height(), 5);
+        assert_eq!(chain.active_chain()[2], f2.block_hash());
+        assert!(!chain.is_active(&b2.block_hash()));
+
+        let mut invalid = mine(&f5, 600);
+        while invalid.validate_pow(&invalid.target()).is_ok() {
+            invalid.nonce += 1;
+        }
+        match chain.add_header(invalid) {
+            Err(Error::ProofOfWork(_)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        let mut bad_bits = mine(&f5, 600);
+        bad_bits.bits = 0x1d00ffff;
+        assert!(chain.add_header(bad_bits).is_err());
+
+        let mut file = vec![];
+        chain.save(&mut file).unwrap();
+        assert_eq!(file.len(), 7 * 80);
+        let loaded = HeaderChain::load(Params::new(Network::Regtest), &file[..]).unwrap();
+        assert_eq!(loaded.active_chain(), chain.active_chain());
+        assert_eq!(loaded.tip(), chain.tip());
+        assert!(loaded.get(&b3.block_hash()).is_some());
+        let mut saved = vec![];
+        loaded.save(&mut saved).unwrap();
+        assert_eq!(saved, file);
+
+        match HeaderChain::load(Params::new(Network::Regtest), &file[..file.len() - 1]) {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        match HeaderChain::load(Params::new(Network::Regtest), &file[80..]) {
+            Err(Error::OrphanHeader(hash)) => assert!(hash == b2.block_hash() || hash == f2.block_hash()),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn orphan_limit() {
+        let mut chain = HeaderChain::new(Params::new(Network::Regtest)).max_orphans(2);
+        let genesis = genesis_block(Network::Regtest).header;
+        let b1 = mine(&genesis, 600);
+        let b2 = mine(&b1, 600);
+        let b3 = mine(&b2, 600);
+        let b4 = mine(&b3, 600);
+
+        // The oldest orphan is dropped
+        assert_eq!(chain.add_header(b4).unwrap(), None);
+        assert_eq!(chain.add_header(b3).unwrap(), None);
+        assert_eq!(chain.add_header(b2).unwrap(), None);
+        assert_eq!(chain.add_header(b2).unwrap(), None);
+        assert_eq!(chain.orphan_count(), 2);
+        assert_eq!(chain.add_header(b1).unwrap(), reorg(&[], &[b1, b2, b3]));
+        assert_eq!(chain.orphan_count(), 0);
+        assert_eq!(chain.add_header(b4).unwrap(), reorg(&[], &[b4]));
+
+        let chain = HeaderChain::new(Params::new(Network::Regtest));
+        assert_eq!(chain.max_orphans(0).add_header(b2).unwrap(), None);
+    }
+}
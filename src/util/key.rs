@@ -16,9 +16,12 @@
 //! Keys used in Bitcoin that can be roundtrip (de)serialized.
 //!
 
-use std::fmt::{self, Write};
-use std::{io, ops, error};
-use std::str::FromStr;
+use core::fmt::{self, Write};
+use core::ops;
+use io;
+#[cfg(feature = "std")] use std::error;
+use core::str::FromStr;
+use prelude::*;
 
 use secp256k1::{self, Secp256k1};
 use network::constants::Network;
@@ -45,6 +48,7 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
         match *self {
@@ -282,7 +286,7 @@ impl<'de> ::serde::Deserialize<'de> for PrivateKey {
         impl<'de> ::serde::de::Visitor<'de> for WifVisitor {
             type Value = PrivateKey;
 
-            fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            fn expecting(&self, formatter: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
                 formatter.write_str("an ASCII WIF string")
             }
 
@@ -290,7 +294,7 @@ impl<'de> ::serde::Deserialize<'de> for PrivateKey {
             where
                 E: ::serde::de::Error,
             {
-                if let Ok(s) = ::std::str::from_utf8(v) {
+                if let Ok(s) = ::core::str::from_utf8(v) {
                     PrivateKey::from_str(s).map_err(E::custom)
                 } else {
                     Err(E::invalid_value(::serde::de::Unexpected::Bytes(v), &self))
@@ -333,7 +337,7 @@ impl<'de> ::serde::Deserialize<'de> for PublicKey {
             impl<'de> ::serde::de::Visitor<'de> for HexVisitor {
                 type Value = PublicKey;
 
-                fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                fn expecting(&self, formatter: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
                     formatter.write_str("an ASCII hex string")
                 }
 
@@ -341,7 +345,7 @@ impl<'de> ::serde::Deserialize<'de> for PublicKey {
                 where
                     E: ::serde::de::Error,
                 {
-                    if let Ok(hex) = ::std::str::from_utf8(v) {
+                    if let Ok(hex) = ::core::str::from_utf8(v) {
                         PublicKey::from_str(hex).map_err(E::custom)
                     } else {
                         Err(E::invalid_value(::serde::de::Unexpected::Bytes(v), &self))
@@ -362,7 +366,7 @@ impl<'de> ::serde::Deserialize<'de> for PublicKey {
             impl<'de> ::serde::de::Visitor<'de> for BytesVisitor {
                 type Value = PublicKey;
 
-                fn expecting(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                fn expecting(&self, formatter: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
                     formatter.write_str("a bytestring")
                 }
 
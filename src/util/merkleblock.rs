@@ -52,8 +52,9 @@
 //! assert_eq!(1, index[0]);
 //! ```
 
-use std::collections::HashSet;
-use std::io;
+use core::cmp;
+use io;
+use prelude::*;
 
 use hashes::Hash;
 use hash_types::{Txid, TxMerkleNode};
@@ -61,6 +62,9 @@ use hash_types::{Txid, TxMerkleNode};
 use blockdata::transaction::Transaction;
 use blockdata::constants::{MAX_BLOCK_WEIGHT, MIN_TRANSACTION_WEIGHT};
 use consensus::encode::{self, Decodable, Encodable};
+use consensus::DecodeContext;
+use util::bloom::BloomFilter;
+use util::hash::merkle_node;
 use util::merkleblock::MerkleBlockError::*;
 use {Block, BlockHeader};
 
@@ -362,11 +366,16 @@ impl Encodable for PartialMerkleTree {
 }
 
 impl Decodable for PartialMerkleTree {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
         let num_transactions: u32 = Decodable::consensus_decode(&mut d)?;
-        let hashes: Vec<TxMerkleNode> = Decodable::consensus_decode(&mut d)?;
+        let hashes: Vec<TxMerkleNode> = Decodable::consensus_decode_limited(&mut d, ctx)?;
 
-        let bytes: Vec<u8> = Decodable::consensus_decode(d)?;
+        let bytes: Vec<u8> = Decodable::consensus_decode_limited(d, ctx)?;
+        ctx.allocate(bytes.len().saturating_mul(8))?;
         let mut bits: Vec<bool> = vec![false; bytes.len() * 8];
 
         for (p, bit) in bits.iter_mut().enumerate() {
@@ -435,6 +444,25 @@ impl MerkleBlock {
         Self::from_header_txids(&block.header, &block_txids, match_txids)
     }
 
+    /// Create a MerkleBlock from a block, with proofs for the transactions matching a BIP37 bloom
+    /// filter.
+    ///
+    /// The filter is updated with every transaction, like a peer does before answering a
+    /// `getdata` request for a filtered block, see [BloomFilter::is_relevant_and_update].
+    pub fn from_block_with_filter(block: &Block, filter: &mut BloomFilter) -> Self {
+        let block_txids: Vec<_> = block.txdata.iter().map(Transaction::txid).collect();
+        let matches: Vec<bool> = block.txdata
+            .iter()
+            .map(|tx| filter.is_relevant_and_update(tx))
+            .collect();
+
+        let pmt = PartialMerkleTree::from_txids(&block_txids, &matches);
+        MerkleBlock {
+            header: block.header,
+            txn: pmt,
+        }
+    }
+
     /// Create a MerkleBlock from the block's header and txids, that should contain proofs for match_txids.
     ///
     /// The `header` is the block header, `block_txids` is the full list of txids included in the block and
@@ -486,10 +514,123 @@ impl Encodable for MerkleBlock {
 }
 
 impl Decodable for MerkleBlock {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
         Ok(MerkleBlock {
             header: Decodable::consensus_decode(&mut d)?,
-            txn: Decodable::consensus_decode(d)?,
+            txn: Decodable::consensus_decode_limited(d, ctx)?,
+        })
+    }
+}
+
+/// A merkle branch proving that a single transaction is included in a block,
+/// as used by Electrum servers: the hashes of the siblings of the nodes on
+/// the path from the transaction to the merkle root, from the bottom up.
+///
+/// Unlike a [`PartialMerkleTree`], a branch does not commit to the number of
+/// transactions in the block, so verifiers which care about the
+/// ambiguity between 64-byte transactions and inner nodes should also check
+/// the branch length.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct MerkleBranch {
+    /// Position of the transaction in the block
+    pub index: u32,
+    /// Hashes of the siblings of the path to the root
+    pub hashes: Vec<TxMerkleNode>,
+}
+
+impl MerkleBranch {
+    /// Create the merkle branch of the transaction with id `txid` in `block`,
+    /// or `None` if the block does not contain it.
+    pub fn from_block(block: &Block, txid: &Txid) -> Option<Self> {
+        let txids: Vec<_> = block.txdata.iter().map(Transaction::txid).collect();
+        let index = txids.iter().position(|t| t == txid)?;
+        Some(Self::from_txids(&txids, index))
+    }
+
+    /// Create the merkle branch of the transaction at position `index` in a
+    /// block with the transaction ids `txids`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn from_txids(txids: &[Txid], index: usize) -> Self {
+        assert!(index < txids.len(), "transaction index out of bounds");
+        let mut level: Vec<TxMerkleNode> = txids.iter().map(|txid| TxMerkleNode::from_inner(txid.into_inner())).collect();
+        let mut pos = index;
+        let mut hashes = vec![];
+        while level.len() > 1 {
+            // If the size is odd, the last element is its own sibling
+            hashes.push(level[cmp::min(pos ^ 1, level.len() - 1)]);
+            level = level.chunks(2).map(|pair| merkle_node(&pair[0], pair.last().unwrap())).collect();
+            pos /= 2;
+        }
+        MerkleBranch {
+            index: index as u32,
+            hashes: hashes,
+        }
+    }
+
+    /// Compute the merkle root committing to the transaction with id `txid`
+    /// through this branch.
+    pub fn merkle_root(&self, txid: &Txid) -> TxMerkleNode {
+        let mut node = TxMerkleNode::from_inner(txid.into_inner());
+        for (height, sibling) in self.hashes.iter().enumerate() {
+            node = if self.index.checked_shr(height as u32).unwrap_or(0) & 1 == 0 {
+                merkle_node(&node, sibling)
+            } else {
+                merkle_node(sibling, &node)
+            };
+        }
+        node
+    }
+
+    /// Check that this branch proves the inclusion of the transaction with id
+    /// `txid` in the block with the given header.
+    pub fn verify(&self, txid: &Txid, header: &BlockHeader) -> bool {
+        // A 32-bit index cannot address a deeper tree
+        if self.hashes.len() > MAX_MERKLE_BRANCH_LEN {
+            return false;
+        }
+        // Bits of the index above the tree height would be ignored
+        if self.hashes.len() < 32 && self.index >> self.hashes.len() != 0 {
+            return false;
+        }
+        self.merkle_root(txid) == header.merkle_root
+    }
+}
+
+/// The maximum number of hashes in a merkle branch, the height of a tree with
+/// 2^32 transactions
+const MAX_MERKLE_BRANCH_LEN: usize = 32;
+
+impl Encodable for MerkleBranch {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        Ok(self.index.consensus_encode(&mut s)? + self.hashes.consensus_encode(s)?)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.index.encoded_len() + self.hashes.encoded_len()
+    }
+}
+
+impl Decodable for MerkleBranch {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
+        let index = Decodable::consensus_decode(&mut d)?;
+        let hashes: Vec<TxMerkleNode> = Decodable::consensus_decode_limited(d, ctx)?;
+        if hashes.len() > MAX_MERKLE_BRANCH_LEN {
+            return Err(encode::Error::ParseFailed("merkle branch longer than 32 hashes"));
+        }
+        Ok(MerkleBranch {
+            index: index,
+            hashes: hashes,
         })
     }
 }
@@ -503,9 +644,9 @@ mod tests {
     use hash_types::{Txid, TxMerkleNode};
     use secp256k1::rand::prelude::*;
 
-    use consensus::encode::{deserialize, serialize};
+    use consensus::encode::{self, deserialize, serialize};
     use util::hash::bitcoin_merkle_root;
-    use util::merkleblock::{MerkleBlock, PartialMerkleTree};
+    use util::merkleblock::{MerkleBlock, MerkleBranch, PartialMerkleTree};
     use Block;
 
     #[test]
@@ -696,6 +837,75 @@ mod tests {
         assert_eq!(index.len(), 0);
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn merkleblock_construct_from_filter() {
+        use util::bloom::{BloomFilter, BloomFlags};
+
+        let block = get_block_13b8a();
+        let txids: Vec<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+
+        let mut filter = BloomFilter::new(2, 0.000_001, 0, BloomFlags::None);
+        filter.insert(&txids[1][..]);
+        filter.insert(&txids[8][..]);
+        let merkle_block = MerkleBlock::from_block_with_filter(&block, &mut filter);
+        let expected = MerkleBlock::from_block(&block, &vec![txids[1], txids[8]].into_iter().collect());
+        assert_eq!(merkle_block, expected);
+
+        // An empty filter matches every transaction
+        let mut filter = BloomFilter { content: vec![], hash_funcs: 0, tweak: 0, flags: BloomFlags::None };
+        let merkle_block = MerkleBlock::from_block_with_filter(&block, &mut filter);
+        let mut matches: Vec<Txid> = vec![];
+        let mut index: Vec<u32> = vec![];
+        merkle_block.extract_matches(&mut matches, &mut index).unwrap();
+        assert_eq!(matches, txids);
+    }
+
+    #[test]
+    fn merkle_branch() {
+        let block = get_block_13b8a();
+        let txids: Vec<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+        for (index, txid) in txids.iter().enumerate() {
+            let branch = MerkleBranch::from_block(&block, txid).unwrap();
+            assert_eq!(branch.index, index as u32);
+            assert_eq!(branch.hashes.len(), 4);
+            assert!(branch.verify(txid, &block.header));
+            assert!(!branch.verify(&txids[(index + 1) % txids.len()], &block.header));
+
+            let encoded = serialize(&branch);
+            assert_eq!(encoded.len(), 4 + 1 + 4 * 32);
+            assert_eq!(deserialize::<MerkleBranch>(&encoded).unwrap(), branch);
+        }
+
+        let mut branch = MerkleBranch::from_txids(&txids, 8);
+        assert_eq!(branch.merkle_root(&txids[8]), block.header.merkle_root);
+        branch.index += 16;
+        assert_eq!(branch.merkle_root(&txids[8]), block.header.merkle_root);
+        assert!(!branch.verify(&txids[8], &block.header));
+
+        let unknown = Txid::hash(&[]);
+        assert_eq!(MerkleBranch::from_block(&block, &unknown), None);
+
+        let single = MerkleBranch::from_txids(&txids[..1], 0);
+        assert!(single.hashes.is_empty());
+        assert_eq!(single.merkle_root(&txids[0]), bitcoin_merkle_root(txids[..1].iter().map(|t| t.as_hash())).into());
+
+        // No tree is deeper than 32 levels
+        let long = MerkleBranch {
+            index: u32::max_value(),
+            hashes: vec![block.header.merkle_root; 33],
+        };
+        long.merkle_root(&txids[0]);
+        assert!(!long.verify(&txids[0], &block.header));
+        match deserialize::<MerkleBranch>(&serialize(&long)) {
+            Err(encode::Error::ParseFailed("merkle branch longer than 32 hashes")) => {}
+            x => panic!("unexpected result: {:?}", x),
+        }
+        let mut max = long.clone();
+        max.hashes.pop();
+        assert_eq!(deserialize::<MerkleBranch>(&serialize(&max)).unwrap(), max);
+    }
+
     impl PartialMerkleTree {
         /// Flip one bit in one of the hashes - this should break the authentication
         fn damage(&mut self, rng: &mut ThreadRng) {
@@ -16,6 +16,8 @@
 //!
 //! Various utility functions
 
+use prelude::*;
+
 use hashes::{sha256d, Hash, HashEngine};
 use blockdata::opcodes;
 use consensus::{encode, Encodable};
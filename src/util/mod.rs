@@ -22,10 +22,18 @@ pub mod amount;
 pub mod base58;
 pub mod bip32;
 pub mod bip143;
+pub mod bip152;
+pub mod bip69;
+pub mod bip125;
+pub mod bloom;
+#[cfg(feature = "std")]
+pub mod blockfile;
 pub mod contracthash;
 pub mod hash;
+pub mod headerchain;
 pub mod merkleblock;
 pub mod misc;
+pub mod package;
 pub mod psbt;
 #[macro_use]
 pub mod taproot;
@@ -34,7 +42,8 @@ pub mod bip158;
 
 pub(crate) mod endian;
 
-use std::{error, fmt};
+use core::fmt;
+#[cfg(feature = "std")] use std::error;
 
 use network;
 use consensus::encode;
@@ -85,6 +94,7 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
@@ -0,0 +1,287 @@
+// Rust Bitcoin Library
+// Written by
+//   The Rust Bitcoin developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Transaction packages
+//!
+//! A package is a set of related unconfirmed transactions, some of which
+//! spend outputs of the others. This module computes ancestor and descendant
+//! sets within a package, package fee rates and the fee a child transaction
+//! has to pay for its parent to reach a target fee rate (child-pays-for-parent).
+//!
+//! Fee rates are expressed in satoshis per 1000 virtual bytes, as everywhere
+//! in Bitcoin Core.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")] use std::error;
+use prelude::*;
+
+use blockdata::transaction::{OutPoint, Transaction, TxOut};
+use hash_types::Txid;
+use util::bip125::{fee_rate, vsize};
+
+/// Errors which may happen during package fee computation
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The transaction is not a part of the package
+    UnknownTransaction(Txid),
+    /// The output spent by a package transaction was not provided
+    UtxoMissing(OutPoint),
+    /// The transaction spends less than it creates
+    NegativeFee(Txid),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownTransaction(ref txid) => write!(f, "transaction {} is not in the package", txid),
+            Error::UtxoMissing(ref coin) => write!(f, "unresolved UTXO {}", coin),
+            Error::NegativeFee(ref txid) => write!(f, "transaction {} has outputs exceeding its inputs", txid),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[allow(deprecated)]
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "description() is deprecated; use Display"
+    }
+}
+
+/// A set of related unconfirmed transactions
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Package {
+    txdata: Vec<Transaction>,
+    index: HashMap<Txid, usize>,
+}
+
+impl Package {
+    /// Creates a package out of transactions. The order of transactions is
+    /// preserved in all returned ancestor and descendant sets.
+    pub fn new(txdata: Vec<Transaction>) -> Package {
+        let index = txdata.iter().enumerate().map(|(i, tx)| (tx.txid(), i)).collect();
+        Package {
+            txdata: txdata,
+            index: index,
+        }
+    }
+
+    /// Returns the transactions of the package
+    pub fn txdata(&self) -> &[Transaction] {
+        &self.txdata
+    }
+
+    /// Returns the package transaction with the given txid
+    pub fn get(&self, txid: &Txid) -> Option<&Transaction> {
+        self.index.get(txid).map(|&i| &self.txdata[i])
+    }
+
+    /// Returns the in-package ancestors of the transaction, not including
+    /// the transaction itself.
+    pub fn ancestors(&self, txid: &Txid) -> Result<Vec<&Transaction>, Error> {
+        let start = *self.index.get(txid).ok_or(Error::UnknownTransaction(*txid))?;
+        let mut found = HashSet::new();
+        let mut queue = vec![start];
+        while let Some(i) = queue.pop() {
+            for input in &self.txdata[i].input {
+                if let Some(&parent) = self.index.get(&input.previous_output.txid) {
+                    if found.insert(parent) {
+                        queue.push(parent);
+                    }
+                }
+            }
+        }
+        Ok(self.collect(found))
+    }
+
+    /// Returns the in-package descendants of the transaction, not including
+    /// the transaction itself.
+    pub fn descendants(&self, txid: &Txid) -> Result<Vec<&Transaction>, Error> {
+        let start = *self.index.get(txid).ok_or(Error::UnknownTransaction(*txid))?;
+        let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, tx) in self.txdata.iter().enumerate() {
+            for input in &tx.input {
+                if let Some(&parent) = self.index.get(&input.previous_output.txid) {
+                    children.entry(parent).or_insert_with(Vec::new).push(i);
+                }
+            }
+        }
+        let mut found = HashSet::new();
+        let mut queue = vec![start];
+        while let Some(i) = queue.pop() {
+            if let Some(spenders) = children.get(&i) {
+                for &child in spenders {
+                    if found.insert(child) {
+                        queue.push(child);
+                    }
+                }
+            }
+        }
+        Ok(self.collect(found))
+    }
+
+    /// Computes the fee paid by a package transaction. Outputs spent from
+    /// other package transactions are resolved within the package, all others
+    /// are looked up with the `spent` function.
+    pub fn fee<S>(&self, txid: &Txid, spent: S) -> Result<u64, Error>
+        where S: Fn(&OutPoint) -> Option<TxOut>
+    {
+        let tx = self.get(txid).ok_or(Error::UnknownTransaction(*txid))?;
+        self.tx_fee(tx, &spent)
+    }
+
+    /// Computes the fee rate of the whole package, i.e. the total fee of all
+    /// its transactions divided by their total virtual size.
+    pub fn fee_rate<S>(&self, spent: S) -> Result<u64, Error>
+        where S: Fn(&OutPoint) -> Option<TxOut>
+    {
+        let txs: Vec<&Transaction> = self.txdata.iter().collect();
+        self.aggregate(&txs, &spent).map(|(fee, size)| fee_rate(fee, size))
+    }
+
+    /// Computes the fee rate of a transaction together with all of its
+    /// in-package ancestors, which is what miners use to select it.
+    pub fn ancestor_fee_rate<S>(&self, txid: &Txid, spent: S) -> Result<u64, Error>
+        where S: Fn(&OutPoint) -> Option<TxOut>
+    {
+        let txs = self.with_ancestors(txid)?;
+        self.aggregate(&txs, &spent).map(|(fee, size)| fee_rate(fee, size))
+    }
+
+    /// Computes the minimal fee a new child transaction of the given virtual
+    /// size must pay for the parent transaction, together with its in-package
+    /// ancestors and the child itself, to reach the target fee rate.
+    ///
+    /// Returns zero if the parent already reaches the target without help.
+    pub fn cpfp_fee<S>(&self, parent: &Txid, child_vsize: usize, target_fee_rate: u64, spent: S) -> Result<u64, Error>
+        where S: Fn(&OutPoint) -> Option<TxOut>
+    {
+        let txs = self.with_ancestors(parent)?;
+        let (fee, size) = self.aggregate(&txs, &spent)?;
+        let required = target_fee_rate.saturating_mul((size + child_vsize) as u64).saturating_add(999) / 1000;
+        Ok(required.saturating_sub(fee))
+    }
+
+    fn collect(&self, found: HashSet<usize>) -> Vec<&Transaction> {
+        let mut found: Vec<usize> = found.into_iter().collect();
+        found.sort();
+        found.into_iter().map(|i| &self.txdata[i]).collect()
+    }
+
+    fn with_ancestors(&self, txid: &Txid) -> Result<Vec<&Transaction>, Error> {
+        let mut txs = self.ancestors(txid)?;
+        txs.push(&self.txdata[self.index[txid]]);
+        Ok(txs)
+    }
+
+    fn aggregate<S>(&self, txs: &[&Transaction], spent: &S) -> Result<(u64, usize), Error>
+        where S: Fn(&OutPoint) -> Option<TxOut>
+    {
+        let mut fee = 0u64;
+        let mut size = 0usize;
+        for tx in txs {
+            fee = fee.saturating_add(self.tx_fee(tx, spent)?);
+            size += vsize(tx);
+        }
+        Ok((fee, size))
+    }
+
+    fn tx_fee<S>(&self, tx: &Transaction, spent: &S) -> Result<u64, Error>
+        where S: Fn(&OutPoint) -> Option<TxOut>
+    {
+        let mut input_value = 0u64;
+        for input in &tx.input {
+            let prevout = input.previous_output;
+            let value = match self.get(&prevout.txid) {
+                Some(parent) => parent.output.get(prevout.vout as usize).map(|o| o.value),
+                None => spent(&prevout).map(|o| o.value),
+            };
+            input_value = input_value.saturating_add(value.ok_or(Error::UtxoMissing(prevout))?);
+        }
+        let output_value = tx.output.iter().fold(0u64, |sum, o| sum.saturating_add(o.value));
+        input_value.checked_sub(output_value).ok_or_else(|| Error::NegativeFee(tx.txid()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use hash_types::Txid;
+    use hashes::Hash;
+    use util::bip125::{fee_rate, vsize};
+
+    use super::{Error, Package};
+
+    fn tx(prevouts: &[OutPoint], values: &[u64]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: prevouts.iter().map(|prevout| TxIn {
+                previous_output: *prevout,
+                ..Default::default()
+            }).collect(),
+            output: values.iter().map(|&value| TxOut {
+                value: value,
+                script_pubkey: Script::from(vec![0x51; 22]),
+            }).collect(),
+        }
+    }
+
+    fn utxo(outpoint: &OutPoint) -> Option<TxOut> {
+        if outpoint.txid == Txid::hash(&[0]) {
+            Some(TxOut { value: 100_000, script_pubkey: Script::new() })
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn package_fees() {
+        let confirmed = OutPoint::new(Txid::hash(&[0]), 0);
+        let parent = tx(&[confirmed], &[50_000, 49_900]);
+        let child_a = tx(&[OutPoint::new(parent.txid(), 0)], &[49_000]);
+        let child_b = tx(&[OutPoint::new(parent.txid(), 1)], &[49_800]);
+        let grandchild = tx(&[OutPoint::new(child_a.txid(), 0), OutPoint::new(child_b.txid(), 0)], &[98_000]);
+        let package = Package::new(vec![parent.clone(), child_a.clone(), child_b.clone(), grandchild.clone()]);
+
+        assert_eq!(package.ancestors(&grandchild.txid()).unwrap(), vec![&parent, &child_a, &child_b]);
+        assert_eq!(package.ancestors(&parent.txid()).unwrap(), Vec::<&Transaction>::new());
+        assert_eq!(package.descendants(&parent.txid()).unwrap(), vec![&child_a, &child_b, &grandchild]);
+        assert_eq!(package.descendants(&child_b.txid()).unwrap(), vec![&grandchild]);
+
+        assert_eq!(package.fee(&parent.txid(), utxo), Ok(100));
+        assert_eq!(package.fee(&child_a.txid(), utxo), Ok(1_000));
+        assert_eq!(package.fee(&grandchild.txid(), utxo), Ok(800));
+
+        let size = vsize(&parent) + vsize(&child_a) + vsize(&child_b) + vsize(&grandchild);
+        assert_eq!(package.fee_rate(utxo), Ok(fee_rate(2_000, size)));
+        assert_eq!(package.ancestor_fee_rate(&child_a.txid(), utxo),
+                   Ok(fee_rate(1_100, vsize(&parent) + vsize(&child_a))));
+
+        // The parent alone pays 100 sat, the child must cover the rest
+        let target = 10_000;
+        let child_vsize = 100;
+        let required = (target * (vsize(&parent) + child_vsize) as u64 + 999) / 1000;
+        assert_eq!(package.cpfp_fee(&parent.txid(), child_vsize, target, utxo), Ok(required - 100));
+        assert_eq!(package.cpfp_fee(&parent.txid(), child_vsize, 1, utxo), Ok(0));
+
+        let unknown = Txid::hash(&[1]);
+        assert_eq!(package.fee(&unknown, utxo), Err(Error::UnknownTransaction(unknown)));
+        assert_eq!(package.fee(&parent.txid(), |_| None), Err(Error::UtxoMissing(confirmed)));
+        let overspending = Package::new(vec![tx(&[confirmed], &[100_001])]);
+        assert_eq!(overspending.fee_rate(utxo), Err(Error::NegativeFee(overspending.txdata()[0].txid())));
+    }
+}
@@ -12,8 +12,9 @@
 // If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
 //
 
-use std::error;
-use std::fmt;
+#[cfg(feature = "std")] use std::error;
+use core::fmt;
+use prelude::*;
 
 use blockdata::transaction::Transaction;
 use consensus::encode;
@@ -135,6 +136,7 @@ impl fmt::Display for Error {
     }
 }
 
+#[cfg(feature = "std")]
 #[allow(deprecated)]
 impl error::Error for Error {
     fn description(&self) -> &str {
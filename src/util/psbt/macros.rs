@@ -48,6 +48,10 @@ macro_rules! impl_psbt_serialize {
             fn serialize(&self) -> Vec<u8> {
                 $crate::consensus::serialize(self)
             }
+
+            fn serialized_len(&self) -> usize {
+                $crate::consensus::Encodable::encoded_len(self)
+            }
         }
     };
 }
@@ -55,7 +59,7 @@ macro_rules! impl_psbt_serialize {
 macro_rules! impl_psbtmap_consensus_encoding {
     ($thing:ty) => {
         impl $crate::consensus::Encodable for $thing {
-            fn consensus_encode<S: ::std::io::Write>(
+            fn consensus_encode<S: $crate::io::Write>(
                 &self,
                 mut s: S,
             ) -> Result<usize, $crate::consensus::encode::Error> {
@@ -69,6 +73,10 @@ macro_rules! impl_psbtmap_consensus_encoding {
 
                 Ok(len + $crate::consensus::Encodable::consensus_encode(&0x00_u8, s)?)
             }
+
+            fn encoded_len(&self) -> usize {
+                $crate::util::psbt::Map::get_pairs_len(self) + 1
+            }
         }
     };
 }
@@ -76,13 +84,20 @@ macro_rules! impl_psbtmap_consensus_encoding {
 macro_rules! impl_psbtmap_consensus_decoding {
     ($thing:ty) => {
         impl $crate::consensus::Decodable for $thing {
-            fn consensus_decode<D: ::std::io::Read>(
+            fn consensus_decode<D: $crate::io::Read>(
+                d: D,
+            ) -> Result<Self, $crate::consensus::encode::Error> {
+                Self::consensus_decode_limited(d, &mut $crate::consensus::DecodeContext::default())
+            }
+
+            fn consensus_decode_limited<D: $crate::io::Read>(
                 mut d: D,
+                ctx: &mut $crate::consensus::DecodeContext,
             ) -> Result<Self, $crate::consensus::encode::Error> {
-                let mut rv: Self = ::std::default::Default::default();
+                let mut rv: Self = ::core::default::Default::default();
 
                 loop {
-                    match $crate::consensus::Decodable::consensus_decode(&mut d) {
+                    match $crate::consensus::Decodable::consensus_decode_limited(&mut d, ctx) {
                         Ok(pair) => $crate::util::psbt::Map::insert_pair(&mut rv, pair)?,
                         Err($crate::consensus::encode::Error::Psbt($crate::util::psbt::Error::NoMorePairs)) => return Ok(rv),
                         Err(e) => return Err(e),
@@ -130,11 +145,11 @@ macro_rules! impl_psbt_insert_pair {
         if !$raw_key.key.is_empty() {
             let key_val: $keyed_key_type = $crate::util::psbt::serialize::Deserialize::deserialize(&$raw_key.key)?;
             match $slf.$keyed_name.entry(key_val) {
-                ::std::collections::btree_map::Entry::Vacant(empty_key) => {
+                $crate::prelude::btree_map::Entry::Vacant(empty_key) => {
                     let val: $keyed_value_type = $crate::util::psbt::serialize::Deserialize::deserialize(&$raw_value)?;
                     empty_key.insert(val);
                 }
-                ::std::collections::btree_map::Entry::Occupied(_) => return Err($crate::util::psbt::Error::DuplicateKey($raw_key).into()),
+                $crate::prelude::btree_map::Entry::Occupied(_) => return Err($crate::util::psbt::Error::DuplicateKey($raw_key).into()),
             }
         } else {
             return Err($crate::util::psbt::Error::InvalidKey($raw_key).into());
@@ -148,7 +163,7 @@ macro_rules! impl_psbt_insert_hash_pair {
         if !$raw_key.key.is_empty() {
             let key_val: $keyed_key_type = $crate::util::psbt::serialize::Deserialize::deserialize(&$raw_key.key)?;
             match $slf.$keyed_name.entry(key_val) {
-                ::std::collections::btree_map::Entry::Vacant(empty_key) => {
+                $crate::prelude::btree_map::Entry::Vacant(empty_key) => {
                     let val: $keyed_value_type = $crate::util::psbt::serialize::Deserialize::deserialize(&$raw_value)?;
                     if <$keyed_key_type>::hash(&val) != key_val{
                         return Err($crate::util::psbt::Error::InvalidPreimageHashPair{
@@ -158,7 +173,7 @@ macro_rules! impl_psbt_insert_hash_pair {
                     }
                     empty_key.insert(val);
                 }
-                ::std::collections::btree_map::Entry::Occupied(_) => return Err($crate::util::psbt::Error::DuplicateKey($raw_key).into()),
+                $crate::prelude::btree_map::Entry::Occupied(_) => return Err($crate::util::psbt::Error::DuplicateKey($raw_key).into()),
             }
         } else {
             return Err($crate::util::psbt::Error::InvalidKey($raw_key).into());
@@ -192,6 +207,26 @@ macro_rules! impl_psbt_get_pair {
     };
 }
 
+#[cfg_attr(rustfmt, rustfmt_skip)]
+macro_rules! impl_psbt_get_pair_len {
+    ($len:ident += $slf:ident.$unkeyed_name:ident as <_>|<$unkeyed_value_type:ty>) => {
+        if let Some(ref $unkeyed_name) = $slf.$unkeyed_name {
+            $len += $crate::util::psbt::raw::pair_len(
+                0,
+                $crate::util::psbt::serialize::Serialize::serialized_len($unkeyed_name),
+            );
+        }
+    };
+    ($len:ident += $slf:ident.$keyed_name:ident as <$keyed_key_type:ty>|<$keyed_value_type:ty>) => {
+        for (key, val) in &$slf.$keyed_name {
+            $len += $crate::util::psbt::raw::pair_len(
+                $crate::util::psbt::serialize::Serialize::serialized_len(key),
+                $crate::util::psbt::serialize::Serialize::serialized_len(val),
+            );
+        }
+    };
+}
+
 // macros for serde of hashes
 macro_rules! impl_psbt_hash_de_serialize {
     ($thing:ty) => {
@@ -218,6 +253,10 @@ macro_rules! impl_psbt_hash_serialize {
             fn serialize(&self) -> Vec<u8> {
                 self.into_inner().to_vec()
             }
+
+            fn serialized_len(&self) -> usize {
+                <$thing as $crate::hashes::Hash>::LEN
+            }
         }
     };
 }
@@ -12,12 +12,12 @@
 // If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
 //
 
-use std::collections::BTreeMap;
-use std::collections::btree_map::Entry;
-use std::io::{self, Cursor, Read};
+use io::{self, Cursor, Read};
+use prelude::*;
+use prelude::btree_map::Entry;
 
 use blockdata::transaction::Transaction;
-use consensus::{encode, Encodable, Decodable};
+use consensus::{encode, Encodable, Decodable, DecodeContext};
 use util::psbt::map::Map;
 use util::psbt::raw;
 use util::psbt;
@@ -146,12 +146,39 @@ impl Map for Global {
 
         Ok(rv)
     }
+
+    fn get_pairs_len(&self) -> usize {
+        let mut len = 0;
+
+        // The unsigned transaction, serialized without witnesses
+        len += raw::pair_len(
+            0,
+            self.unsigned_tx.version.encoded_len()
+                + self.unsigned_tx.input.encoded_len()
+                + self.unsigned_tx.output.encoded_len()
+                + self.unsigned_tx.lock_time.encoded_len(),
+        );
+
+        for (key, value) in self.proprietary.iter() {
+            len += raw::pair_len(key.encoded_len(), value.len());
+        }
+
+        for (key, value) in self.unknown.iter() {
+            len += raw::pair_len(key.key.len(), value.len());
+        }
+
+        len
+    }
 }
 
 impl_psbtmap_consensus_encoding!(Global);
 
 impl Decodable for Global {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
 
         let mut tx: Option<Transaction> = None;
         let mut version: Option<u32> = None;
@@ -160,7 +187,7 @@ impl Decodable for Global {
         let mut xpub_map: BTreeMap<ExtendedPubKey, (Fingerprint, DerivationPath)> = Default::default();
 
         loop {
-            match raw::Pair::consensus_decode(&mut d) {
+            match raw::Pair::consensus_decode_limited(&mut d, ctx) {
                 Ok(pair) => {
                     match pair.key.type_value {
                         PSBT_GLOBAL_UNSIGNED_TX => {
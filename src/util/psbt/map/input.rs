@@ -12,11 +12,11 @@
 // If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
 //
 
-use std::collections::BTreeMap;
+use prelude::*;
 
 use blockdata::script::Script;
 use blockdata::transaction::{SigHashType, Transaction, TxOut};
-use consensus::encode;
+use consensus::{encode, Encodable};
 use hashes::{Hash, hash160, ripemd160, sha256, sha256d};
 use util::bip32::KeySource;
 use util::key::PublicKey;
@@ -153,8 +153,8 @@ impl Map for Input {
                 }
             }
             PSBT_IN_PROPRIETARY => match self.proprietary.entry(raw::ProprietaryKey::from_key(raw_key.clone())?) {
-                ::std::collections::btree_map::Entry::Vacant(empty_key) => {empty_key.insert(raw_value);},
-                ::std::collections::btree_map::Entry::Occupied(_) => return Err(Error::DuplicateKey(raw_key).into()),
+                btree_map::Entry::Vacant(empty_key) => {empty_key.insert(raw_value);},
+                btree_map::Entry::Occupied(_) => return Err(Error::DuplicateKey(raw_key).into()),
             }
             10u8 => {
                 impl_psbt_insert_hash_pair! {
@@ -177,10 +177,10 @@ impl Map for Input {
                 }
             }
             _ => match self.unknown.entry(raw_key) {
-                ::std::collections::btree_map::Entry::Vacant(empty_key) => {
+                btree_map::Entry::Vacant(empty_key) => {
                     empty_key.insert(raw_value);
                 }
-                ::std::collections::btree_map::Entry::Occupied(k) => {
+                btree_map::Entry::Occupied(k) => {
                     return Err(Error::DuplicateKey(k.key().clone()).into())
                 }
             },
@@ -260,6 +260,72 @@ impl Map for Input {
 
         Ok(rv)
     }
+
+    fn get_pairs_len(&self) -> usize {
+        let mut len = 0;
+
+        impl_psbt_get_pair_len! {
+            len += self.non_witness_utxo as <_>|<Transaction>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.witness_utxo as <_>|<TxOut>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.partial_sigs as <PublicKey>|<Vec<u8>>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.sighash_type as <_>|<SigHashType>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.redeem_script as <_>|<Script>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.witness_script as <_>|<Script>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.bip32_derivation as <PublicKey>|<(Fingerprint, DerivationPath)>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.final_script_sig as <_>|<Script>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.final_script_witness as <_>|<Script>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.ripemd_preimages as <ripemd160::Hash>|<Vec<u8>>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.sha256_preimages as <sha256::Hash>|<Vec<u8>>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.hash160_preimages as <hash160::Hash>|<Vec<u8>>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.hash256_preimages as <sha256d::Hash>|<Vec<u8>>
+        }
+
+        for (key, value) in self.proprietary.iter() {
+            len += raw::pair_len(key.encoded_len(), value.len());
+        }
+
+        for (key, value) in self.unknown.iter() {
+            len += raw::pair_len(key.key.len(), value.len());
+        }
+
+        len
+    }
 }
 
 impl_psbtmap_consensus_enc_dec_oding!(Input);
@@ -12,7 +12,9 @@
 // If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
 //
 
-use consensus::encode;
+use prelude::*;
+
+use consensus::{encode, Encodable};
 use util::psbt::raw;
 
 /// A trait that describes a PSBT key-value map.
@@ -22,6 +24,12 @@ pub trait Map {
 
     /// Attempt to get all key-value pairs.
     fn get_pairs(&self) -> Result<Vec<raw::Pair>, encode::Error>;
+
+    /// Returns the encoded length of all key-value pairs, without computing
+    /// them where possible.
+    fn get_pairs_len(&self) -> usize {
+        self.get_pairs().map_or(0, |pairs| pairs.iter().map(Encodable::encoded_len).sum())
+    }
 }
 
 // place at end to pick up macros
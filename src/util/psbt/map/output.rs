@@ -12,12 +12,12 @@
 // If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
 //
 
-use std::collections::BTreeMap;
-use std::collections::btree_map::Entry;
+use prelude::*;
+use prelude::btree_map::Entry;
 
 use hashes::sha256;
 use blockdata::script::Script;
-use consensus::encode;
+use consensus::{encode, Encodable};
 use util::bip32::KeySource;
 use util::key::PublicKey;
 use util::psbt::map::Map;
@@ -135,6 +135,36 @@ impl Map for Output {
 
         Ok(rv)
     }
+
+    fn get_pairs_len(&self) -> usize {
+        let mut len = 0;
+
+        impl_psbt_get_pair_len! {
+            len += self.redeem_script as <_>|<Script>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.witness_script as <_>|<Script>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.bip32_derivation as <PublicKey>|<(Fingerprint, DerivationPath)>
+        }
+
+        impl_psbt_get_pair_len! {
+            len += self.tweak as <PublicKey>|<sha256::Hash>
+        }
+
+        for (key, value) in self.proprietary.iter() {
+            len += raw::pair_len(key.encoded_len(), value.len());
+        }
+
+        for (key, value) in self.unknown.iter() {
+            len += raw::pair_len(key.key.len(), value.len());
+        }
+
+        len
+    }
 }
 
 impl_psbtmap_consensus_enc_dec_oding!(Output);
@@ -20,12 +20,13 @@
 
 use blockdata::transaction::SigHashType;
 use hash_types::SigHash;
-use consensus::{encode, Encodable, Decodable};
+use consensus::{encode, Encodable, Decodable, DecodeContext};
 use util::bip143::SigHashCache;
 use blockdata::script::Builder;
 use blockdata::opcodes;
 
-use std::io;
+use io;
+use prelude::*;
 
 mod error;
 pub use self::error::Error;
@@ -156,10 +157,22 @@ impl Encodable for PartiallySignedTransaction {
 
         Ok(len)
     }
+
+    fn encoded_len(&self) -> usize {
+        b"psbt".encoded_len()
+            + 0xff_u8.encoded_len()
+            + self.global.encoded_len()
+            + self.inputs.iter().map(Encodable::encoded_len).sum::<usize>()
+            + self.outputs.iter().map(Encodable::encoded_len).sum::<usize>()
+    }
 }
 
 impl Decodable for PartiallySignedTransaction {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
         let magic: [u8; 4] = Decodable::consensus_decode(&mut d)?;
 
         if *b"psbt" != magic {
@@ -170,7 +183,7 @@ impl Decodable for PartiallySignedTransaction {
             return Err(Error::InvalidSeparator.into());
         }
 
-        let global: Global = Decodable::consensus_decode(&mut d)?;
+        let global: Global = Decodable::consensus_decode_limited(&mut d, ctx)?;
 
         let inputs: Vec<Input> = {
             let inputs_len: usize = (&global.unsigned_tx.input).len();
@@ -178,7 +191,7 @@ impl Decodable for PartiallySignedTransaction {
             let mut inputs: Vec<Input> = Vec::with_capacity(inputs_len);
 
             for _ in 0..inputs_len {
-                inputs.push(Decodable::consensus_decode(&mut d)?);
+                inputs.push(Decodable::consensus_decode_limited(&mut d, ctx)?);
             }
 
             inputs
@@ -190,7 +203,7 @@ impl Decodable for PartiallySignedTransaction {
             let mut outputs: Vec<Output> = Vec::with_capacity(outputs_len);
 
             for _ in 0..outputs_len {
-                outputs.push(Decodable::consensus_decode(&mut d)?);
+                outputs.push(Decodable::consensus_decode_limited(&mut d, ctx)?);
             }
 
             outputs
@@ -216,7 +229,7 @@ mod tests {
 
     use blockdata::script::Script;
     use blockdata::transaction::{Transaction, TxIn, TxOut, OutPoint};
-    use consensus::encode::{deserialize, serialize, serialize_hex};
+    use consensus::encode::{deserialize, serialize, serialize_hex, Encodable};
     use util::bip32::{ChildNumber, KeySource, ExtendedPrivKey, ExtendedPubKey, Fingerprint, DefaultResolver, KeyVersion, VERSION_MAGIC_XPRV};
     use util::key::PublicKey;
     use util::psbt::map::{Global, Output, Input};
@@ -290,6 +303,7 @@ mod tests {
         let actual: Output = deserialize(&serialize(&expected)).unwrap();
 
         assert_eq!(expected, actual);
+        assert_eq!(expected.encoded_len(), serialize(&expected).len());
     }
 
     #[test]
@@ -333,6 +347,7 @@ mod tests {
         let actual: Global = deserialize(&serialize(&expected)).unwrap();
 
         assert_eq!(expected, actual);
+        assert_eq!(expected.encoded_len(), serialize(&expected).len());
     }
 
     #[test]
@@ -355,6 +370,7 @@ mod tests {
         let hex = "70736274ff0100890200000001207ae985d787dfe6143d5c58fad79cc7105e0e799fcf033b7f2ba17e62d7b3200000000000ffffffff02563d03000000000022002019899534b9a011043c0dd57c3ff9a381c3522c5f27c6a42319085b56ca543a1d6adc020000000000220020618b47a07ebecca4e156edb1b9ea7c24bdee0139fc049237965ffdaf56d5ee73000000000001012b801a0600000000002200201148e93e9315e37dbed2121be5239257af35adc03ffdfc5d914b083afa44dab82202025fe7371376d53cf8a2783917c28bf30bd690b0a4d4a207690093ca2b920ee076473044022007e06b362e89912abd4661f47945430739b006a85d1b2a16c01dc1a4bd07acab022061576d7aa834988b7ab94ef21d8eebd996ea59ea20529a19b15f0c9cebe3d8ac01220202b3fe93530020a8294f0e527e33fbdff184f047eb6b5a1558a352f62c29972f8a473044022002787f926d6817504431ee281183b8119b6845bfaa6befae45e13b6d430c9d2f02202859f149a6cd26ae2f03a107e7f33c7d91730dade305fe077bae677b5d44952a01010547522102b3fe93530020a8294f0e527e33fbdff184f047eb6b5a1558a352f62c29972f8a21025fe7371376d53cf8a2783917c28bf30bd690b0a4d4a207690093ca2b920ee07652ae0001014752210283ef76537f2d58ae3aa3a4bd8ae41c3f230ccadffb1a0bd3ca504d871cff05e7210353d79cc0cb1396f4ce278d005f16d948e02a6aec9ed1109f13747ecb1507b37b52ae00010147522102b3937241777b6665e0d694e52f9c1b188433641df852da6fc42187b5d8a368a321034cdd474f01cc5aa7ff834ad8bcc882a87e854affc775486bc2a9f62e8f49bd7852ae00";
         let psbt: PartiallySignedTransaction = hex_psbt!(hex).unwrap();
         assert_eq!(hex, serialize_hex(&psbt));
+        assert_eq!(psbt.encoded_len(), hex.len() / 2);
     }
 
     mod bip_vectors {
@@ -365,11 +381,22 @@ mod tests {
 
         use blockdata::script::Script;
         use blockdata::transaction::{SigHashType, Transaction, TxIn, TxOut, OutPoint};
-        use consensus::encode::serialize_hex;
+        use consensus::encode::{serialize, serialize_hex, Encodable};
         use util::psbt::map::{Map, Global, Input, Output};
         use util::psbt::raw;
         use util::psbt::PartiallySignedTransaction;
 
+        fn check_encoded_len(psbt: &PartiallySignedTransaction) {
+            assert_eq!(psbt.encoded_len(), serialize(psbt).len());
+            assert_eq!(psbt.global.encoded_len(), serialize(&psbt.global).len());
+            for input in &psbt.inputs {
+                assert_eq!(input.encoded_len(), serialize(input).len());
+            }
+            for output in &psbt.outputs {
+                assert_eq!(output.encoded_len(), serialize(output).len());
+            }
+        }
+
         #[test]
         fn invalid_vector_1() {
             let psbt: Result<PartiallySignedTransaction, _> = hex_psbt!("0200000001268171371edff285e937adeea4b37b78000c0566cbb3ad64641713ca42171bf6000000006a473044022070b2245123e6bf474d60c5b50c043d4c691a5d2435f09a34a7662a9dc251790a022001329ca9dacf280bdf30740ec0390422422c81cb45839457aeb76fc12edd95b3012102657d118d3357b8e0f4c2cd46db7b39f6d9c38d9a70abcb9b2de5dc8dbfe4ce31feffffff02d3dff505000000001976a914d0c59903c5bac2868760e90fd521a4665aa7652088ac00e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787b32e1300");
@@ -493,6 +520,7 @@ mod tests {
 
             assert_eq!(serialize_hex(&unserialized), serialized);
             assert_eq!(unserialized, hex_psbt!(serialized).unwrap());
+            check_encoded_len(&unserialized);
         }
 
         #[test]
@@ -501,6 +529,7 @@ mod tests {
 
             assert_eq!(psbt.inputs.len(), 2);
             assert_eq!(psbt.outputs.len(), 2);
+            check_encoded_len(&psbt);
 
             assert!(&psbt.inputs[0].final_script_sig.is_some());
 
@@ -525,6 +554,7 @@ mod tests {
 
             assert_eq!(psbt.inputs.len(), 1);
             assert_eq!(psbt.outputs.len(), 2);
+            check_encoded_len(&psbt);
 
             let tx_input = &psbt.global.unsigned_tx.input[0];
             let psbt_non_witness_utxo = (&psbt.inputs[0].non_witness_utxo).as_ref().unwrap();
@@ -547,6 +577,7 @@ mod tests {
 
             assert_eq!(psbt.inputs.len(), 2);
             assert_eq!(psbt.outputs.len(), 2);
+            check_encoded_len(&psbt);
 
             assert!(&psbt.inputs[0].final_script_sig.is_none());
             assert!(&psbt.inputs[1].final_script_sig.is_none());
@@ -572,6 +603,7 @@ mod tests {
 
             assert_eq!(psbt.inputs.len(), 1);
             assert_eq!(psbt.outputs.len(), 1);
+            check_encoded_len(&psbt);
 
             assert!(&psbt.inputs[0].final_script_sig.is_none());
 
@@ -593,6 +625,7 @@ mod tests {
 
             assert_eq!(psbt.inputs.len(), 1);
             assert_eq!(psbt.outputs.len(), 1);
+            check_encoded_len(&psbt);
 
             let tx = &psbt.global.unsigned_tx;
             assert_eq!(
@@ -716,9 +749,16 @@ mod tests {
         };
         unserialized.inputs[0].hash160_preimages = hash160_preimages;
         unserialized.inputs[0].sha256_preimages = sha256_preimages;
+        unserialized.inputs[0].proprietary.insert(raw::ProprietaryKey {
+            prefix: b"test".to_vec(),
+            subtype: 1,
+            key: vec![2, 3],
+        }, vec![4, 5, 6]);
 
         let rtt : PartiallySignedTransaction = hex_psbt!(&serialize_hex(&unserialized)).unwrap();
         assert_eq!(rtt, unserialized);
+        assert_eq!(unserialized.inputs[0].encoded_len(), serialize(&unserialized.inputs[0]).len());
+        assert_eq!(unserialized.encoded_len(), serialize(&unserialized).len());
 
         // Now add an ripemd160 with incorrect preimage
         let mut ripemd160_preimages = BTreeMap::new();
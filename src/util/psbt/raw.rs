@@ -17,10 +17,11 @@
 //! Raw PSBT key-value pairs as defined at
 //! https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki.
 
-use std::{fmt, io};
+use core::fmt;
+use io;
+use prelude::*;
 
-use consensus::encode::{self, ReadExt, WriteExt, Decodable, Encodable, VarInt, serialize, deserialize, MAX_VEC_SIZE};
-use hashes::hex::ToHex;
+use consensus::encode::{self, HexBytes, ReadExt, WriteExt, Decodable, DecodeContext, Encodable, VarInt, serialize, deserialize, MAX_VEC_SIZE};
 use util::psbt::Error;
 
 /// A PSBT key in its raw byte form.
@@ -63,13 +64,17 @@ impl fmt::Display for Key {
             f,
             "type: {:#x}, key: {}",
             self.type_value,
-            self.key[..].to_hex()
+            HexBytes(&self.key)
         )
     }
 }
 
 impl Decodable for Key {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
         let VarInt(byte_size): VarInt = Decodable::consensus_decode(&mut d)?;
 
         if byte_size == 0 {
@@ -84,6 +89,7 @@ impl Decodable for Key {
                 max: MAX_VEC_SIZE,
             })
         }
+        ctx.allocate(key_byte_size as usize)?;
 
         let type_value: u8 = Decodable::consensus_decode(&mut d)?;
 
@@ -115,6 +121,10 @@ impl Encodable for Key {
 
         Ok(len)
     }
+
+    fn encoded_len(&self) -> usize {
+        encode::len_with_size(self.key.len() + 1)
+    }
 }
 
 impl Encodable for Pair {
@@ -125,17 +135,31 @@ impl Encodable for Pair {
         let len = self.key.consensus_encode(&mut s)?;
         Ok(len + self.value.consensus_encode(s)?)
     }
+
+    fn encoded_len(&self) -> usize {
+        self.key.encoded_len() + self.value.encoded_len()
+    }
 }
 
 impl Decodable for Pair {
-    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Self::consensus_decode_limited(d, &mut DecodeContext::default())
+    }
+
+    fn consensus_decode_limited<D: io::Read>(mut d: D, ctx: &mut DecodeContext) -> Result<Self, encode::Error> {
         Ok(Pair {
-            key: Decodable::consensus_decode(&mut d)?,
-            value: Decodable::consensus_decode(d)?,
+            key: Decodable::consensus_decode_limited(&mut d, ctx)?,
+            value: Decodable::consensus_decode_limited(d, ctx)?,
         })
     }
 }
 
+/// Returns the encoded length of a pair whose key data and value are
+/// `key_len` and `value_len` bytes long.
+pub(crate) fn pair_len(key_len: usize, value_len: usize) -> usize {
+    encode::len_with_size(key_len + 1) + encode::len_with_size(value_len)
+}
+
 impl<Subtype> Encodable for ProprietaryKey<Subtype> where Subtype: Copy + From<u8> + Into<u8> {
     fn consensus_encode<W: io::Write>(&self, mut e: W) -> Result<usize, encode::Error> {
         let mut len = self.prefix.consensus_encode(&mut e)? + 1;
@@ -16,7 +16,8 @@
 //! according to
 //! [BIP-174](https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki)
 
-use std::fmt::{self, Display, Debug, Formatter};
+use core::fmt::{self, Display, Debug, Formatter};
+use prelude::*;
 
 use secp256k1::Signature;
 use blockdata::transaction::{Transaction, TxIn};
@@ -17,7 +17,8 @@
 //! Defines traits used for (de)serializing PSBT values into/from raw
 //! bytes in PSBT key-value pairs.
 
-use std::io;
+use io;
+use prelude::*;
 
 use blockdata::script::Script;
 use blockdata::transaction::{SigHashType, Transaction, TxOut};
@@ -32,6 +33,12 @@ use util::psbt;
 pub trait Serialize {
     /// Serialize a value as raw data.
     fn serialize(&self) -> Vec<u8>;
+
+    /// Returns the length of the raw data, without serializing the value
+    /// where possible.
+    fn serialized_len(&self) -> usize {
+        self.serialize().len()
+    }
 }
 
 /// A trait for deserializing a value from raw data in PSBT key-value pairs.
@@ -53,6 +60,10 @@ impl Serialize for Script {
     fn serialize(&self) -> Vec<u8> {
         self.to_bytes()
     }
+
+    fn serialized_len(&self) -> usize {
+        self.len()
+    }
 }
 
 impl Deserialize for Script {
@@ -67,6 +78,10 @@ impl Serialize for PublicKey {
         self.write_into(&mut buf);
         buf
     }
+
+    fn serialized_len(&self) -> usize {
+        if self.compressed { 33 } else { 65 }
+    }
 }
 
 impl Deserialize for PublicKey {
@@ -101,6 +116,10 @@ impl Serialize for KeySource {
 
         rv
     }
+
+    fn serialized_len(&self) -> usize {
+        4 + 4 * (self.1).as_ref().len()
+    }
 }
 
 impl Deserialize for KeySource {
@@ -129,6 +148,10 @@ impl Serialize for Vec<u8> {
     fn serialize(&self) -> Vec<u8> {
         self.clone()
     }
+
+    fn serialized_len(&self) -> usize {
+        self.len()
+    }
 }
 
 impl Deserialize for Vec<u8> {
@@ -141,6 +164,10 @@ impl Serialize for SigHashType {
     fn serialize(&self) -> Vec<u8> {
         serialize(&self.as_u32())
     }
+
+    fn serialized_len(&self) -> usize {
+        4
+    }
 }
 
 impl Deserialize for SigHashType {